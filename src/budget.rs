@@ -0,0 +1,100 @@
+// Per-path token budgets for --budget src/=60%,tests/=20%: --max-total-tokens is split between path prefixes
+// (files matching none share what is left), and each group keeps its files in bundle order while they fit its
+// share. Tokens a group leaves unused then go to the files that did not fit, in order.
+
+use crate::bundle::{Bundle, BundleEntry};
+
+pub struct Share {
+    pub prefix: String,
+    pub percent: f64,
+}
+
+pub fn parse(value: &str) -> Result<Vec<Share>, String> {
+    let mut shares = Vec::new();
+    for item in value.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let Some((prefix, percent)) = item.split_once('=') else {
+            return Err(format!("'{}' is not PATH=N%", item));
+        };
+        let percent: f64 = percent
+            .trim()
+            .strip_suffix('%')
+            .and_then(|number| number.trim().parse().ok())
+            .filter(|percent: &f64| (0.0..=100.0).contains(percent))
+            .ok_or_else(|| format!("'{}' needs a percentage between 0% and 100%", item))?;
+        let prefix = prefix.trim().trim_start_matches("./").replace('\\', "/");
+        shares.push(Share { prefix, percent });
+    }
+    if shares.is_empty() {
+        return Err("no PATH=N% shares given".to_string());
+    }
+    let sum: f64 = shares.iter().map(|share| share.percent).sum();
+    if sum > 100.0 + f64::EPSILON {
+        return Err(format!("the shares add up to {}%, more than 100%", sum));
+    }
+    Ok(shares)
+}
+
+// The share (by index; shares.len() for "everything else") a file belongs to: the longest matching prefix.
+fn group_of(display_name: &str, shares: &[Share]) -> usize {
+    shares
+        .iter()
+        .enumerate()
+        .filter(|(_, share)| {
+            let prefix = share.prefix.trim_end_matches('/');
+            prefix.is_empty() || display_name == prefix || display_name.starts_with(&format!("{}/", prefix))
+        })
+        .max_by_key(|(_, share)| share.prefix.len())
+        .map_or(shares.len(), |(i, _)| i)
+}
+
+// Drops the files that do not fit their group's share of `total` tokens; returns their names. Files `pinned`
+// selects are always kept, using up their group's share first.
+pub fn apply(bundle: &mut Bundle, shares: &[Share], total: usize, pinned: impl Fn(&BundleEntry) -> bool, verbose: bool) -> Vec<String> {
+    let rest: f64 = 100.0 - shares.iter().map(|share| share.percent).sum::<f64>();
+    let mut remaining: Vec<usize> = shares
+        .iter()
+        .map(|share| share.percent)
+        .chain(std::iter::once(rest.max(0.0)))
+        .map(|percent| (total as f64 * percent / 100.0) as usize)
+        .collect();
+    // Sections outside the files (preamble, appendix) are paid for first, from the shared remainder.
+    let overhead = bundle.total_tokens() - bundle.entries.iter().map(|entry| entry.tokens).sum::<usize>();
+    let last = remaining.len() - 1;
+    remaining[last] = remaining[last].saturating_sub(overhead);
+
+    for entry in bundle.entries.iter().filter(|entry| pinned(entry)) {
+        let group = group_of(&entry.display_name, shares);
+        remaining[group] = remaining[group].saturating_sub(entry.tokens);
+    }
+
+    let mut kept: Vec<Option<BundleEntry>> = Vec::with_capacity(bundle.entries.len());
+    let mut deferred = Vec::new();
+    for entry in bundle.entries.drain(..) {
+        let group = group_of(&entry.display_name, shares);
+        if pinned(&entry) {
+            kept.push(Some(entry));
+        } else if entry.tokens <= remaining[group] {
+            remaining[group] -= entry.tokens;
+            kept.push(Some(entry));
+        } else {
+            deferred.push((kept.len(), entry));
+            kept.push(None);
+        }
+    }
+    // Second pass: whatever the groups did not use goes to the deferred files, in order.
+    let mut spare: usize = remaining.iter().sum();
+    let mut dropped = Vec::new();
+    for (slot, entry) in deferred {
+        if entry.tokens <= spare {
+            spare -= entry.tokens;
+            kept[slot] = Some(entry);
+        } else {
+            if verbose {
+                println!("Over its --budget share (~{} tokens): {}", entry.tokens, entry.display_name);
+            }
+            dropped.push(entry.display_name);
+        }
+    }
+    bundle.entries = kept.into_iter().flatten().collect();
+    dropped
+}
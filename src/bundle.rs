@@ -0,0 +1,253 @@
+// The bundle being assembled: formatted entries plus bookkeeping about what was included or skipped.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+use crate::cache::Cache;
+use crate::codeowners::CodeOwners;
+use crate::output::Stream;
+use crate::tokens::estimate_tokens;
+
+// One formatted file (or fetched document) in the bundle.
+pub struct BundleEntry {
+    pub display_name: String,
+    // Canonical path for local files; None for fetched documents.
+    pub path: Option<PathBuf>,
+    // Contents after transforms, before formatting; empty unless a pass reads it (see `keep_contents`).
+    pub contents: String,
+    // Empty once written to a stream.
+    pub segment: String,
+    // Estimated token count of the segment.
+    pub tokens: usize,
+}
+
+pub struct Bundle {
+    pub entries: Vec<BundleEntry>,
+    // Sections placed before and after the file entries.
+    pub preamble: Vec<String>,
+    pub appendix: Vec<String>,
+    pub seen_paths: HashSet<PathBuf>,
+    seen_hashes: HashMap<u64, String>,
+    pub skipped_duplicates: Vec<String>,
+    // Lockfiles and minified assets left out by the directory-mode heuristic.
+    pub auto_skipped: Vec<String>,
+    // Empty or whitespace-only files left out by --skip-empty.
+    pub skipped_empty: Vec<String>,
+    // Whether the traversal is inside a directory argument (rather than an explicitly named file).
+    pub in_directory: bool,
+    // Files under this directory are displayed relative to it, prefixed with the label unless it is empty
+    // (used for remote clones and --at trees).
+    pub display_root: Option<(PathBuf, String)>,
+    // With several directory arguments, each canonical root and the label its files are displayed under.
+    pub root_labels: Vec<(PathBuf, String)>,
+    pub cache: Option<Cache>,
+    // The CODEOWNERS file consulted by --owner.
+    pub codeowners: Option<CodeOwners>,
+    // Number of symlinked directories the traversal is currently inside.
+    pub symlinked_dir_depth: usize,
+    // Output as edited by the user (--edit); replaces the sections below when set.
+    pub edited: Option<String>,
+    // Whether the --why path was reached by the traversal.
+    pub why_reached: bool,
+    // Paths that exist but were filtered out, with the reason (recorded for --list-excluded).
+    pub excluded: Vec<(String, String)>,
+    // Files of directories listed without contents (--summarize-over), still mapped by --symbol-index.
+    pub summarized: Vec<PathBuf>,
+    // Files -R did not select, with their display names, to be outlined for --tiered.
+    pub outline_candidates: Vec<(PathBuf, String)>,
+    // Written between sections (--separator).
+    pub separator: String,
+    // Whether entries keep their contents after formatting, for the passes that read them.
+    pub keep_contents: bool,
+    // Where file sections are written as they are added, when the bundle is streamed (see output::can_stream).
+    pub stream: Option<Stream>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Bundle {
+            entries: Vec::new(),
+            preamble: Vec::new(),
+            appendix: Vec::new(),
+            seen_paths: HashSet::new(),
+            seen_hashes: HashMap::new(),
+            skipped_duplicates: Vec::new(),
+            auto_skipped: Vec::new(),
+            skipped_empty: Vec::new(),
+            in_directory: false,
+            display_root: None,
+            root_labels: Vec::new(),
+            cache: None,
+            codeowners: None,
+            symlinked_dir_depth: 0,
+            edited: None,
+            why_reached: false,
+            excluded: Vec::new(),
+            summarized: Vec::new(),
+            outline_candidates: Vec::new(),
+            separator: "\n\n".to_string(),
+            keep_contents: true,
+            stream: None,
+        }
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.display_name.as_str())
+    }
+
+    // Canonical paths of the included local files, in bundle order.
+    pub fn included_paths(&self) -> Vec<PathBuf> {
+        self.entries.iter().filter_map(|entry| entry.path.clone()).collect()
+    }
+
+    // Returns true if the file was already added, either by path or (with --dedup-content) by content.
+    pub fn is_duplicate(&mut self, canonical_path: &Path, contents: &[u8], display_name: &str, config: &Config) -> bool {
+        if !self.seen_paths.insert(canonical_path.to_path_buf()) {
+            if config.verbose {
+                println!("Skipping duplicate (already included): {}", display_name);
+            }
+            self.trace(config, canonical_path, false, "already included");
+            self.skipped_duplicates.push(display_name.to_string());
+            return true;
+        }
+        if config.dedup_content {
+            let mut hasher = DefaultHasher::new();
+            contents.hash(&mut hasher);
+            let hash = hasher.finish();
+            if let Some(original) = self.seen_hashes.get(&hash) {
+                if config.verbose {
+                    println!("Skipping duplicate (identical to {}): {}", original, display_name);
+                }
+                let reason = format!("--dedup-content: identical to {}", original);
+                self.trace(config, canonical_path, false, &reason);
+                self.skipped_duplicates.push(display_name.to_string());
+                return true;
+            }
+            self.seen_hashes.insert(hash, display_name.to_string());
+        }
+        false
+    }
+
+    // Returns true if the file is empty or whitespace-only and should be skipped: by default only in
+    // directory mode, always with --skip-empty, never with --no-skip-empty.
+    pub fn is_empty_file(&mut self, canonical_path: &Path, contents: &str, display_name: &str, config: &Config) -> bool {
+        if !config.skip_empty.unwrap_or(self.in_directory) || !contents.trim().is_empty() {
+            return false;
+        }
+        if config.verbose {
+            println!("Skipping empty file: {}", display_name);
+        }
+        self.exclude(config, canonical_path, "empty or whitespace-only; --no-skip-empty keeps it");
+        self.skipped_empty.push(display_name.to_string());
+        true
+    }
+
+    // Reports a filtering decision to stderr for --trace-filters, or for --why when `path` is that path
+    // or one of its parent directories.
+    pub fn trace(&mut self, config: &Config, path: &Path, included: bool, reason: &str) {
+        let wanted = match &config.why {
+            Some(why) if why.starts_with(path) => {
+                self.why_reached |= why == path;
+                true
+            }
+            _ => config.trace_filters,
+        };
+        if !wanted {
+            return;
+        }
+        eprintln!("{:<8}  {}  ({})", if included { "included" } else { "excluded" }, crate::display_relative(path), reason);
+    }
+
+    // Reports a path that was filtered out, and records it for --list-excluded. (Duplicates are traced
+    // instead: their contents are in the bundle.)
+    pub fn exclude(&mut self, config: &Config, path: &Path, reason: &str) {
+        self.trace(config, path, false, reason);
+        if config.list_excluded {
+            let mut name = crate::display_relative(path);
+            if path.is_dir() {
+                name.push('/');
+            }
+            self.excluded.push((crate::anonymize(config, &name), crate::anonymize(config, reason)));
+        }
+    }
+
+    // With a stream, the segment is written out and only the entry's name and token count are kept; a file
+    // that did not fit is left out, and the traversal stops at the next check of `stream_stopped`.
+    pub fn add_file(&mut self, mut entry: BundleEntry) {
+        if !self.keep_contents {
+            entry.contents = String::new();
+        }
+        if let Some(stream) = &mut self.stream {
+            if !crate::timings::time(crate::timings::Phase::Output, || stream.write(&entry.segment, entry.tokens)) {
+                return;
+            }
+            entry.segment = String::new();
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn stream_stopped(&self) -> bool {
+        self.stream.as_ref().is_some_and(Stream::stopped)
+    }
+
+    fn sections(&self) -> impl Iterator<Item = &String> {
+        let unedited = self.edited.is_none();
+        self.edited.iter().chain(
+            self.preamble.iter()
+                .chain(self.entries.iter().map(|entry| &entry.segment))
+                .chain(self.appendix.iter())
+                .filter(move |_| unedited),
+        )
+    }
+
+    // Writes the preamble, formatted entries and appendix one after another, separated by `separator`,
+    // without joining them first. (A streamed bundle is written by its stream instead.)
+    pub fn write_to(&self, writer: &mut dyn Write) -> io::Result<()> {
+        for (i, section) in self.sections().enumerate() {
+            if i > 0 {
+                writer.write_all(self.separator.as_bytes())?;
+            }
+            writer.write_all(section.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Size in bytes of the output written by `write_to`.
+    pub fn total_size(&self) -> usize {
+        let sections: Vec<&String> = self.sections().collect();
+        sections.iter().map(|s| s.len()).sum::<usize>() + self.separator.len() * sections.len().saturating_sub(1)
+    }
+
+    pub fn total_tokens(&self) -> usize {
+        if let Some(edited) = &self.edited {
+            return estimate_tokens(edited);
+        }
+        self.entries.iter().map(|entry| entry.tokens).sum::<usize>()
+            + self.preamble.iter().chain(self.appendix.iter()).map(|s| estimate_tokens(s)).sum::<usize>()
+    }
+
+    // The first `max_chars` characters of the output, without rendering the rest.
+    pub fn preview(&self, max_chars: usize) -> String {
+        let mut preview = String::new();
+        let mut remaining = max_chars;
+        for (i, section) in self.sections().enumerate() {
+            let separator = if i > 0 { self.separator.as_str() } else { "" };
+            for c in separator.chars().chain(section.chars()) {
+                if remaining == 0 {
+                    return preview;
+                }
+                preview.push(c);
+                remaining -= 1;
+            }
+        }
+        preview
+    }
+}
@@ -0,0 +1,206 @@
+// On-disk cache of formatted segments, keyed by (path, mtime, size) plus a fingerprint of the
+// options that affect formatting. A file that changes gets a new key, so stale entries are never
+// read; entries that have not been used for a while are pruned. A warm cache (`toprompt daemon`)
+// also keeps segments and directory listings in memory across bundles; a listing is reused while
+// the directory's mtime, which changes whenever an entry is added, removed or renamed, stays the same.
+// When the daemon watches the tree (the `watch` feature), change notifications invalidate what changed
+// instead, and everything else below the watched directory is answered without touching the disk.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::manifest::stable_hash;
+
+const MAGIC: &str = "toprompt-cache v1";
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+#[derive(Clone)]
+pub struct CachedSegment {
+    pub contents: String,
+    pub segment: String,
+    pub tokens: usize,
+}
+
+#[derive(Default)]
+struct Warm {
+    segments: HashMap<String, CachedSegment>,
+    // Entry names and types by absolute directory path.
+    listings: HashMap<PathBuf, (SystemTime, Vec<(OsString, fs::FileType)>)>,
+    // The directory whose changes are reported through `invalidate`; listings and stats below it are trusted.
+    watched: Option<PathBuf>,
+    // (mtime in nanoseconds, length) of files below the watched directory.
+    stats: HashMap<PathBuf, (u128, u64)>,
+}
+
+impl Warm {
+    fn trusts(&self, path: &Path) -> bool {
+        self.watched.as_ref().is_some_and(|root| path.starts_with(root))
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    // Shared by the bundles a daemon builds; None for a one-off run.
+    warm: Option<Rc<RefCell<Warm>>>,
+}
+
+// A directory's entries with their file types, unsorted.
+pub fn read_dir(dir: &Path) -> io::Result<Vec<(PathBuf, fs::FileType)>> {
+    // DirEntry::file_type comes from the directory listing itself, so only symlinks need an extra stat.
+    Ok(fs::read_dir(dir)?.filter_map(|e| e.ok()).filter_map(|e| Some((e.path(), e.file_type().ok()?))).collect())
+}
+
+impl Cache {
+    pub fn open() -> Self {
+        Cache { dir: crate::remote::cache_dir().join("segments"), warm: None }
+    }
+
+    // A cache that also keeps what it reads and writes in memory; clones share it.
+    pub fn warm() -> Self {
+        Cache { warm: Some(Rc::default()), ..Cache::open() }
+    }
+
+    // Like read_dir, answered from memory while the directory is unchanged.
+    pub fn list_dir(&self, dir: &Path) -> io::Result<Vec<(PathBuf, fs::FileType)>> {
+        let Some(warm) = &self.warm else {
+            return read_dir(dir);
+        };
+        // Names only, so the same directory reached as `.` and as `src/..` yields paths spelled like `dir`.
+        let join = |entries: &[(OsString, fs::FileType)]| entries.iter().map(|(name, file_type)| (dir.join(name), *file_type)).collect();
+        let absolute = std::path::absolute(dir)?;
+        if let Some((_, entries)) = warm.borrow().listings.get(&absolute).filter(|_| warm.borrow().trusts(&absolute)) {
+            return Ok(join(entries));
+        }
+        let mtime = fs::metadata(dir)?.modified()?;
+        if let Some((cached_mtime, entries)) = warm.borrow().listings.get(&absolute)
+            && *cached_mtime == mtime
+        {
+            return Ok(join(entries));
+        }
+        let entries = read_dir(dir)?;
+        let names = entries.iter().filter_map(|(path, file_type)| Some((path.file_name()?.to_os_string(), *file_type))).collect();
+        warm.borrow_mut().listings.insert(absolute, (mtime, names));
+        Ok(entries)
+    }
+
+    // Trusts the listings and file metadata held for paths below `root` until `invalidate` says otherwise.
+    #[cfg(feature = "watch")]
+    pub fn watch(&self, root: PathBuf) {
+        if let Some(warm) = &self.warm {
+            warm.borrow_mut().watched = Some(root);
+        }
+    }
+
+    // Forgets the listings and metadata of `path` (and anything below it) and its directory's listing.
+    #[cfg(feature = "watch")]
+    pub fn invalidate(&self, path: &Path) {
+        let Some(warm) = &self.warm else {
+            return;
+        };
+        let mut warm = warm.borrow_mut();
+        warm.stats.retain(|file, _| !file.starts_with(path));
+        warm.listings.retain(|dir, _| !dir.starts_with(path) && Some(dir.as_path()) != path.parent());
+    }
+
+    // Forgets every listing and all metadata, e.g. when change notifications were lost.
+    #[cfg(feature = "watch")]
+    pub fn invalidate_all(&self) {
+        if let Some(warm) = &self.warm {
+            let mut warm = warm.borrow_mut();
+            warm.stats.clear();
+            warm.listings.clear();
+        }
+    }
+
+    // (mtime in nanoseconds, length) of a file, from memory when the watcher vouches for it.
+    fn stat(&self, canonical_path: &Path) -> Option<(u128, u64)> {
+        if let Some(warm) = &self.warm
+            && let Some(stat) = warm.borrow().stats.get(canonical_path).filter(|_| warm.borrow().trusts(canonical_path))
+        {
+            return Some(*stat);
+        }
+        let metadata = fs::metadata(canonical_path).ok()?;
+        let stat = (metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos(), metadata.len());
+        if let Some(warm) = &self.warm
+            && warm.borrow().trusts(canonical_path)
+        {
+            warm.borrow_mut().stats.insert(canonical_path.to_path_buf(), stat);
+        }
+        Some(stat)
+    }
+
+    // Number of segments and directory listings held in memory.
+    pub fn warm_size(&self) -> (usize, usize) {
+        self.warm.as_ref().map_or((0, 0), |warm| (warm.borrow().segments.len(), warm.borrow().listings.len()))
+    }
+
+    // Key for a file in its current state, or None if its metadata can't be read.
+    pub fn key(&self, canonical_path: &Path, fingerprint: &str) -> Option<String> {
+        let (mtime, len) = self.stat(canonical_path)?;
+        let identity = format!("{}\0{}\0{}\0{}", canonical_path.display(), mtime, len, fingerprint);
+        Some(format!("{:016x}", stable_hash(identity.as_bytes())))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedSegment> {
+        if let Some(cached) = self.warm.as_ref().and_then(|warm| warm.borrow().segments.get(key).cloned()) {
+            return Some(cached);
+        }
+        let cached = self.read(key)?;
+        if let Some(warm) = &self.warm {
+            warm.borrow_mut().segments.insert(key.to_string(), cached.clone());
+        }
+        Some(cached)
+    }
+
+    fn read(&self, key: &str) -> Option<CachedSegment> {
+        let raw = fs::read_to_string(self.dir.join(key)).ok()?;
+        let mut header = raw.splitn(4, '\n');
+        if header.next()? != MAGIC {
+            return None;
+        }
+        let tokens = header.next()?.parse().ok()?;
+        let contents_len: usize = header.next()?.parse().ok()?;
+        let body = header.next()?;
+        if !body.is_char_boundary(contents_len) || contents_len > body.len() {
+            return None;
+        }
+        let (contents, segment) = body.split_at(contents_len);
+        Some(CachedSegment { contents: contents.to_string(), segment: segment.to_string(), tokens })
+    }
+
+    pub fn put(&self, key: &str, cached: &CachedSegment) -> std::io::Result<()> {
+        if let Some(warm) = &self.warm {
+            warm.borrow_mut().segments.insert(key.to_string(), cached.clone());
+        }
+        fs::create_dir_all(&self.dir)?;
+        let serialized = format!("{}\n{}\n{}\n{}{}", MAGIC, cached.tokens, cached.contents.len(), cached.contents, cached.segment);
+        // Write then rename, so concurrent runs never observe a half-written entry.
+        let temp = self.dir.join(format!("{}.{}.tmp", key, std::process::id()));
+        fs::write(&temp, serialized)?;
+        fs::rename(temp, self.dir.join(key))
+    }
+
+    // Removes entries that have not been written for MAX_AGE.
+    pub fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let now = SystemTime::now();
+        for entry in entries.flatten() {
+            let expired = entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > MAX_AGE);
+            if expired {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+// Clipboard access through the platform's command-line clipboard tools, including the X11/Wayland primary
+// selection (what middle-click pastes) for --primary.
+// --rich adds an HTML flavor next to the plain text through the system scripting tools (osascript on macOS,
+// PowerShell on Windows); other platforms' clipboard tools hold one flavor at a time.
+// A tool that does not finish in time is killed and retried, then the next tool is tried: xclip can
+// hang waiting for a selection owner (e.g. under XWayland), and a copy should fail rather than block.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How long one run of a tool may take, including reading the whole bundle.
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Runs of each tool before moving on to the next one.
+const ATTEMPTS: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Selection {
+    Clipboard,
+    // Only X11 and Wayland have one; macOS and Windows have no tools for it.
+    Primary,
+}
+
+// Candidate tools for the current platform, in order of preference.
+pub fn clipboard_tools(selection: Selection) -> Vec<(&'static str, &'static [&'static str])> {
+    if cfg!(target_os = "macos") {
+        if selection == Selection::Primary { vec![] } else { vec![("pbcopy", &[])] }
+    } else if cfg!(target_os = "windows") {
+        if selection == Selection::Primary { vec![] } else { vec![("clip", &[])] }
+    } else if selection == Selection::Primary {
+        vec![
+            ("xclip", &["-selection", "primary"]),
+            ("xsel", &["--primary", "--input"]),
+            ("wl-copy", &["--primary"]),
+        ]
+    } else {
+        vec![
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+            ("wl-copy", &[]),
+        ]
+    }
+}
+
+pub fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    copy_streaming(Selection::Clipboard, &|writer| writer.write_all(text.as_bytes()))
+}
+
+// Pipes the output of `write` straight into the first clipboard tool that accepts it,
+// so the bundle's sections never have to be joined into one String.
+pub fn copy_streaming(selection: Selection, write: &dyn Fn(&mut dyn Write) -> io::Result<()>) -> Result<(), Box<dyn std::error::Error>> {
+    let tools = clipboard_tools(selection);
+    if tools.is_empty() {
+        return Err("There is no primary selection on this platform; it exists only on X11 and Wayland.".into());
+    }
+    copy_with(&tools, write)
+}
+
+// Whether --rich can put an HTML flavor on this platform's clipboard.
+pub fn supports_rich() -> bool {
+    cfg!(any(target_os = "macos", target_os = "windows"))
+}
+
+// Puts `text` and its HTML rendering on the clipboard together; pasting picks the richest flavor the target accepts.
+pub fn copy_rich(text: &str, html: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // The script reads both flavors from files, so nothing has to be quoted into it.
+    let base = env::temp_dir().join(format!("toprompt-{}", std::process::id()));
+    let (text_path, html_path) = (base.with_extension("txt"), base.with_extension("html"));
+    fs::write(&text_path, text)?;
+    let html = if cfg!(target_os = "windows") { windows_html(html) } else { html.to_string() };
+    fs::write(&html_path, html)?;
+    let (text_file, html_file) = (text_path.to_string_lossy(), html_path.to_string_lossy());
+    let (tool, args, script): (&str, &[&str], String) = if cfg!(target_os = "macos") {
+        (
+            "osascript",
+            &["-l", "JavaScript", "-"],
+            format!(
+                "ObjC.import('AppKit');\n\
+                 function read(path) {{ return $.NSString.stringWithContentsOfFileEncodingError(path, $.NSUTF8StringEncoding, null); }}\n\
+                 var board = $.NSPasteboard.generalPasteboard;\n\
+                 board.clearContents;\n\
+                 board.setStringForType(read({}), $.NSPasteboardTypeHTML);\n\
+                 board.setStringForType(read({}), $.NSPasteboardTypeString);\n",
+                serde_json::to_string(&html_file)?,
+                serde_json::to_string(&text_file)?
+            ),
+        )
+    } else {
+        (
+            "powershell",
+            &["-NoProfile", "-STA", "-Command", "-"],
+            format!(
+                "Add-Type -AssemblyName System.Windows.Forms\n\
+                 $data = New-Object System.Windows.Forms.DataObject\n\
+                 $data.SetData([System.Windows.Forms.DataFormats]::UnicodeText, [IO.File]::ReadAllText('{}', [Text.Encoding]::UTF8))\n\
+                 $data.SetData([System.Windows.Forms.DataFormats]::Html, [IO.File]::ReadAllText('{}', [Text.Encoding]::UTF8))\n\
+                 [System.Windows.Forms.Clipboard]::SetDataObject($data, $true)\n",
+                text_file.replace('\'', "''"),
+                html_file.replace('\'', "''")
+            ),
+        )
+    };
+    let result = copy_with(&[(tool, args)], &|writer| writer.write_all(script.as_bytes()));
+    let _ = fs::remove_file(&text_path);
+    let _ = fs::remove_file(&html_path);
+    result
+}
+
+// The CF_HTML clipboard format Windows expects: a header with the byte offsets of the document and the fragment.
+fn windows_html(fragment: &str) -> String {
+    let header = |start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize| {
+        format!(
+            "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n",
+            start_html, end_html, start_fragment, end_fragment
+        )
+    };
+    let (prefix, suffix) = ("<html><body>\r\n<!--StartFragment-->", "<!--EndFragment-->\r\n</body></html>");
+    // The offsets are zero-padded, so the header's length does not depend on them.
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + prefix.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + suffix.len();
+    format!("{}{}{}{}", header(start_html, end_html, start_fragment, end_fragment), prefix, fragment, suffix)
+}
+
+// Tries each tool in turn, retrying the ones that fail or hang, and explains every failure if none succeeds.
+fn copy_with(tools: &[(&str, &[&str])], write: &dyn Fn(&mut dyn Write) -> io::Result<()>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failures = Vec::new();
+    for &(tool, args) in tools {
+        for attempt in 1..=ATTEMPTS {
+            match run_tool(tool, args, write) {
+                Ok(()) => return Ok(()),
+                Err(Failure::Missing) => break,
+                Err(Failure::Write(e)) => return Err(e.into()),
+                Err(Failure::Tool(reason)) => {
+                    failures.push(format!("{} {} (attempt {} of {})", tool, reason, attempt, ATTEMPTS));
+                }
+            }
+        }
+    }
+    if failures.is_empty() {
+        return Err("No clipboard tool found or tool failed. Please install xclip/xsel (Linux X11), wl-clipboard (Wayland), pbcopy (macOS), or ensure clip.exe is in PATH (Windows).".into());
+    }
+    let mut message = format!("The clipboard tools failed: {}.", failures.join("; "));
+    if failures.iter().any(|failure| failure.starts_with("xclip")) {
+        message.push_str(" Under Wayland, install wl-clipboard (wl-copy) or use --stdout or -o instead.");
+    }
+    Err(message.into())
+}
+
+enum Failure {
+    // The tool is not installed.
+    Missing,
+    // Producing the text failed, so another tool would fail the same way.
+    Write(io::Error),
+    // The tool failed, hung or exited early.
+    Tool(String),
+}
+
+// One run of a clipboard tool, killed if it does not finish within TIMEOUT.
+fn run_tool(tool: &str, args: &[&str], write: &dyn Fn(&mut dyn Write) -> io::Result<()>) -> Result<(), Failure> {
+    let mut child = Command::new(tool).args(args).stdin(Stdio::piped()).spawn().map_err(|_| Failure::Missing)?;
+    let stdin = child.stdin.take();
+    let child = Arc::new(Mutex::new(child));
+    let (finished, done) = mpsc::channel::<()>();
+    let watchdog = {
+        let child = Arc::clone(&child);
+        thread::spawn(move || {
+            let timed_out = done.recv_timeout(TIMEOUT) == Err(RecvTimeoutError::Timeout);
+            if timed_out {
+                // Closes the pipe too, so a write blocked on a tool that stopped reading returns.
+                let _ = lock(&child).kill();
+            }
+            timed_out
+        })
+    };
+
+    let written = stdin.map_or(Ok(()), |stdin| {
+        let mut writer = BufWriter::new(stdin);
+        write(&mut writer).and_then(|_| writer.flush())
+    });
+    let status = loop {
+        match lock(&child).try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let _ = finished.send(());
+    if watchdog.join().unwrap_or(false) {
+        return Err(Failure::Tool(format!("did not finish within {}s", TIMEOUT.as_secs())));
+    }
+
+    match (written, status) {
+        // A tool that exits without reading everything closes the pipe under the writer.
+        (Err(e), _) if e.kind() == ErrorKind::BrokenPipe => Err(Failure::Tool("stopped reading its input".to_string())),
+        (Err(e), _) => Err(Failure::Write(e)),
+        (Ok(()), Err(e)) => Err(Failure::Tool(format!("could not be waited for ({})", e))),
+        (Ok(()), Ok(status)) if status.success() => Ok(()),
+        (Ok(()), Ok(status)) => Err(Failure::Tool(format!("failed ({})", status))),
+    }
+}
+
+fn lock(child: &Mutex<Child>) -> std::sync::MutexGuard<'_, Child> {
+    child.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,69 @@
+// CODEOWNERS lookup for --owner. Patterns use gitignore syntax and the last matching line wins, as on GitHub;
+// a directory pattern owns everything below it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ignore;
+
+// Where GitHub looks for the file, relative to the repository root, in order.
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+pub struct CodeOwners {
+    pub path: PathBuf,
+    root: PathBuf,
+    matcher: ignore::Matcher,
+    // The owners listed on each line (empty for comments and blank lines).
+    owners: Vec<Vec<String>>,
+}
+
+impl CodeOwners {
+    // Loads the CODEOWNERS file of the repository containing `start` (the nearest ancestor that has one).
+    pub fn find(start: &Path) -> Option<Self> {
+        start.ancestors().find_map(|root| {
+            let path = LOCATIONS.iter().map(|location| root.join(location)).find(|path| path.is_file())?;
+            let contents = fs::read_to_string(&path).ok()?;
+            let mut patterns = Vec::new();
+            let mut owners = Vec::new();
+            for line in contents.lines() {
+                let mut fields = line.split_whitespace();
+                match fields.next().filter(|pattern| !pattern.starts_with('#')) {
+                    Some(pattern) => {
+                        patterns.push(pattern);
+                        owners.push(fields.take_while(|field| !field.starts_with('#')).map(str::to_string).collect());
+                    }
+                    None => {
+                        patterns.push("");
+                        owners.push(Vec::new());
+                    }
+                }
+            }
+            let mut matcher = ignore::Matcher::new(root);
+            matcher.add_patterns(patterns, root, &path.display().to_string());
+            Some(CodeOwners { path, root: root.to_path_buf(), matcher, owners })
+        })
+    }
+
+    // Whether `owner` owns the file at the absolute `path`; the error names the deciding rule.
+    pub fn check(&self, path: &Path, owner: &str) -> Result<(), String> {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return Err(format!("outside the repository of {}", self.path.display()));
+        };
+        // The last line matching the file or any directory above it decides.
+        let line = relative
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .filter_map(|ancestor| self.matcher.explain(ancestor, ancestor != relative))
+            .map(|decision| decision.line)
+            .max();
+        let Some(line) = line else {
+            return Err(format!("no {} rule matches it", self.path.display()));
+        };
+        let owners = &self.owners[line - 1];
+        if owners.iter().any(|candidate| candidate.eq_ignore_ascii_case(owner)) {
+            return Ok(());
+        }
+        let listed = if owners.is_empty() { "no owners".to_string() } else { owners.join(" ") };
+        Err(format!("not owned by {}: {}:{} assigns {}", owner, self.path.display(), line, listed))
+    }
+}
@@ -0,0 +1,226 @@
+// `toprompt config check|explain`: validates the effective configuration and explains, for one path,
+// which rule (gitignore line, config exclude, -R pattern, recursion) decides whether it is bundled.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::config_file;
+use crate::ignore;
+use crate::{Config, EXIT_ERROR};
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt config check [--profile <name>]");
+    eprintln!("       toprompt config explain [--profile <name> | --no-config] [-i] [--ignore-file <name>]... [--ignore-from <file>]... [--include-generated] [--no-auto-skip] [--no-submodules] [--no-skip-empty] [-r] [-R <pattern>] <path>");
+    eprintln!("  check    Validates {} and prints the settings it results in.", config_file::FILE_NAME);
+    eprintln!("  explain  Shows which rule includes or excludes <path> when the current directory is bundled.");
+}
+
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("check") => check(&args[1..]),
+        Some("explain") => explain(&args[1..]),
+        _ => {
+            print_usage();
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+// Parses the flags shared with the main command, returning the config and the remaining positional arguments.
+fn parse(args: &[String]) -> (Config, Vec<String>) {
+    let mut config = Config::default();
+    crate::apply_config_file(&mut config, args);
+    let mut positional = Vec::new();
+    let mut iter = args.iter().cloned();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--profile" => {
+                crate::next_value(&mut iter, &arg);
+            }
+            "--no-config" => {}
+            "-i" => config.use_gitignore = true,
+            "--include-generated" => config.include_generated = true,
+            "--no-auto-skip" => config.auto_skip = false,
+            "--no-submodules" => config.submodules = false,
+            "--skip-empty" => config.skip_empty = Some(true),
+            "--no-skip-empty" => config.skip_empty = Some(false),
+            "--ignore-file" => config.ignore_files.push(crate::next_value(&mut iter, &arg)),
+            "--ignore-from" => crate::add_ignore_from(&mut config, PathBuf::from(crate::next_value(&mut iter, &arg))),
+            "-r" => config.recursive = true,
+            "-R" => {
+                config.regex_pattern = Some(crate::next_value(&mut iter, &arg));
+                config.recursive = true;
+            }
+            // Combined short flags, as in the main command.
+            _ if arg.len() > 2 && arg.starts_with('-') && !arg.starts_with("--") && arg[1..].chars().all(|c| c == 'i' || c == 'r') => {
+                config.use_gitignore |= arg.contains('i');
+                config.recursive |= arg.contains('r');
+            }
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown argument for config: {}", arg);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    (config, positional)
+}
+
+fn check(args: &[String]) {
+    let (config, positional) = parse(args);
+    if !positional.is_empty() {
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    match config_file::find().filter(|_| !args.iter().any(|arg| arg == "--no-config")) {
+        Some(path) => match args.iter().position(|arg| arg == "--profile").and_then(|i| args.get(i + 1)) {
+            Some(profile) => println!("Using {} with profile '{}'", path.display(), profile),
+            None => println!("Using {}", path.display()),
+        },
+        None => println!("No {} found; using built-in defaults", config_file::FILE_NAME),
+    }
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".to_string());
+    println!("  gitignore:        {}", config.use_gitignore);
+    println!("  recursive:        {}", config.recursive);
+    println!("  format:           {}", match config.preset {
+        Some(preset) => preset.name(),
+        None if config.use_xml => "xml",
+        None => "markdown",
+    });
+    println!("  pattern:          {}", or_none(config.regex_pattern.clone()));
+    println!("  exclude:          {}", if config.exclude.is_empty() { "none".to_string() } else { config.exclude.join(", ") });
+    println!("  dedup_content:    {}", config.dedup_content);
+    println!("  bare:             {}", config.bare);
+    println!("  separator:        {:?}", config.separator);
+    println!("  header_prefix:    {:?}", config.header_prefix);
+    println!("  fence_char:       {}", &config.fence[..1]);
+    println!("  normalize:        {}", config.normalize);
+    let rules: Vec<&str> = config.rewrites.iter().map(|rewrite| rewrite.rule.as_str()).collect();
+    println!("  replace:          {}", if rules.is_empty() { "none".to_string() } else { rules.join(", ") });
+    let globs: Vec<&str> = config.file_rules.iter().map(|rule| rule.glob.pattern.as_str()).collect();
+    println!("  rules:            {}", if globs.is_empty() { "none".to_string() } else { globs.join(", ") });
+    let priority: Vec<&str> = config.priority.iter().map(|glob| glob.pattern.as_str()).collect();
+    println!("  priority:         {}", if priority.is_empty() { "none".to_string() } else { priority.join(", ") });
+    let plugins: Vec<&str> = config.transform_plugins.iter().map(|plugin| plugin.name()).collect();
+    println!("  transform_plugins: {}", if plugins.is_empty() { "none".to_string() } else { plugins.join(", ") });
+    println!("  format_plugin:    {}", or_none(config.formatter.as_ref().map(|formatter| formatter.name().to_string())));
+    println!("  max_line_length:  {}", or_none(config.max_line_length.map(|n| n.to_string())));
+    println!("  max_total_size:   {}", or_none(config.max_total_size.map(crate::format_size)));
+    println!("  max_total_tokens: {}", or_none(config.max_total_tokens.map(|n| n.to_string())));
+    println!("  model:            {}", or_none(config.model.map(|model| model.name.to_string())));
+    let prices: Vec<String> = config.prices.iter().map(|(model, price)| format!("{} ${}/M", model, price)).collect();
+    println!("  prices:           {}", if prices.is_empty() { "none".to_string() } else { prices.join(", ") });
+    // Loading the file already validated it (invalid files exit with an error).
+    println!(":: Configuration is valid ::");
+}
+
+fn explain(args: &[String]) {
+    let (config, positional) = parse(args);
+    let [target] = positional.as_slice() else {
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    };
+    let path = match fs::canonicalize(target) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Error: '{}': {}", target, e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    // Explain as if the current directory were bundled, or the path's own directory if it lies outside it.
+    let cwd = env::current_dir().and_then(fs::canonicalize).unwrap_or_else(|_| PathBuf::from("."));
+    let root = if path.starts_with(&cwd) && path != cwd { cwd } else { path.parent().unwrap_or(&path).to_path_buf() };
+    let relative = path.strip_prefix(&root).unwrap_or(&path).to_path_buf();
+    let display = crate::display_path(&relative);
+
+    let (included, reason) = decide(&config, &root, &relative, path.is_dir());
+    println!("{}: {}", display, if included { "included" } else { "excluded" });
+    println!("  {}", reason);
+    if !config.use_gitignore {
+        println!("  (.gitignore files are not applied; pass -i or set gitignore = true)");
+    }
+}
+
+// Replays the traversal from `root` down to `relative`, returning whether it is bundled and why.
+fn decide(config: &Config, root: &Path, relative: &Path, is_dir: bool) -> (bool, String) {
+    let describe = |decision: &ignore::Decision, what: &str| format!("{} {}", what, crate::describe_rule(decision));
+    let mut matcher = crate::base_matcher(config, root);
+    let load_ignore_files = |matcher: &mut ignore::Matcher, dir: &Path| {
+        if config.use_gitignore {
+            matcher.add_ignore_file(&dir.join(".gitignore"));
+            matcher.add_hgignore_file(&dir.join(".hgignore"));
+            if !config.include_generated {
+                matcher.add_attributes_file(&dir.join(".gitattributes"));
+            }
+        }
+        for name in &config.ignore_files {
+            matcher.add_ignore_file(&dir.join(name));
+        }
+        crate::check_matcher(matcher, dir);
+    };
+    load_ignore_files(&mut matcher, root);
+
+    // Each directory between the root and the path must be reached and not ignored.
+    let mut dir = PathBuf::new();
+    let components: Vec<_> = relative.components().collect();
+    for component in &components[..components.len().saturating_sub(1)] {
+        dir.push(component);
+        if !config.recursive {
+            return (false, format!("'{}' is in a subdirectory and recursion is off (pass -r)", crate::display_path(&dir)));
+        }
+        if let Some(decision) = matcher.explain(&dir, true)
+            && decision.ignored
+        {
+            return (false, describe(&decision, &format!("directory '{}' is ignored by", crate::display_path(&dir))));
+        }
+        if root.join(&dir).join(".git").exists() {
+            if !config.submodules {
+                return (false, format!("'{}' is a git submodule and --no-submodules is set", crate::display_path(&dir)));
+            }
+            if config.use_gitignore {
+                matcher = crate::base_matcher(config, root);
+            }
+        }
+        load_ignore_files(&mut matcher, &root.join(&dir));
+    }
+
+    let decision = matcher.explain(relative, is_dir);
+    if let Some(decision) = &decision
+        && decision.ignored
+    {
+        return (false, describe(decision, "ignored by"));
+    }
+    if is_dir {
+        return (true, "directory is traversed".to_string());
+    }
+    if let Some(pattern) = &config.regex_pattern {
+        let path_str = crate::display_path(relative);
+        match Regex::new(pattern) {
+            Ok(regex) if !regex.is_match(&path_str) => return (false, format!("-R pattern '{}' does not match '{}'", pattern, path_str)),
+            Ok(_) => {}
+            Err(e) => return (false, format!("-R pattern '{}' is invalid: {}", pattern, e)),
+        }
+    }
+    if config.auto_skip
+        && let Some(decision) = ignore::machine_generated(&relative.file_name().unwrap_or_default().to_string_lossy())
+    {
+        return (false, describe(&decision, "skipped as a lockfile/minified asset by"));
+    }
+    if let Some(reason) = crate::policy_exclusion(&root.join(relative), config) {
+        return (false, reason);
+    }
+    // Explained paths are found by bundling a directory, where empty files are skipped by default.
+    if config.skip_empty.unwrap_or(true)
+        && fs::read(root.join(relative)).is_ok_and(|contents| contents.iter().all(u8::is_ascii_whitespace))
+    {
+        return (false, "empty or whitespace-only (pass --no-skip-empty to keep it)".to_string());
+    }
+    match &decision {
+        Some(decision) => (true, describe(decision, "re-included by")),
+        None => (true, "no rule excludes it".to_string()),
+    }
+}
@@ -0,0 +1,352 @@
+// Project configuration from `.toprompt.toml`, found in the working directory or its nearest ancestor.
+// Settings are applied before the command line, so flags given explicitly still take effect;
+// `--profile <name>` layers a `[profiles.<name>]` table on top, and `--no-config` skips the file.
+// `[rules."<glob>"]` tables hold per-file-type settings (size cap, truncation, language, transforms, exclusion).
+// `priority` lists the files to put first and protect from budget trimming.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::Config;
+use crate::ignore;
+use crate::transform::LineLimitMode;
+
+pub const FILE_NAME: &str = ".toprompt.toml";
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub gitignore: Option<bool>,
+    pub recursive: Option<bool>,
+    // "markdown", "xml", or a --format preset ("aider", "repomix", "code2prompt").
+    pub format: Option<String>,
+    // Regex applied like -R.
+    pub pattern: Option<String>,
+    // Gitignore-syntax patterns that are always excluded, anchored at the config file's directory.
+    pub exclude: Vec<String>,
+    // Extra ignore file names read in every directory, like --ignore-file.
+    pub ignore_files: Vec<String>,
+    // Exclusion lists like --ignore-from; relative paths are resolved against the config file's directory.
+    pub ignore_from: Vec<PathBuf>,
+    // Keep linguist-generated/linguist-vendored files (see --include-generated).
+    pub include_generated: Option<bool>,
+    // Skip lockfiles and minified assets in directories (see --no-auto-skip).
+    pub auto_skip: Option<bool>,
+    // Skip empty files everywhere (true) or nowhere (false); by default only in directories.
+    pub skip_empty: Option<bool>,
+    pub dedup_content: Option<bool>,
+    // Copy a lone file without its header and fences, like --bare.
+    pub bare: Option<bool>,
+    // Markdown layout, like --separator, --header-prefix and --fence-char (TOML strings take real newlines).
+    pub separator: Option<String>,
+    pub header_prefix: Option<String>,
+    pub fence_char: Option<String>,
+    pub normalize: Option<bool>,
+    // Regexes naming what --pseudonymize renames (customer names, codenames); only applied with --pseudonymize.
+    pub pseudonymize_patterns: Vec<String>,
+    // sed-style `s/pattern/replacement/flags` rules applied to every file, like --replace.
+    pub replace: Vec<String>,
+    // Commands run like --transform-plugin and --format-plugin, from the config file's directory.
+    pub transform_plugins: Vec<String>,
+    pub format_plugin: Option<String>,
+    pub max_line_length: Option<usize>,
+    // A size like "10mb", or "none".
+    pub max_total_size: Option<String>,
+    pub max_total_tokens: Option<usize>,
+    // A --model preset name, e.g. "claude-3.5".
+    pub model: Option<String>,
+    // USD per million input tokens, by model name, for the cost estimate in the report.
+    pub prices: BTreeMap<String, f64>,
+    pub rules: BTreeMap<String, RuleSettings>,
+    // Globs of files that go first, in this order, and are never dropped or shrunk to fit a budget.
+    pub priority: Vec<String>,
+    pub profiles: BTreeMap<String, Settings>,
+}
+
+// The transforms a rule can list, applied in the order given.
+pub const RULE_TRANSFORMS: &[&str] = &["normalize", "strip-comments", "strip-imports", "remove-blank-lines", "minify-data"];
+
+// A `[rules."*.sql"]` table: settings for the files matching a gitignore-style glob, anchored at the config
+// file's directory (a glob without a slash matches at any depth).
+#[derive(Deserialize, Default, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleSettings {
+    pub exclude: bool,
+    // A size like "100kb"; larger matching files are skipped.
+    pub max_size: Option<String>,
+    // N, or "N:head|tail|split" like --max-lines.
+    pub max_lines: Option<MaxLines>,
+    pub max_line_length: Option<usize>,
+    // The code fence language, e.g. "sql" for *.ddl files.
+    pub language: Option<String>,
+    // Names from RULE_TRANSFORMS.
+    pub transforms: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum MaxLines {
+    Count(usize),
+    Spec(String),
+}
+
+impl MaxLines {
+    fn parse(&self) -> Option<(usize, LineLimitMode)> {
+        match self {
+            MaxLines::Count(count) => Some((*count, LineLimitMode::Head)),
+            MaxLines::Spec(spec) => {
+                let (count, mode) = spec.split_once(':').unwrap_or((spec, "head"));
+                Some((count.trim().parse().ok()?, LineLimitMode::parse(mode.trim())?))
+            }
+        }
+    }
+}
+
+// A gitignore-style glob anchored at the config file's directory.
+pub struct Glob {
+    pub pattern: String,
+    matcher: ignore::Matcher,
+    base: PathBuf,
+}
+
+impl Glob {
+    fn new(pattern: &str, config_dir: &Path) -> Glob {
+        let base = fs::canonicalize(config_dir).unwrap_or_else(|_| config_dir.to_path_buf());
+        let mut matcher = ignore::Matcher::new(&base);
+        matcher.add_patterns([pattern], &base, FILE_NAME);
+        Glob { pattern: pattern.to_string(), matcher, base }
+    }
+
+    // Whether the glob matches the file or, like `logs/`, one of the directories above it.
+    pub fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        self.matcher.is_ignored(relative, false)
+            || relative.ancestors().skip(1).filter(|dir| !dir.as_os_str().is_empty()).any(|dir| self.matcher.is_ignored(dir, true))
+    }
+}
+
+// A parsed `[rules]` entry. When several rules match a file, exclusion and transforms add up and, for the other
+// settings, the last rule (in glob order) that sets one wins.
+pub struct FileRule {
+    pub glob: Glob,
+    pub exclude: bool,
+    pub max_size: Option<u64>,
+    pub max_lines: Option<(usize, LineLimitMode)>,
+    pub max_line_length: Option<usize>,
+    pub language: Option<String>,
+    pub transforms: Vec<String>,
+    // The settings as written, for the cache fingerprint.
+    pub fingerprint: String,
+}
+
+impl FileRule {
+    // Expects validated settings.
+    fn new(glob: &str, settings: &RuleSettings, config_dir: &Path) -> FileRule {
+        let glob = Glob::new(glob, config_dir);
+        FileRule {
+            exclude: settings.exclude,
+            max_size: settings.max_size.as_deref().and_then(crate::parse_size),
+            max_lines: settings.max_lines.as_ref().and_then(MaxLines::parse),
+            max_line_length: settings.max_line_length,
+            language: settings.language.clone(),
+            transforms: settings.transforms.clone(),
+            fingerprint: format!("{}={:?}@{}", glob.pattern, settings, glob.base.display()),
+            glob,
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        self.glob.matches(path)
+    }
+}
+
+// The config file that applies to the working directory, if any.
+pub fn find() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    cwd.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file())
+}
+
+pub fn load(path: &Path) -> Result<Settings, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    let settings: Settings = toml::from_str(&contents)?;
+    settings.validate()?;
+    for (name, profile) in &settings.profiles {
+        if !profile.profiles.is_empty() {
+            return Err(format!("profile '{}' cannot define nested profiles", name).into());
+        }
+        profile.validate().map_err(|e| format!("profile '{}': {}", name, e))?;
+    }
+    Ok(settings)
+}
+
+impl Settings {
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(format) = &self.format
+            && !crate::output::FORMATS.contains(&format.as_str())
+        {
+            return Err(format!("format must be one of {}, not \"{}\"", crate::output::FORMATS.join(", "), format).into());
+        }
+        if let Some(pattern) = &self.pattern {
+            regex::Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        }
+        for pattern in &self.exclude {
+            crate::ignore::check_pattern(pattern)?;
+        }
+        for pattern in &self.pseudonymize_patterns {
+            regex::Regex::new(pattern).map_err(|e| format!("invalid pseudonymize pattern '{}': {}", pattern, e))?;
+        }
+        for rule in &self.replace {
+            crate::transform::Rewrite::parse(rule).map_err(|e| format!("invalid replace rule '{}': {}", rule, e))?;
+        }
+        if self.transform_plugins.iter().chain(&self.format_plugin).any(|command| command.trim().is_empty()) {
+            return Err("plugin commands cannot be empty".into());
+        }
+        if let Some(fence_char) = &self.fence_char
+            && crate::fence_of(fence_char).is_none()
+        {
+            return Err(format!("fence_char must be \"`\" or \"~\", not \"{}\"", fence_char).into());
+        }
+        if let Some(name) = self.ignore_files.iter().find(|name| name.contains(['/', '\\'])) {
+            return Err(format!("ignore_files takes file names like \".dockerignore\", not paths ('{}')", name).into());
+        }
+        if let Some(size) = &self.max_total_size
+            && size != "none"
+            && crate::parse_size(size).is_none()
+        {
+            return Err(format!("max_total_size must be a size like \"10mb\" or \"none\", not \"{}\"", size).into());
+        }
+        for pattern in &self.priority {
+            ignore::check_pattern(pattern).map_err(|e| format!("priority: {}", e))?;
+        }
+        for (glob, rule) in &self.rules {
+            rule.validate().map_err(|e| format!("rules.\"{}\": {}", glob, e))?;
+            ignore::check_pattern(glob)?;
+        }
+        if let Some((model, price)) = self.prices.iter().find(|(_, price)| !price.is_finite() || **price < 0.0) {
+            return Err(format!("prices.\"{}\" must be a non-negative number (USD per million input tokens), not {}", model, price).into());
+        }
+        if let Some(model) = &self.model
+            && crate::tokens::find_model(model).is_none()
+        {
+            let known: Vec<&str> = crate::tokens::MODELS.iter().map(|model| model.name).collect();
+            return Err(format!("model must be one of {}, not \"{}\"", known.join(", "), model).into());
+        }
+        Ok(())
+    }
+
+    // Applies these settings (and then the named profile's) on top of `config`.
+    pub fn apply(&self, config: &mut Config, config_dir: &Path, profile: Option<&str>) -> Result<(), String> {
+        self.apply_own(config, config_dir);
+        if let Some(name) = profile {
+            let Some(profile) = self.profiles.get(name) else {
+                let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+                return Err(format!("unknown profile '{}' (defined: {})", name, if known.is_empty() { "none".to_string() } else { known.join(", ") }));
+            };
+            profile.apply_own(config, config_dir);
+        }
+        Ok(())
+    }
+
+    fn apply_own(&self, config: &mut Config, config_dir: &Path) {
+        if let Some(gitignore) = self.gitignore {
+            config.use_gitignore = gitignore;
+        }
+        if let Some(recursive) = self.recursive {
+            config.recursive = recursive;
+        }
+        if let Some(format) = &self.format {
+            crate::set_format(config, format);
+        }
+        if let Some(pattern) = &self.pattern {
+            config.regex_pattern = Some(pattern.clone());
+            config.recursive = true;
+        }
+        if !self.exclude.is_empty() {
+            config.exclude.extend(self.exclude.iter().cloned());
+            config.exclude_base = Some(config_dir.to_path_buf());
+        }
+        config.ignore_files.extend(self.ignore_files.iter().cloned());
+        for list in &self.ignore_from {
+            crate::add_ignore_from(config, config_dir.join(list));
+        }
+        if let Some(include_generated) = self.include_generated {
+            config.include_generated = include_generated;
+        }
+        if let Some(auto_skip) = self.auto_skip {
+            config.auto_skip = auto_skip;
+        }
+        if self.skip_empty.is_some() {
+            config.skip_empty = self.skip_empty;
+        }
+        if let Some(dedup_content) = self.dedup_content {
+            config.dedup_content = dedup_content;
+        }
+        if let Some(bare) = self.bare {
+            config.bare = bare;
+        }
+        if let Some(separator) = &self.separator {
+            config.separator = separator.clone();
+        }
+        if let Some(header_prefix) = &self.header_prefix {
+            config.header_prefix = header_prefix.clone();
+        }
+        if let Some(fence) = self.fence_char.as_deref().and_then(crate::fence_of) {
+            config.fence = fence;
+        }
+        if let Some(normalize) = self.normalize {
+            config.normalize = normalize;
+        }
+        // Validated on load.
+        config.pseudonym_patterns.extend(self.pseudonymize_patterns.iter().filter_map(|pattern| regex::Regex::new(pattern).ok()));
+        config.rewrites.extend(self.replace.iter().filter_map(|rule| crate::transform::Rewrite::parse(rule).ok()));
+        for command in &self.transform_plugins {
+            config.transform_plugins.push(Box::new(crate::plugin::External { command: command.clone(), dir: Some(config_dir.to_path_buf()) }));
+        }
+        if let Some(command) = &self.format_plugin {
+            config.formatter = Some(Box::new(crate::plugin::External { command: command.clone(), dir: Some(config_dir.to_path_buf()) }));
+        }
+        if let Some(max_line_length) = self.max_line_length {
+            config.max_line_length = Some(max_line_length);
+        }
+        if let Some(size) = &self.max_total_size {
+            config.max_total_size = if size == "none" { None } else { crate::parse_size(size) };
+        }
+        if let Some(max_total_tokens) = self.max_total_tokens {
+            config.max_total_tokens = Some(max_total_tokens);
+        }
+        if let Some(model) = &self.model {
+            config.model = crate::tokens::find_model(model);
+        }
+        config.priority.extend(self.priority.iter().map(|pattern| Glob::new(pattern, config_dir)));
+        config.file_rules.extend(self.rules.iter().map(|(glob, rule)| FileRule::new(glob, rule, config_dir)));
+        for (model, price) in &self.prices {
+            config.prices.retain(|(name, _)| name != model);
+            config.prices.push((model.clone(), *price));
+        }
+    }
+}
+
+impl RuleSettings {
+    fn validate(&self) -> Result<(), String> {
+        if let Some(size) = &self.max_size
+            && crate::parse_size(size).is_none()
+        {
+            return Err(format!("max_size must be a size like \"100kb\", not \"{}\"", size));
+        }
+        if let Some(max_lines) = &self.max_lines
+            && max_lines.parse().is_none()
+        {
+            return Err(format!("max_lines must be N or \"N:head|tail|split\", not {:?}", max_lines));
+        }
+        if let Some(transform) = self.transforms.iter().find(|transform| !RULE_TRANSFORMS.contains(&transform.as_str())) {
+            return Err(format!("unknown transform \"{}\" (expected one of {})", transform, RULE_TRANSFORMS.join(", ")));
+        }
+        Ok(())
+    }
+}
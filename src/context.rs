@@ -0,0 +1,129 @@
+// Project context header for --context-header: project name, main languages, totals, git state and the
+// command line, so a model can orient itself before reading the files. --stamp's footer records when and
+// how a bundle was made (time, toprompt version, commit, command line) for archived prompts.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Config;
+use crate::bundle::Bundle;
+use crate::git;
+
+// The header for a bundle whose main directory is `root`.
+pub fn header(bundle: &Bundle, root: &Path, config: &Config) -> String {
+    let mut fields: Vec<(&str, String)> = vec![("Project", project_name(root))];
+
+    // Lines per language, for the local files in the bundle.
+    let mut total_lines = 0;
+    let mut lines_by_language: HashMap<&str, usize> = HashMap::new();
+    for entry in bundle.entries.iter().filter(|entry| entry.path.is_some()) {
+        let lines = entry.contents.lines().count();
+        total_lines += lines;
+        let language = crate::get_language_from_extension(&entry.display_name);
+        if !language.is_empty() {
+            *lines_by_language.entry(language).or_default() += lines;
+        }
+    }
+    let mut languages: Vec<(&str, usize)> = lines_by_language.into_iter().collect();
+    languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    if !languages.is_empty() {
+        let shares: Vec<String> = languages
+            .iter()
+            .take(3)
+            .map(|(language, lines)| format!("{} ({}%)", language, lines * 100 / total_lines.max(1)))
+            .collect();
+        fields.push(("Languages", shares.join(", ")));
+    }
+    fields.push(("Files", format!("{} ({} lines)", bundle.file_count(), total_lines)));
+
+    if let Ok(branch) = git::run(root, &["rev-parse", "--abbrev-ref", "HEAD"]) {
+        fields.push(("Git branch", branch.trim().to_string()));
+        if let Ok(subject) = git::run(root, &["log", "-1", "--format=%s"]) {
+            fields.push(("Latest commit", subject.trim().to_string()));
+        }
+    }
+    fields.push(("Command", crate::anonymize(config, &invocation())));
+
+    if config.use_xml {
+        let items: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        format!("<context>\n{}\n</context>", items.join("\n"))
+    } else {
+        let items: Vec<String> = fields.iter().map(|(name, value)| format!("- {}: {}", name, value)).collect();
+        format!("# Project context\n{}", items.join("\n"))
+    }
+}
+
+// The --stamp footer for a bundle whose main directory is `root`.
+pub fn stamp(root: &Path, config: &Config) -> String {
+    let mut fields: Vec<(&str, String)> = vec![
+        ("Generated", utc_timestamp(SystemTime::now())),
+        ("toprompt", env!("CARGO_PKG_VERSION").to_string()),
+    ];
+    if let Ok(commit) = git::run(root, &["rev-parse", "HEAD"]) {
+        // Uncommitted changes mean the commit alone does not reproduce the files.
+        let dirty = git::run(root, &["status", "--porcelain", "--untracked-files=no"]).is_ok_and(|status| !status.trim().is_empty());
+        fields.push(("Git commit", format!("{}{}", commit.trim(), if dirty { " (with uncommitted changes)" } else { "" })));
+    }
+    fields.push(("Command", crate::anonymize(config, &invocation())));
+
+    if config.use_xml {
+        let items: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        format!("<stamp>\n{}\n</stamp>", items.join("\n"))
+    } else {
+        let items: Vec<String> = fields.iter().map(|(name, value)| format!("- {}: {}", name, value)).collect();
+        format!("# Generated by toprompt\n{}", items.join("\n"))
+    }
+}
+
+// `time` as an RFC 3339 UTC timestamp, e.g. 2024-05-01T12:30:00Z.
+fn utc_timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+}
+
+// The package name from the manifest at `root` or its nearest ancestor, or else the directory name.
+pub fn project_name(root: &Path) -> String {
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    root.ancestors()
+        .find_map(manifest_name)
+        .or_else(|| root.file_name().map(|name| name.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn manifest_name(dir: &Path) -> Option<String> {
+    let from_toml = |file: &str, table: &str| {
+        let manifest = fs::read_to_string(dir.join(file)).ok()?.parse::<toml::Table>().ok()?;
+        manifest.get(table)?.get("name")?.as_str().map(str::to_string)
+    };
+    let from_json = || {
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("package.json")).ok()?).ok()?;
+        manifest.get("name")?.as_str().map(str::to_string)
+    };
+    let from_go = || {
+        let go_mod = fs::read_to_string(dir.join("go.mod")).ok()?;
+        go_mod.lines().find_map(|line| line.trim().strip_prefix("module ").map(|module| module.trim().to_string()))
+    };
+    from_toml("Cargo.toml", "package").or_else(|| from_toml("pyproject.toml", "project")).or_else(from_json).or_else(from_go)
+}
+
+// The toprompt command line, quoting arguments that contain spaces.
+fn invocation() -> String {
+    let args: Vec<String> = crate::command_line_args()
+        .into_iter()
+        .map(|arg| if arg.is_empty() || arg.contains(char::is_whitespace) { format!("'{}'", arg) } else { arg })
+        .collect();
+    format!("toprompt {}", args.join(" "))
+}
@@ -0,0 +1,142 @@
+// `toprompt daemon`: the editor protocol of `toprompt serve` (see serve.rs) on a unix socket, with one warm
+// cache shared by every request. Formatted files, their token counts and directory listings stay in memory
+// and are revalidated by mtime, so a repeated bundle of a large tree skips most of the reading and walking.
+// Clients connect, send requests one line at a time and are served one at a time; `shutdown` stops the daemon.
+// Built with the `watch` feature, the daemon also watches its working directory: change notifications
+// invalidate the affected listings and files, and the rest of the tree is trusted without being re-checked.
+
+use std::env;
+use std::path::PathBuf;
+
+use crate::cache::Cache;
+use crate::manifest::stable_hash;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt daemon [--socket <path>]");
+    eprintln!("  Serves newline-delimited JSON-RPC 2.0 (the `toprompt serve` methods) on a unix socket, keeping");
+    eprintln!("  formatted files and directory listings in memory between requests.");
+    eprintln!("  The socket defaults to one per working directory in the cache directory; its path is printed on start.");
+}
+
+// The socket a daemon started in the current directory listens on by default.
+fn default_socket() -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_default();
+    crate::remote::cache_dir().join("daemon").join(format!("{:016x}.sock", stable_hash(cwd.to_string_lossy().as_bytes())))
+}
+
+pub fn run(args: &[String]) {
+    let socket = match args {
+        [] => default_socket(),
+        [flag, path] if flag == "--socket" => PathBuf::from(path),
+        _ => {
+            print_usage();
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    };
+    if let Err(e) = listen(&socket) {
+        eprintln!("Error: daemon on {}: {}", socket.display(), e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+}
+
+#[cfg(unix)]
+fn listen(socket: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if socket.exists() {
+        // A socket left behind by a daemon that did not shut down cleanly refuses connections.
+        if UnixStream::connect(socket).is_ok() {
+            return Err("another daemon is already listening".into());
+        }
+        std::fs::remove_file(socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    eprintln!(":: toprompt daemon listening on {} ::", socket.display());
+
+    let cache = Cache::warm();
+    #[cfg(feature = "watch")]
+    let changes = watch(&cache);
+    let mut shutdown = false;
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let Ok(mut writer) = stream.try_clone() else { continue };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            #[cfg(feature = "watch")]
+            if let Some((_, events)) = &changes {
+                apply_changes(&cache, events);
+            }
+            let (response, stop) = crate::serve::handle_message(&line, Some(&cache));
+            shutdown = stop;
+            if let Some(response) = response
+                && (writeln!(writer, "{}", response).is_err() || writer.flush().is_err())
+            {
+                break;
+            }
+            if shutdown {
+                break;
+            }
+        }
+        if shutdown {
+            break;
+        }
+    }
+    let (segments, listings) = cache.warm_size();
+    eprintln!(":: toprompt daemon stopped ({} files and {} directory listings were cached) ::", segments, listings);
+    let _ = std::fs::remove_file(socket);
+    Ok(())
+}
+
+#[cfg(feature = "watch")]
+type Events = std::sync::mpsc::Receiver<notify::Result<notify::Event>>;
+
+// Starts watching the working directory; without a watcher the cache falls back to checking mtimes.
+#[cfg(feature = "watch")]
+fn watch(cache: &Cache) -> Option<(notify::RecommendedWatcher, Events)> {
+    use notify::{RecursiveMode, Watcher};
+
+    let root = env::current_dir().ok()?;
+    let (sender, events) = std::sync::mpsc::channel();
+    let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| watcher.watch(&root, RecursiveMode::Recursive).map(|_| watcher));
+    match watcher {
+        Ok(watcher) => {
+            eprintln!(":: Watching {} for changes ::", root.display());
+            cache.watch(root);
+            Some((watcher, events))
+        }
+        Err(e) => {
+            eprintln!("Warning: could not watch {} ({}); checking files for changes on every request", root.display(), e);
+            None
+        }
+    }
+}
+
+// Applies the notifications received since the last request.
+#[cfg(feature = "watch")]
+fn apply_changes(cache: &Cache, events: &Events) {
+    use notify::EventKind;
+    use notify::event::{MetadataKind, ModifyKind};
+
+    for event in events.try_iter() {
+        match event {
+            // Lost or overflowed notifications: nothing held can be trusted any more.
+            Ok(event) if event.need_rescan() => cache.invalidate_all(),
+            // The daemon's own reads show up as accesses; they change nothing.
+            Ok(event) if matches!(event.kind, EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime))) => {}
+            Ok(event) => event.paths.iter().for_each(|path| cache.invalidate(path)),
+            Err(_) => cache.invalidate_all(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn listen(_socket: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    Err("unix sockets are not available on this platform; use `toprompt serve --editor`".into())
+}
@@ -0,0 +1,186 @@
+// Dependency summary for --deps: reads the package manifests (Cargo.toml, package.json, pyproject.toml, go.mod)
+// at the bundled roots and lists each dependency's name and version requirement, leaving lockfiles out.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value as Json;
+use toml::Value as Toml;
+
+use crate::Config;
+
+// (group, "name version" items) pairs for one manifest; the main group is "dependencies".
+type Groups = Vec<(String, Vec<String>)>;
+
+const MANIFESTS: [&str; 4] = ["Cargo.toml", "package.json", "pyproject.toml", "go.mod"];
+
+// The dependency summary for the manifests found directly in `roots`, or None if there are none.
+pub fn summarize(roots: &[&Path], config: &Config) -> Option<String> {
+    let mut sections = Vec::new();
+    for root in roots {
+        for name in MANIFESTS {
+            let path = root.join(name);
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let groups = match name {
+                "Cargo.toml" => cargo(&contents),
+                "package.json" => npm(&contents),
+                "pyproject.toml" => python(&contents),
+                _ => go(&contents),
+            };
+            let groups: Groups = groups.into_iter().filter(|(_, items)| !items.is_empty()).collect();
+            if !groups.is_empty() {
+                sections.push((crate::anonymize(config, &crate::display_relative(&fs::canonicalize(&path).unwrap_or(path))), groups));
+            }
+        }
+    }
+    if sections.is_empty() {
+        return None;
+    }
+    let mut out = String::new();
+    if config.use_xml {
+        out.push_str("<dependencies>\n");
+        for (path, groups) in &sections {
+            for (group, items) in groups {
+                out.push_str(&format!("<manifest path=\"{}\" group=\"{}\">{}</manifest>\n", path, group, items.join(", ")));
+            }
+        }
+        out.push_str("</dependencies>");
+    } else {
+        out.push_str("# Dependencies");
+        for (path, groups) in &sections {
+            out.push_str(&format!("\n{}:", path));
+            for (group, items) in groups {
+                out.push_str(&format!("\n  {}: {}", group, items.join(", ")));
+            }
+        }
+    }
+    Some(out)
+}
+
+fn cargo(contents: &str) -> Groups {
+    let Ok(manifest) = contents.parse::<toml::Table>() else {
+        return Vec::new();
+    };
+    let table_items = |table: Option<&Toml>| -> Vec<String> {
+        let Some(table) = table.and_then(Toml::as_table) else {
+            return Vec::new();
+        };
+        table
+            .iter()
+            .map(|(name, spec)| {
+                let version = match spec {
+                    Toml::String(version) => Some(version.as_str()),
+                    Toml::Table(spec) => spec.get("version").and_then(Toml::as_str),
+                    _ => None,
+                };
+                let source = match spec {
+                    Toml::Table(spec) if spec.contains_key("path") => Some("path"),
+                    Toml::Table(spec) if spec.contains_key("git") => Some("git"),
+                    Toml::Table(spec) if spec.get("workspace").and_then(Toml::as_bool) == Some(true) => Some("workspace"),
+                    _ => None,
+                };
+                match (version, source) {
+                    (Some(version), _) => format!("{} {}", name, version),
+                    (None, Some(source)) => format!("{} ({})", name, source),
+                    (None, None) => name.clone(),
+                }
+            })
+            .collect()
+    };
+    let mut groups = vec![
+        ("dependencies".to_string(), table_items(manifest.get("dependencies"))),
+        ("dev".to_string(), table_items(manifest.get("dev-dependencies"))),
+        ("build".to_string(), table_items(manifest.get("build-dependencies"))),
+    ];
+    if let Some(workspace) = manifest.get("workspace") {
+        groups.push(("workspace".to_string(), table_items(workspace.get("dependencies"))));
+    }
+    groups
+}
+
+fn npm(contents: &str) -> Groups {
+    let Ok(manifest) = serde_json::from_str::<Json>(contents) else {
+        return Vec::new();
+    };
+    let object_items = |key: &str| -> Vec<String> {
+        manifest
+            .get(key)
+            .and_then(Json::as_object)
+            .map(|deps| deps.iter().map(|(name, version)| format!("{} {}", name, version.as_str().unwrap_or("*"))).collect())
+            .unwrap_or_default()
+    };
+    vec![
+        ("dependencies".to_string(), object_items("dependencies")),
+        ("dev".to_string(), object_items("devDependencies")),
+        ("peer".to_string(), object_items("peerDependencies")),
+    ]
+}
+
+fn python(contents: &str) -> Groups {
+    let Ok(manifest) = contents.parse::<toml::Table>() else {
+        return Vec::new();
+    };
+    // PEP 621 requirement strings ("requests>=2.31") are already compact.
+    let requirements = |value: Option<&Toml>| -> Vec<String> {
+        value
+            .and_then(Toml::as_array)
+            .map(|items| items.iter().filter_map(Toml::as_str).map(|item| item.split(';').next().unwrap_or(item).trim().to_string()).collect())
+            .unwrap_or_default()
+    };
+    let project = manifest.get("project");
+    let mut groups = vec![("dependencies".to_string(), requirements(project.and_then(|project| project.get("dependencies"))))];
+    if let Some(optional) = project.and_then(|project| project.get("optional-dependencies")).and_then(Toml::as_table) {
+        for (extra, items) in optional {
+            groups.push((format!("extra {}", extra), requirements(Some(items))));
+        }
+    }
+    // Poetry keeps its own table, with the interpreter listed as "python".
+    if let Some(poetry) = manifest.get("tool").and_then(|tool| tool.get("poetry")).and_then(|poetry| poetry.get("dependencies")).and_then(Toml::as_table) {
+        let items = poetry
+            .iter()
+            .filter(|(name, _)| name.as_str() != "python")
+            .map(|(name, spec)| match spec {
+                Toml::String(version) => format!("{} {}", name, version),
+                Toml::Table(spec) => match spec.get("version").and_then(Toml::as_str) {
+                    Some(version) => format!("{} {}", name, version),
+                    None => name.clone(),
+                },
+                _ => name.clone(),
+            })
+            .collect();
+        groups.push(("poetry".to_string(), items));
+    }
+    groups
+}
+
+fn go(contents: &str) -> Groups {
+    let mut direct = Vec::new();
+    let mut indirect = Vec::new();
+    let mut in_block = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        let requirement = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if line == "require (" {
+            in_block = true;
+            continue;
+        } else if let Some(requirement) = line.strip_prefix("require ") {
+            requirement
+        } else {
+            continue;
+        };
+        let (requirement, comment) = requirement.split_once("//").unwrap_or((requirement, ""));
+        let mut parts = requirement.split_whitespace();
+        if let (Some(module), Some(version)) = (parts.next(), parts.next()) {
+            let item = format!("{} {}", module, version);
+            if comment.trim() == "indirect" { indirect.push(item) } else { direct.push(item) }
+        }
+    }
+    vec![("dependencies".to_string(), direct), ("indirect".to_string(), indirect)]
+}
@@ -0,0 +1,183 @@
+// `toprompt doctor`: checks the environment toprompt depends on (clipboard, display session, external tools,
+// cache directory) and prints a fix for each problem, so failures show up before a full bundling run.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::clipboard;
+use crate::config_file;
+use crate::remote;
+
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    status: Status,
+    name: &'static str,
+    detail: String,
+    fix: Option<String>,
+}
+
+impl Check {
+    fn new(status: Status, name: &'static str, detail: impl Into<String>) -> Self {
+        Check { status, name, detail: detail.into(), fix: None }
+    }
+
+    fn fix(mut self, fix: impl Into<String>) -> Self {
+        self.fix = Some(fix.into());
+        self
+    }
+}
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt doctor");
+    eprintln!("  Checks clipboard backends, the display/SSH session, git, gh, curl, .toprompt.toml and the cache,");
+    eprintln!("  and prints how to fix anything that would make toprompt fail.");
+}
+
+pub fn run(args: &[String]) {
+    if !args.is_empty() {
+        print_usage();
+        std::process::exit(crate::EXIT_ERROR);
+    }
+
+    let mut checks = session_checks();
+    checks.push(clipboard_check());
+    checks.push(tool_check("git", "needed for --compare and git repository URLs", "install git (https://git-scm.com/downloads)"));
+    checks.push(tool_check("gh", "needed for `toprompt pr`", "install the GitHub CLI (https://cli.github.com) and run `gh auth login`"));
+    checks.push(tool_check("curl", "needed to fetch http(s) URLs", "install curl"));
+    checks.push(match find_executable("bat").or_else(|| find_executable("batcat")) {
+        Some(path) => Check::new(Status::Ok, "highlighting", format!("--preview highlights with {}", path.display())),
+        None => Check::new(Status::Ok, "highlighting", "bat not found; --preview pages without highlighting"),
+    });
+    checks.push(config_check());
+    checks.push(cache_check());
+    checks.push(Check::new(Status::Ok, "tokenizer", "token counts are estimated (~4 characters per token); no tokenizer needs to be installed"));
+
+    let mut failures = 0;
+    for check in &checks {
+        let label = match check.status {
+            Status::Ok => "ok",
+            Status::Warn => "warn",
+            Status::Fail => {
+                failures += 1;
+                "FAIL"
+            }
+        };
+        println!("[{:>4}] {}: {}", label, check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            println!("       fix: {}", fix);
+        }
+    }
+    if failures > 0 {
+        std::process::exit(crate::EXIT_ERROR);
+    }
+}
+
+// Finds `name` on PATH, like `which`.
+fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        [name.to_string(), format!("{}.exe", name)]
+            .into_iter()
+            .map(|file| dir.join(file))
+            .find(|candidate| candidate.is_file())
+    })
+}
+
+fn is_set(var: &str) -> bool {
+    env::var_os(var).is_some_and(|value| !value.is_empty())
+}
+
+// On Linux the clipboard tools need a graphical session; over SSH they would copy on the remote machine.
+fn session_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+    let over_ssh = is_set("SSH_CONNECTION") || is_set("SSH_TTY");
+    if cfg!(target_os = "linux") {
+        checks.push(match (is_set("WAYLAND_DISPLAY"), is_set("DISPLAY")) {
+            (true, _) => Check::new(Status::Ok, "session", "Wayland"),
+            (false, true) => Check::new(Status::Ok, "session", "X11"),
+            (false, false) => Check::new(Status::Warn, "session", "neither WAYLAND_DISPLAY nor DISPLAY is set, so clipboard tools cannot reach a clipboard")
+                .fix("use --stdout or -o <file>, or run toprompt inside a graphical session"),
+        });
+    }
+    if over_ssh {
+        checks.push(
+            Check::new(Status::Warn, "ssh", "running over SSH; the clipboard is the remote machine's, not yours")
+                .fix("use --stdout and copy locally, or forward X11 with `ssh -X` so xclip reaches your display"),
+        );
+    }
+    checks
+}
+
+fn clipboard_check() -> Check {
+    let available: Vec<String> = clipboard::clipboard_tools(clipboard::Selection::Clipboard)
+        .into_iter()
+        .filter_map(|(tool, _)| find_executable(tool).map(|_| tool.to_string()))
+        .collect();
+    if available.is_empty() {
+        let fix = if cfg!(target_os = "macos") {
+            "pbcopy ships with macOS; check that /usr/bin is on PATH"
+        } else if cfg!(target_os = "windows") {
+            "clip.exe ships with Windows; check that C:\\Windows\\System32 is on PATH"
+        } else if is_set("WAYLAND_DISPLAY") {
+            "install wl-clipboard (e.g. `sudo apt install wl-clipboard`)"
+        } else {
+            "install xclip or xsel (e.g. `sudo apt install xclip`), or wl-clipboard on Wayland"
+        };
+        return Check::new(Status::Fail, "clipboard", "no clipboard tool found on PATH").fix(fix);
+    }
+    // wl-copy only works in a Wayland session, xclip/xsel only with an X display.
+    let usable = !cfg!(target_os = "linux")
+        || available.iter().any(|tool| if tool == "wl-copy" { is_set("WAYLAND_DISPLAY") } else { is_set("DISPLAY") });
+    if usable {
+        Check::new(Status::Ok, "clipboard", format!("found {}", available.join(", ")))
+    } else {
+        Check::new(Status::Warn, "clipboard", format!("found {}, but not for the current session", available.join(", ")))
+            .fix("install wl-clipboard for Wayland or xclip for X11, or use --stdout")
+    }
+}
+
+fn tool_check(tool: &'static str, purpose: &str, fix: &str) -> Check {
+    let version = Command::new(tool)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default().trim().to_string());
+    match version {
+        Some(version) => Check::new(Status::Ok, tool, version),
+        None => Check::new(Status::Warn, tool, format!("not found ({})", purpose)).fix(fix),
+    }
+}
+
+fn config_check() -> Check {
+    match config_file::find() {
+        None => Check::new(Status::Ok, "config", format!("no {} (create one with `toprompt init`)", config_file::FILE_NAME)),
+        Some(path) => match config_file::load(&path) {
+            Ok(_) => Check::new(Status::Ok, "config", format!("{} is valid", path.display())),
+            Err(e) => Check::new(Status::Fail, "config", format!("{} is invalid: {}", path.display(), e))
+                .fix("fix the file, or pass --no-config to ignore it"),
+        },
+    }
+}
+
+fn cache_check() -> Check {
+    let dir = remote::cache_dir();
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    match fs::create_dir_all(&dir).and_then(|_| fs::write(&probe, b"")) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            Check::new(Status::Ok, "cache", format!("{} is writable", dir.display()))
+        }
+        Err(e) => Check::new(Status::Warn, "cache", format!("{} is not writable: {}", dir.display(), e))
+            .fix("set XDG_CACHE_HOME to a writable directory, or pass --no-cache"),
+    }
+}
@@ -0,0 +1,44 @@
+// Lets the user review and trim the bundle in their editor before it is copied.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process::Command;
+
+use crate::bundle::Bundle;
+
+// $EDITOR, then $VISUAL, then a platform default.
+fn editor_command() -> String {
+    ["EDITOR", "VISUAL"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() })
+}
+
+// Writes the bundle to a temp file, opens it in the editor and returns the buffer once the editor exits.
+pub fn edit_bundle(bundle: &Bundle, use_xml: bool) -> Result<String, Box<dyn std::error::Error>> {
+    let extension = if use_xml { "xml" } else { "md" };
+    let path = env::temp_dir().join(format!("toprompt-{}.{}", std::process::id(), extension));
+    {
+        let file = File::create(&path).map_err(|e| format!("could not create '{}': {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        bundle.write_to(&mut writer)?;
+        writer.flush()?;
+    }
+
+    let editor = editor_command();
+    // Go through the shell so editors configured with arguments (e.g. "code --wait") work, as git does.
+    let status = if cfg!(windows) {
+        Command::new("cmd").arg("/C").arg(format!("{} \"{}\"", editor, path.display())).status()
+    } else {
+        Command::new("sh").arg("-c").arg(format!("{} \"$@\"", editor)).arg(&editor).arg(&path).status()
+    };
+    let result = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path).map_err(|e| format!("could not read back '{}': {}", path.display(), e).into()),
+        Ok(status) => Err(format!("editor '{}' exited with {}", editor, status).into()),
+        Err(e) => Err(format!("could not run editor '{}': {}", editor, e).into()),
+    };
+    let _ = fs::remove_file(&path);
+    result
+}
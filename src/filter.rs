@@ -0,0 +1,303 @@
+// File selection expressions for --filter, e.g. `ext=rs and size<50kb and not path~"tests/"`. Predicates are
+// `field op value` over ext, name, path, lang, size and mtime, combined with and/or/not and parentheses
+// (`and` binds tighter than `or`). Values are bare words or double-quoted strings. Names and paths follow
+// --ignore-case/--smart-case; languages always match regardless of case.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+
+use crate::ignore::Case;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+enum Predicate {
+    // ext, name and lang: one of a comma-separated list of values (lowercased when `fold`).
+    Text { field: &'static str, negate: bool, fold: bool, values: Vec<String> },
+    Path { negate: bool, regex: Regex },
+    Size { op: Op, bytes: u64 },
+    // size=1kb..200kb (either bound may be left out).
+    SizeRange { min: Option<u64>, max: Option<u64> },
+    // The file's age against a duration: mtime<7d is "modified in the last 7 days".
+    Age { op: Op, age: Duration },
+}
+
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Predicate(Predicate),
+}
+
+pub struct Filter {
+    pub source: String,
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str, case: Case) -> Result<Filter, String> {
+        let mut parser = Parser { tokens: tokenize(source)?, position: 0, case };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(format!("unexpected '{}'", token.text()));
+        }
+        Ok(Filter { source: source.to_string(), expr })
+    }
+
+    // Whether the file at `path` (`relative` is the name predicates on `path` see) is selected.
+    pub fn matches(&self, path: &Path, relative: &str) -> bool {
+        eval(&self.expr, path, relative)
+    }
+}
+
+fn eval(expr: &Expr, path: &Path, relative: &str) -> bool {
+    match expr {
+        Expr::And(left, right) => eval(left, path, relative) && eval(right, path, relative),
+        Expr::Or(left, right) => eval(left, path, relative) || eval(right, path, relative),
+        Expr::Not(inner) => !eval(inner, path, relative),
+        Expr::Predicate(predicate) => test(predicate, path, relative),
+    }
+}
+
+fn test(predicate: &Predicate, path: &Path, relative: &str) -> bool {
+    match predicate {
+        Predicate::Text { field, negate, fold, values } => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let actual = match *field {
+                "ext" => path.extension().unwrap_or_default().to_string_lossy().into_owned(),
+                "lang" => crate::detect_language(path),
+                _ => name.into_owned(),
+            };
+            let actual = if *fold { actual.to_lowercase() } else { actual };
+            values.contains(&actual) != *negate
+        }
+        Predicate::Path { negate, regex } => regex.is_match(relative) != *negate,
+        Predicate::Size { op, bytes } => fs::metadata(path).is_ok_and(|metadata| compare(metadata.len(), *op, *bytes)),
+        Predicate::SizeRange { min, max } => fs::metadata(path)
+            .is_ok_and(|metadata| min.is_none_or(|min| metadata.len() >= min) && max.is_none_or(|max| metadata.len() <= max)),
+        Predicate::Age { op, age } => fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| compare(SystemTime::now().duration_since(modified).unwrap_or_default(), *op, *age)),
+    }
+}
+
+fn compare<T: PartialOrd>(actual: T, op: Op, expected: T) -> bool {
+    match op {
+        Op::Eq => actual == expected,
+        Op::Ne => actual != expected,
+        Op::Lt => actual < expected,
+        Op::Le => actual <= expected,
+        Op::Gt => actual > expected,
+        Op::Ge => actual >= expected,
+        Op::Match | Op::NotMatch => false,
+    }
+}
+
+#[derive(PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Word(word) => word.clone(),
+            Token::Quoted(text) => format!("\"{}\"", text),
+            Token::Op(op) => OPERATORS.iter().find(|(_, candidate)| candidate == op).map_or("?", |(text, _)| text).to_string(),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+        }
+    }
+}
+
+// Two-character operators first, so `<=` is not read as `<` followed by `=`.
+const OPERATORS: &[(&str, Op)] =
+    &[("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("!~", Op::NotMatch), ("==", Op::Eq), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt), ("~", Op::Match)];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while !rest.is_empty() {
+        if let Some((text, op)) = OPERATORS.iter().find(|(text, _)| rest.starts_with(text)) {
+            tokens.push(Token::Op(*op));
+            rest = &rest[text.len()..];
+        } else if let Some(after) = rest.strip_prefix('(') {
+            tokens.push(Token::Open);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(')') {
+            tokens.push(Token::Close);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('"') {
+            let mut text = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => text.extend(chars.next().map(|(_, c)| c)),
+                    Some((_, c)) => text.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            };
+            tokens.push(Token::Quoted(text));
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find(|c: char| c.is_whitespace() || "()\"=!<>~".contains(c)).unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    case: Case,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.tokens.get(self.position), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword));
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field = field.to_lowercase();
+                let op = match self.next() {
+                    Some(Token::Op(op)) => *op,
+                    _ => return Err(format!("expected an operator after '{}'", field)),
+                };
+                let value = match self.next() {
+                    Some(Token::Word(value) | Token::Quoted(value)) => value.clone(),
+                    _ => return Err(format!("expected a value after '{}'", field)),
+                };
+                predicate(&field, op, &value, self.case).map(Expr::Predicate)
+            }
+            Some(token) => Err(format!("unexpected '{}'", token.text())),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+fn predicate(field: &str, op: Op, value: &str, case: Case) -> Result<Predicate, String> {
+    let equality = |op: Op| match op {
+        Op::Eq => Ok(false),
+        Op::Ne => Ok(true),
+        _ => Err(format!("{} only supports = and !=", field)),
+    };
+    match field {
+        "ext" | "name" | "lang" => {
+            let field = match field {
+                "ext" => "ext",
+                "lang" => "lang",
+                _ => "name",
+            };
+            let fold = field == "lang" || case.folds(value);
+            let values = value
+                .split(',')
+                .map(|value| value.trim().trim_start_matches('.'))
+                .map(|value| if fold { value.to_lowercase() } else { value.to_string() })
+                .collect();
+            Ok(Predicate::Text { field, negate: equality(op)?, fold, values })
+        }
+        "path" => {
+            let negate = match op {
+                Op::Match => false,
+                Op::NotMatch => true,
+                // An exact path is a regex match of the whole escaped path.
+                Op::Eq | Op::Ne => {
+                    let regex = case.regex(&format!("^{}$", regex::escape(value.trim_start_matches("./")))).map_err(|e| e.to_string())?;
+                    return Ok(Predicate::Path { negate: op == Op::Ne, regex });
+                }
+                _ => return Err("path only supports ~, !~, = and !=".to_string()),
+            };
+            let regex = case.regex(value).map_err(|e| format!("invalid path regex '{}': {}", value, e))?;
+            Ok(Predicate::Path { negate, regex })
+        }
+        "size" => match op {
+            Op::Match | Op::NotMatch => Err("size only supports comparisons".to_string()),
+            Op::Eq if value.contains("..") => crate::parse_size_range(value)
+                .map(|(min, max)| Predicate::SizeRange { min, max })
+                .ok_or_else(|| format!("'{}' is not a size range like 1kb..200kb", value)),
+            _ => crate::parse_size(value)
+                .map(|bytes| Predicate::Size { op, bytes })
+                .ok_or_else(|| format!("'{}' is not a size like 500kb or 10mb", value)),
+        },
+        "mtime" => match op {
+            Op::Match | Op::NotMatch => Err("mtime only supports comparisons".to_string()),
+            _ => parse_age(value).map(|age| Predicate::Age { op, age }).ok_or_else(|| format!("'{}' is not an age like 30m, 12h, 7d or 2w", value)),
+        },
+        _ => Err(format!("unknown field '{}' (expected ext, name, path, lang, size or mtime)", field)),
+    }
+}
+
+// Parses ages such as `90s`, `30m`, `12h`, `7d` or `2w`.
+fn parse_age(value: &str) -> Option<Duration> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let (number, unit) = value.split_at(split);
+    let seconds: f64 = match unit {
+        "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86_400.0,
+        "w" => 604_800.0,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    (number >= 0.0).then(|| Duration::from_secs_f64(number * seconds))
+}
@@ -0,0 +1,107 @@
+// Helpers for reading repository state through the git CLI.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::remote;
+
+// Runs git in `dir` and returns its stdout, or an error carrying git's stderr.
+pub fn run(dir: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run git: {}. Is git installed?", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Contents of `path` at `git_ref`, or None if the file does not exist at that revision.
+pub fn show_file(path: &Path, git_ref: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(format!("'{}' is not a file path", path.display()).into());
+    };
+    // Validate the ref separately so a bad ref is an error rather than a "missing file".
+    run(dir, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", git_ref)])
+        .map_err(|_| format!("unknown git revision '{}'", git_ref))?;
+    let spec = format!("{}:./{}", git_ref, name.to_string_lossy());
+    match run(dir, &["show", &spec]) {
+        Ok(contents) => Ok(Some(contents.replace("\r\n", "\n"))),
+        Err(_) => Ok(None),
+    }
+}
+
+// The (short hash, author) of the commit that last changed each line of the working-tree file at `path`.
+// Lines not committed yet get the hash "0000000" and the author "uncommitted".
+pub fn blame(path: &Path) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(format!("'{}' is not a file path", path.display()).into());
+    };
+    let output = run(dir, &["blame", "--line-porcelain", "--", &name.to_string_lossy()])?;
+    let mut lines = Vec::new();
+    let mut hash = String::new();
+    let mut author = String::new();
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            let uncommitted = hash.bytes().all(|b| b == b'0');
+            lines.push((hash[..hash.len().min(7)].to_string(), if uncommitted { "uncommitted".to_string() } else { author.clone() }));
+        } else if let Some(name) = line.strip_prefix("author ") {
+            author = name.to_string();
+        } else if line.len() > 40 && line.as_bytes()[40] == b' ' && line[..40].bytes().all(|b| b.is_ascii_hexdigit()) {
+            hash = line[..40].to_string();
+        }
+    }
+    Ok(lines)
+}
+
+// The repository containing `path` (a file or directory).
+pub fn toplevel(path: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+    let top = run(dir, &["rev-parse", "--show-toplevel"])?;
+    Ok(fs::canonicalize(top.trim())?)
+}
+
+// Extracts the tree of `git_ref` into the cache (once per commit) and returns the directory and the commit hash.
+pub fn export_tree(repo: &Path, git_ref: &str) -> Result<(PathBuf, String), Box<dyn std::error::Error>> {
+    let commit = run(repo, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", git_ref)])
+        .map_err(|_| format!("unknown git revision '{}'", git_ref))?
+        .trim()
+        .to_string();
+    let trees_dir = remote::cache_dir().join("trees");
+    let tree_dir = trees_dir.join(&commit);
+    if tree_dir.is_dir() {
+        return Ok((tree_dir, commit));
+    }
+
+    // Extract next to the final location and rename, so an interrupted run never leaves a partial tree.
+    let partial = trees_dir.join(format!("{}.{}", commit, std::process::id()));
+    fs::create_dir_all(&partial)?;
+    let mut archive = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["archive", "--format=tar", &commit])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run git: {}. Is git installed?", e))?;
+    let extracted = Command::new("tar").arg("-x").arg("-C").arg(&partial).stdin(archive.stdout.take().ok_or("could not read git archive output")?).status();
+    let archived = archive.wait()?;
+    if !matches!(&extracted, Ok(status) if status.success()) || !archived.success() {
+        let _ = fs::remove_dir_all(&partial);
+        return Err(match extracted {
+            Err(e) => format!("could not run tar: {}", e),
+            Ok(_) => format!("could not extract the tree of '{}'", git_ref),
+        }
+        .into());
+    }
+    if fs::rename(&partial, &tree_dir).is_err() {
+        // Another run extracted the same commit first.
+        let _ = fs::remove_dir_all(&partial);
+    }
+    Ok((tree_dir, commit))
+}
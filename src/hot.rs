@@ -0,0 +1,86 @@
+// Git activity ranking for --hot: keeps the files changed in the most commits (churn) or most recently,
+// using one `git log` per repository the bundled files belong to.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bundle::{Bundle, BundleEntry};
+use crate::git;
+
+#[derive(Clone, Copy)]
+pub enum HotMode {
+    // Most commits first.
+    Churn,
+    // Latest commit first.
+    Recent,
+}
+
+impl HotMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "churn" => Some(HotMode::Churn),
+            "recent" => Some(HotMode::Recent),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Activity {
+    commits: usize,
+    // Unix time of the latest commit touching the file.
+    latest: i64,
+}
+
+// Keeps the `count` hottest local files, hottest first. Files without history rank last.
+// Returns the number of files dropped.
+pub fn apply(bundle: &mut Bundle, count: usize, mode: HotMode, verbose: bool) -> usize {
+    let mut repos: HashMap<PathBuf, Option<HashMap<PathBuf, Activity>>> = HashMap::new();
+    let mut ranked: Vec<(Activity, BundleEntry)> = bundle
+        .entries
+        .drain(..)
+        .map(|entry| {
+            let activity = entry.path.as_deref().and_then(|path| {
+                let root = git::toplevel(path).ok()?;
+                let history = repos.entry(root.clone()).or_insert_with(|| history(&root));
+                history.as_ref()?.get(path).copied()
+            });
+            (activity.unwrap_or_default(), entry)
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| match mode {
+        HotMode::Churn => b.commits.cmp(&a.commits).then(b.latest.cmp(&a.latest)),
+        HotMode::Recent => b.latest.cmp(&a.latest).then(b.commits.cmp(&a.commits)),
+    });
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs() as i64).unwrap_or(0);
+    let candidate_count = ranked.len();
+    for (activity, entry) in ranked.into_iter().take(count) {
+        if verbose {
+            match activity.commits {
+                0 => println!("Hot (no git history): {}", entry.display_name),
+                commits => println!("Hot ({} commit(s), last {} day(s) ago): {}", commits, (now - activity.latest).max(0) / 86400, entry.display_name),
+            }
+        }
+        bundle.entries.push(entry);
+    }
+    candidate_count - bundle.entries.len()
+}
+
+// Commit count and latest commit time for every file in the repository's history.
+fn history(root: &Path) -> Option<HashMap<PathBuf, Activity>> {
+    let log = git::run(root, &["-c", "core.quotePath=false", "log", "--no-renames", "--format=@%ct", "--name-only"]).ok()?;
+    let mut activity: HashMap<PathBuf, Activity> = HashMap::new();
+    let mut time = 0;
+    for line in log.lines().filter(|line| !line.is_empty()) {
+        if let Some(timestamp) = line.strip_prefix('@').and_then(|timestamp| timestamp.parse().ok()) {
+            time = timestamp;
+            continue;
+        }
+        let file = activity.entry(root.join(line)).or_default();
+        file.commits += 1;
+        file.latest = file.latest.max(time);
+    }
+    Some(activity)
+}
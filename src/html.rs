@@ -0,0 +1,306 @@
+// Minimal HTML-to-markdown conversion for webpage inputs. Keeps the readable content
+// (preferring <article>/<main>) and drops scripts, styles and page chrome.
+
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "svg", "nav", "header", "footer", "aside", "form", "iframe", "template", "button",
+];
+
+pub fn looks_like_html(body: &str) -> bool {
+    let start: String = body.trim_start().chars().take(512).collect::<String>().to_ascii_lowercase();
+    start.starts_with("<!doctype html") || start.contains("<html") || start.contains("<body") || start.contains("<head")
+}
+
+pub fn extract_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title>")?;
+    let title = collapse_whitespace(&decode_entities(&html[start..end]));
+    let title = title.trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+pub fn to_markdown(html: &str) -> String {
+    let content = main_content(html);
+    let mut converter = Converter::default();
+    converter.convert(content);
+    tidy(&converter.output)
+}
+
+// Narrows the document to its <article> or <main> element when present, like readability tools do.
+fn main_content(html: &str) -> &str {
+    let lower = html.to_ascii_lowercase();
+    for tag in ["article", "main"] {
+        if let Some(open) = lower.find(&format!("<{}", tag))
+            && let Some(close) = lower.rfind(&format!("</{}>", tag))
+            && close > open
+        {
+            return &html[open..close + tag.len() + 3];
+        }
+    }
+    match (lower.find("<body"), lower.rfind("</body>")) {
+        (Some(open), Some(close)) if close > open => &html[open..close + 7],
+        _ => html,
+    }
+}
+
+#[derive(Default)]
+struct Converter {
+    output: String,
+    skip_depth: usize,
+    skip_tag: String,
+    pre_depth: usize,
+    list_depth: usize,
+    link_href: Vec<Option<String>>,
+}
+
+impl Converter {
+    fn convert(&mut self, html: &str) {
+        let mut rest = html;
+        while !rest.is_empty() {
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+                let end = rest.find('>').unwrap_or(rest.len() - 1);
+                self.handle_tag(&rest[1..end]);
+                rest = &rest[end + 1..];
+                continue;
+            }
+            let text_end = rest[1..].find('<').map_or(rest.len(), |i| i + 1);
+            self.handle_text(&rest[..text_end]);
+            rest = &rest[text_end..];
+        }
+    }
+
+    fn handle_text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        let decoded = decode_entities(text);
+        if self.pre_depth > 0 {
+            self.output.push_str(&decoded);
+        } else {
+            let collapsed = collapse_whitespace(&decoded);
+            if collapsed.trim().is_empty() {
+                if !self.output.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+                    self.output.push(' ');
+                }
+                return;
+            }
+            if collapsed.starts_with(' ') && self.output.ends_with(char::is_whitespace) {
+                self.output.push_str(collapsed.trim_start());
+            } else {
+                self.output.push_str(&collapsed);
+            }
+        }
+    }
+
+    fn handle_tag(&mut self, tag: &str) {
+        if tag.starts_with('!') || tag.starts_with('?') {
+            return;
+        }
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace() || c == '/').unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if self.skip_depth > 0 {
+            if name == self.skip_tag {
+                if closing {
+                    self.skip_depth -= 1;
+                } else if !tag.ends_with('/') {
+                    self.skip_depth += 1;
+                }
+            }
+            return;
+        }
+        if !closing && SKIPPED_ELEMENTS.contains(&name.as_str()) && !tag.ends_with('/') {
+            self.skip_depth = 1;
+            self.skip_tag = name;
+            return;
+        }
+
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                self.block_break();
+                self.output.push_str(&"#".repeat(level));
+                self.output.push(' ');
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "p" | "div" | "section" | "table" | "blockquote" | "dl", _) => self.block_break(),
+            ("br", _) => self.output.push('\n'),
+            ("hr", _) => {
+                self.block_break();
+                self.output.push_str("---");
+                self.block_break();
+            }
+            ("tr" | "dt" | "dd", _) => self.line_break(),
+            ("td" | "th", false) => self.output.push_str(" | "),
+            ("ul" | "ol", false) => {
+                self.list_depth += 1;
+                self.line_break();
+            }
+            ("ul" | "ol", true) => {
+                self.list_depth = self.list_depth.saturating_sub(1);
+                self.line_break();
+            }
+            ("li", false) => {
+                self.line_break();
+                self.output.push_str(&"  ".repeat(self.list_depth.saturating_sub(1)));
+                self.output.push_str("- ");
+            }
+            ("pre", false) => {
+                self.block_break();
+                self.output.push_str("```\n");
+                self.pre_depth += 1;
+            }
+            ("pre", true) => {
+                self.pre_depth = self.pre_depth.saturating_sub(1);
+                if !self.output.ends_with('\n') {
+                    self.output.push('\n');
+                }
+                self.output.push_str("```");
+                self.block_break();
+            }
+            ("code", _) if self.pre_depth == 0 => self.output.push('`'),
+            ("strong" | "b", _) => self.output.push_str("**"),
+            ("em" | "i", _) => self.output.push('*'),
+            ("a", false) => {
+                self.link_href.push(attribute(tag, "href"));
+                self.output.push('[');
+            }
+            ("a", true) => match self.link_href.pop() {
+                Some(Some(href)) => self.output.push_str(&format!("]({})", href)),
+                _ => self.output.push(']'),
+            },
+            ("img", _) => {
+                if let Some(alt) = attribute(tag, "alt").filter(|alt| !alt.is_empty()) {
+                    self.output.push_str(&format!("[image: {}]", alt));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn line_break(&mut self) {
+        let trimmed_len = self.output.trim_end_matches([' ', '\t']).len();
+        self.output.truncate(trimmed_len);
+        if !self.output.is_empty() && !self.output.ends_with('\n') {
+            self.output.push('\n');
+        }
+    }
+
+    fn block_break(&mut self) {
+        self.line_break();
+        if !self.output.is_empty() && !self.output.ends_with("\n\n") {
+            self.output.push('\n');
+        }
+    }
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(found) = lower[search_from..].find(name) {
+        let start = search_from + found;
+        search_from = start + name.len();
+        let preceded_by_space = lower[..start].ends_with(char::is_whitespace);
+        let after = lower[search_from..].trim_start();
+        if !preceded_by_space || !after.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - after.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value.split(char::is_whitespace).next().unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut previous_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !previous_space {
+                collapsed.push(' ');
+            }
+            previous_space = true;
+        } else {
+            collapsed.push(c);
+            previous_space = false;
+        }
+    }
+    collapsed
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity_end = rest.find(';').filter(|&end| end <= 10);
+        let replacement = entity_end.and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "mdash" => Some('—'),
+                "ndash" => Some('–'),
+                "hellip" => Some('…'),
+                "copy" => Some('©'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match replacement {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn tidy(markdown: &str) -> String {
+    let mut tidied = String::with_capacity(markdown.len());
+    let mut blank_run = 0;
+    for line in markdown.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run > 1 {
+                continue;
+            }
+        } else {
+            blank_run = 0;
+        }
+        tidied.push_str(line);
+        tidied.push('\n');
+    }
+    tidied.trim().to_string()
+}
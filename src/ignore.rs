@@ -0,0 +1,409 @@
+//! Gitignore-style matching. Every active pattern is translated to a regex and compiled into a
+//! single RegexSet, so each path is checked in one pass instead of once per pattern. The set is
+//! compiled once all ignore files are loaded, on the first match or an explicit [`Matcher::build`].
+//!
+//! This module is also toprompt's public library API. Nested ignore files merge the way git's do:
+//! clone the parent directory's matcher and add the directory's own file, whose patterns are anchored
+//! where it lives and, being added last, take precedence.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use toprompt::ignore::Matcher;
+//!
+//! let root = Path::new("project");
+//! let mut matcher = Matcher::with_defaults(root);
+//! matcher.add_ignore_file(&root.join(".gitignore"));
+//! let mut nested = matcher.clone();
+//! nested.add_ignore_file(&root.join("src/.gitignore"));
+//! assert!(!nested.is_ignored(Path::new("src/main.rs"), false));
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, OnceLock};
+
+use regex::{Regex, RegexBuilder, RegexSet};
+
+// Lockfiles and minified/bundled assets: machine-generated, large, and rarely useful to a model.
+// Skipped in directory mode unless --no-auto-skip is given.
+const MACHINE_GENERATED: &[&str] = &[
+    "Cargo.lock", "package-lock.json", "npm-shrinkwrap.json", "yarn.lock", "pnpm-lock.yaml", "bun.lockb",
+    "poetry.lock", "Pipfile.lock", "uv.lock", "composer.lock", "Gemfile.lock", "go.sum", "flake.lock",
+    "*.min.js", "*.min.css", "*.map", "*.bundle.js",
+];
+
+static MACHINE_GENERATED_MATCHER: LazyLock<Matcher> = LazyLock::new(|| {
+    let mut matcher = Matcher::new(Path::new(""));
+    matcher.add_patterns(MACHINE_GENERATED.iter().copied(), Path::new(""), "built-in lockfile/minified list");
+    matcher
+});
+
+/// The built-in pattern matching `file_name` if it is a lockfile or minified asset.
+pub fn machine_generated(file_name: &str) -> Option<Decision<'static>> {
+    MACHINE_GENERATED_MATCHER.explain(Path::new(file_name), false)
+}
+
+/// Case sensitivity of path patterns (--ignore-case, --smart-case).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Case {
+    #[default]
+    Sensitive,
+    Insensitive,
+    /// Insensitive unless the pattern has an uppercase letter.
+    Smart,
+}
+
+impl Case {
+    /// Whether `pattern` matches regardless of case.
+    pub fn folds(self, pattern: &str) -> bool {
+        match self {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !pattern.chars().any(char::is_uppercase),
+        }
+    }
+
+    /// Compiles a regex with this case sensitivity.
+    pub fn regex(self, pattern: &str) -> Result<regex::Regex, regex::Error> {
+        RegexBuilder::new(pattern).case_insensitive(self.folds(pattern)).build()
+    }
+}
+
+#[derive(Clone)]
+struct Rule {
+    regex: String,
+    is_negation: bool,
+    is_directory: bool,
+    // The pattern as written, and where: e.g. "src/.gitignore" and its 1-based line number.
+    pattern: String,
+    source: String,
+    line: usize,
+}
+
+/// A pattern that was left out because it does not translate to a valid regex.
+#[derive(Clone, Debug)]
+pub struct InvalidPattern {
+    pub pattern: String,
+    pub source: String,
+    pub line: usize,
+    pub error: String,
+}
+
+/// The rule that decided whether a path is ignored.
+#[derive(Debug)]
+pub struct Decision<'a> {
+    pub ignored: bool,
+    pub pattern: &'a str,
+    pub source: &'a str,
+    pub line: usize,
+}
+
+#[derive(Clone)]
+pub struct Matcher {
+    base_dir: PathBuf,
+    rules: Vec<Rule>,
+    // Compiled from `rules` when first needed; reset whenever rules are added or the case changes.
+    set: OnceLock<Result<RegexSet, String>>,
+    case: Case,
+    // Patterns added since the last `take_invalid` that could not be compiled.
+    invalid: Vec<InvalidPattern>,
+}
+
+impl Matcher {
+    /// A matcher for paths relative to `base_dir` with no rules.
+    pub fn new(base_dir: &Path) -> Self {
+        Matcher { base_dir: base_dir.to_path_buf(), rules: Vec::new(), set: OnceLock::new(), case: Case::Sensitive, invalid: Vec::new() }
+    }
+
+    /// A matcher that always ignores git's and Mercurial's own metadata (`.git` is a file in submodules and worktrees).
+    pub fn with_defaults(base_dir: &Path) -> Self {
+        let mut matcher = Matcher::new(base_dir);
+        matcher.add_patterns([".git", ".gitignore", ".hg/", ".hgignore"], base_dir, "built-in defaults");
+        matcher
+    }
+
+    /// Sets the case sensitivity of the rules, including those added later; smart case looks at each pattern as written.
+    pub fn set_case(&mut self, case: Case) {
+        self.case = case;
+        self.set = OnceLock::new();
+    }
+
+    /// Adds the patterns of an ignore file (gitignore syntax), anchored at the file's directory.
+    /// Returns false if the file could not be read.
+    pub fn add_ignore_file(&mut self, ignore_file: &Path) -> bool {
+        let Ok(contents) = fs::read_to_string(ignore_file) else {
+            return false;
+        };
+        let defined_in = ignore_file.parent().unwrap_or(&self.base_dir).to_path_buf();
+        self.add_patterns(contents.lines(), &defined_in, &ignore_file.display().to_string());
+        true
+    }
+
+    /// Adds the paths a .gitattributes file marks `linguist-generated` or `linguist-vendored` as ignored;
+    /// lines that unset those attributes re-include. Returns false if the file could not be read.
+    pub fn add_attributes_file(&mut self, attributes_file: &Path) -> bool {
+        let Ok(contents) = fs::read_to_string(attributes_file) else {
+            return false;
+        };
+        // One pattern per line (blank when the line is irrelevant) keeps line numbers for --why.
+        let patterns: Vec<String> = contents.lines().map(attributes_line_to_pattern).collect();
+        let defined_in = attributes_file.parent().unwrap_or(&self.base_dir).to_path_buf();
+        self.add_patterns(patterns.iter().map(String::as_str), &defined_in, &attributes_file.display().to_string());
+        true
+    }
+
+    /// Adds the patterns of a Mercurial .hgignore: regexp syntax unless `syntax: glob` (or `rootglob`) switches,
+    /// and `re:`/`glob:`/`rootglob:` prefixes choose per line. Regexps match anywhere in the path unless they
+    /// start with `^`; globs match at any depth. Returns false if the file could not be read.
+    pub fn add_hgignore_file(&mut self, hgignore: &Path) -> bool {
+        let Ok(contents) = fs::read_to_string(hgignore) else {
+            return false;
+        };
+        let prefix = self.prefix_for(hgignore.parent().unwrap_or(&self.base_dir));
+        let source = hgignore.display().to_string();
+        let mut syntax = "regexp";
+        for (i, line) in contents.lines().enumerate() {
+            // `#` starts a comment unless escaped as `\#`.
+            let uncommented = match line.match_indices('#').find(|(pos, _)| !line[..*pos].ends_with('\\')) {
+                Some((pos, _)) => &line[..pos],
+                None => line,
+            };
+            let pattern = uncommented.replace("\\#", "#");
+            let pattern = pattern.trim_end();
+            if pattern.trim_start().is_empty() {
+                continue;
+            }
+            if let Some(name) = pattern.strip_prefix("syntax:") {
+                syntax = match name.trim() {
+                    "re" | "regexp" => "regexp",
+                    "glob" => "glob",
+                    "rootglob" => "rootglob",
+                    _ => syntax,
+                };
+                continue;
+            }
+            let (kind, pattern) = [("re:", "regexp"), ("regexp:", "regexp"), ("glob:", "glob"), ("rootglob:", "rootglob")]
+                .iter()
+                .find_map(|(marker, kind)| pattern.strip_prefix(marker).map(|rest| (*kind, rest)))
+                .unwrap_or((syntax, pattern));
+            let regex = match kind {
+                "regexp" => match pattern.strip_prefix('^') {
+                    Some(anchored) => format!("^{}(?:{})", prefix, anchored),
+                    None => format!("^{}.*?(?:{})", prefix, pattern),
+                },
+                "glob" => format!("^{}(?:.*/)?{}$", prefix, glob_to_regex(pattern)),
+                _ => format!("^{}{}$", prefix, glob_to_regex(pattern)),
+            };
+            let pattern = line.trim().to_string();
+            self.push_rule(Rule { regex, is_negation: false, is_directory: false, pattern, source: source.clone(), line: i + 1 });
+        }
+        true
+    }
+
+    // The regex prefix that anchors patterns defined in `defined_in` to it.
+    fn prefix_for(&self, defined_in: &Path) -> String {
+        match defined_in.strip_prefix(&self.base_dir) {
+            Ok(relative) if relative.as_os_str().is_empty() => String::new(),
+            Ok(relative) => format!("{}/", regex::escape(&relative.to_string_lossy().replace('\\', "/"))),
+            // Patterns from outside the base directory can only sensibly be applied from the base.
+            Err(_) => String::new(),
+        }
+    }
+
+    /// Adds gitignore-syntax patterns that apply to paths under `defined_in`; `source` names where they came from.
+    pub fn add_patterns<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>, defined_in: &Path, source: &str) {
+        let prefix = self.prefix_for(defined_in);
+        for (i, line) in lines.into_iter().enumerate() {
+            if let Some(mut rule) = Rule::parse(line, &prefix) {
+                rule.source = source.to_string();
+                rule.line = i + 1;
+                self.push_rule(rule);
+            }
+        }
+    }
+
+    // Adds a rule whose regex compiles. One that does not is set aside for `take_invalid` to report, rather
+    // than failing the whole set (which would disable every rule).
+    fn push_rule(&mut self, rule: Rule) {
+        match Regex::new(&rule.regex) {
+            Ok(_) => {
+                self.rules.push(rule);
+                self.set = OnceLock::new();
+            }
+            Err(e) => self.invalid.push(InvalidPattern { pattern: rule.pattern, source: rule.source, line: rule.line, error: e.to_string() }),
+        }
+    }
+
+    /// The patterns left out since the last call because they are not valid, so callers can report them once.
+    pub fn take_invalid(&mut self) -> Vec<InvalidPattern> {
+        std::mem::take(&mut self.invalid)
+    }
+
+    /// Compiles the rules added so far, reporting a set that cannot be compiled (e.g. one over the regex
+    /// size limit). Matching compiles them too, but a matcher whose set failed to compile matches nothing.
+    pub fn build(&self) -> Result<(), String> {
+        self.compiled().map(|_| ())
+    }
+
+    fn compiled(&self) -> Result<&RegexSet, String> {
+        let set = self.set.get_or_init(|| {
+            let case = self.case;
+            let regexes = self.rules.iter().map(|rule| if case.folds(&rule.pattern) { format!("(?i:{})", rule.regex) } else { rule.regex.clone() });
+            RegexSet::new(regexes).map_err(|e| format!("{} ignore rules could not be compiled together: {}", self.rules.len(), e))
+        });
+        set.as_ref().map_err(String::clone)
+    }
+
+    /// Whether `relative_path` (relative to the base directory) is ignored.
+    pub fn is_ignored(&self, relative_path: &Path, is_dir: bool) -> bool {
+        self.explain(relative_path, is_dir).is_some_and(|decision| decision.ignored)
+    }
+
+    /// The rule deciding `relative_path`, if any matches. The last matching rule wins,
+    /// so a later negation re-includes a path.
+    pub fn explain(&self, relative_path: &Path, is_dir: bool) -> Option<Decision<'_>> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let set = self.compiled().ok()?;
+        let path_str = relative_path.to_string_lossy().replace('\\', "/");
+        set
+            .matches(&path_str)
+            .iter()
+            .rev()
+            .map(|index| &self.rules[index])
+            .find(|rule| is_dir || !rule.is_directory)
+            .map(|rule| Decision { ignored: !rule.is_negation, pattern: &rule.pattern, source: &rule.source, line: rule.line })
+    }
+}
+
+// Translates a .gitattributes line into an ignore pattern: `gen/** linguist-generated` becomes `gen/**`,
+// and `gen/keep.rs -linguist-generated` becomes `!gen/keep.rs`.
+fn attributes_line_to_pattern(line: &str) -> String {
+    let mut fields = line.split_whitespace();
+    let Some(pattern) = fields.next().filter(|pattern| !pattern.starts_with('#')) else {
+        return String::new();
+    };
+    let mut marked = None;
+    for attribute in fields {
+        let (name, set) = match attribute.split_once('=') {
+            Some((name, value)) => (name, value != "false"),
+            None => match attribute.strip_prefix(['-', '!']) {
+                Some(name) => (name, false),
+                None => (attribute, true),
+            },
+        };
+        if name == "linguist-generated" || name == "linguist-vendored" {
+            marked = Some(marked.unwrap_or(false) || set);
+        }
+    }
+    match marked {
+        Some(true) => pattern.to_string(),
+        Some(false) => format!("!{}", pattern),
+        None => String::new(),
+    }
+}
+
+/// Checks that a gitignore-syntax pattern translates to a valid rule (comments and blank lines are fine).
+pub fn check_pattern(line: &str) -> Result<(), String> {
+    match Rule::parse(line, "") {
+        Some(rule) => Regex::new(&rule.regex).map(|_| ()).map_err(|e| format!("invalid pattern '{}': {}", line, e)),
+        None => Ok(()),
+    }
+}
+
+impl Rule {
+    fn parse(line: &str, prefix: &str) -> Option<Rule> {
+        let raw = line.trim();
+        let mut pattern = line.trim_start();
+        // Trailing spaces are ignored unless escaped.
+        if !pattern.ends_with("\\ ") {
+            pattern = pattern.trim_end();
+        }
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return None;
+        }
+        let is_negation = pattern.starts_with('!');
+        if is_negation {
+            pattern = &pattern[1..];
+        }
+        let is_directory = pattern.ends_with('/');
+        if is_directory {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+        // A slash at the start or in the middle anchors the pattern to its directory.
+        let is_anchored = pattern.contains('/');
+        pattern = pattern.trim_start_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let body = glob_to_regex(pattern);
+        let regex = if is_anchored {
+            format!("^{}{}$", prefix, body)
+        } else {
+            format!("^{}(?:.*/)?{}$", prefix, body)
+        };
+        Some(Rule { regex, is_negation, is_directory, pattern: raw.to_string(), source: String::new(), line: 0 })
+    }
+}
+
+// Translates gitignore glob syntax (`*`, `?`, `**`, `[...]`, `\` escapes) into regex syntax.
+fn glob_to_regex(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::with_capacity(glob.len() * 2);
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let at_segment_start = i == 0 || chars[i - 1] == '/';
+                match chars.get(i + 2) {
+                    Some('/') if at_segment_start => {
+                        regex.push_str("(?:.*/)?");
+                        i += 3;
+                    }
+                    None if at_segment_start => {
+                        regex.push_str(".*");
+                        i += 2;
+                    }
+                    _ => {
+                        regex.push_str("[^/]*");
+                        i += 2;
+                    }
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let close = chars[i + 1..].iter().skip(1).position(|&c| c == ']').map(|p| i + 2 + p);
+                match close {
+                    Some(close) => {
+                        regex.push('[');
+                        let mut class = &chars[i + 1..close];
+                        if matches!(class.first(), Some('!') | Some('^')) {
+                            regex.push('^');
+                            class = &class[1..];
+                        }
+                        for &c in class {
+                            if c == '\\' || c == '[' || c == '&' || c == '~' {
+                                regex.push('\\');
+                            }
+                            regex.push(c);
+                        }
+                        regex.push(']');
+                        i = close + 1;
+                        continue;
+                    }
+                    None => regex.push_str("\\["),
+                }
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
@@ -0,0 +1,131 @@
+// Placeholder entries for image files, so the model knows an asset exists without receiving raw bytes.
+
+use std::path::Path;
+
+pub struct ImageInfo {
+    pub format: &'static str,
+    pub dimensions: Option<(u32, u32)>,
+}
+
+pub fn image_format(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("png"),
+        "jpg" | "jpeg" => Some("jpeg"),
+        "gif" => Some("gif"),
+        "bmp" => Some("bmp"),
+        "webp" => Some("webp"),
+        "ico" => Some("x-icon"),
+        _ => None,
+    }
+}
+
+pub fn inspect(bytes: &[u8], format: &'static str) -> ImageInfo {
+    let dimensions = match format {
+        "png" => png_dimensions(bytes),
+        "gif" => gif_dimensions(bytes),
+        "bmp" => bmp_dimensions(bytes),
+        "jpeg" => jpeg_dimensions(bytes),
+        "webp" => webp_dimensions(bytes),
+        _ => None,
+    };
+    ImageInfo { format, dimensions }
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn png_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"\x89PNG\r\n\x1a\n") || bytes.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some((be_u32(bytes, 16)?, be_u32(bytes, 20)?))
+}
+
+fn gif_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"GIF8") {
+        return None;
+    }
+    Some((le_u16(bytes, 6)?, le_u16(bytes, 8)?))
+}
+
+fn bmp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"BM") {
+        return None;
+    }
+    // Height is signed; negative values mean the rows are stored top-down.
+    let height = le_u32(bytes, 22)? as i32;
+    Some((le_u32(bytes, 18)?, height.unsigned_abs()))
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        let segment_len = be_u16(bytes, pos + 2)? as usize;
+        // SOF0..SOF15, excluding DHT (C4), JPG (C8) and DAC (CC), carry the frame size.
+        if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+            return Some((be_u16(bytes, pos + 7)?, be_u16(bytes, pos + 5)?));
+        }
+        pos += 2 + segment_len;
+    }
+    None
+}
+
+fn webp_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if !bytes.starts_with(b"RIFF") || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    match bytes.get(12..16)? {
+        b"VP8X" => {
+            // Canvas size is stored as two 24-bit little-endian values, minus one.
+            let size = bytes.get(24..30)?;
+            let width = u32::from_le_bytes([size[0], size[1], size[2], 0]) + 1;
+            let height = u32::from_le_bytes([size[3], size[4], size[5], 0]) + 1;
+            Some((width, height))
+        }
+        b"VP8 " => Some((le_u16(bytes, 26)? & 0x3FFF, le_u16(bytes, 28)? & 0x3FFF)),
+        b"VP8L" => {
+            let bits = le_u32(bytes, 21)?;
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        _ => None,
+    }
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        encoded.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        encoded.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        encoded.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        encoded.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+    encoded
+}
@@ -0,0 +1,216 @@
+// `toprompt import-config <file>`: translates a repomix.config.json or a code2prompt config (.code2promptrc,
+// .c2pconfig; TOML or JSON) into a `.toprompt.toml`. Include globs become a `pattern` regex, ignore globs become
+// `exclude` patterns (both tools use gitignore-style globs), and the output style maps to `format`. Settings
+// without a toprompt equivalent are kept as comments so nothing is dropped silently.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::config_file;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt import-config [--stdout] [--force] <repomix.config.json|.code2promptrc|.c2pconfig>");
+    eprintln!("  Writes {} in the current directory from another tool's include/exclude/format settings.", config_file::FILE_NAME);
+    eprintln!("  --stdout  Print the translated configuration instead of writing it.");
+    eprintln!("  --force   Overwrite an existing {}.", config_file::FILE_NAME);
+}
+
+// The translated settings, plus notes about what could not be carried over.
+#[derive(Default)]
+struct Imported {
+    gitignore: Option<bool>,
+    format: Option<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    notes: Vec<String>,
+}
+
+pub fn run(args: &[String]) {
+    let mut to_stdout = false;
+    let mut force = false;
+    let mut source = None;
+    for arg in args {
+        match arg.as_str() {
+            "--stdout" => to_stdout = true,
+            "--force" => force = true,
+            _ if arg.starts_with('-') || source.is_some() => {
+                eprintln!("Unknown argument for import-config: {}", arg);
+                print_usage();
+                std::process::exit(crate::EXIT_ERROR);
+            }
+            _ => source = Some(arg.clone()),
+        }
+    }
+    let Some(source) = source else {
+        print_usage();
+        std::process::exit(crate::EXIT_ERROR);
+    };
+
+    let imported = match load(Path::new(&source)) {
+        Ok(imported) => imported,
+        Err(e) => {
+            eprintln!("Error: could not import {}: {}", source, e);
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    };
+    let text = render(&imported, &source);
+    // Catch anything the translation got wrong before it is written.
+    if let Err(e) = toml::from_str::<config_file::Settings>(&text) {
+        eprintln!("Error: the translated configuration is invalid: {}", e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    if to_stdout {
+        print!("{}", text);
+        return;
+    }
+    let path = Path::new(config_file::FILE_NAME);
+    if path.exists() && !force {
+        eprintln!("{} already exists; use --force to overwrite it (or --stdout to print the translation).", config_file::FILE_NAME);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    if let Err(e) = fs::write(path, text) {
+        eprintln!("Error: could not write {}: {}", config_file::FILE_NAME, e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    println!(":: Wrote {} from {} ::", config_file::FILE_NAME, source);
+    for note in &imported.notes {
+        println!("Not translated: {}", note);
+    }
+}
+
+fn load(path: &Path) -> Result<Imported, Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    if name.contains("repomix") {
+        return Ok(repomix(&serde_json::from_str(&contents)?));
+    }
+    // code2prompt configs are TOML; older setups kept JSON in .code2promptrc.
+    let value = match contents.parse::<toml::Table>() {
+        Ok(table) => serde_json::to_value(table)?,
+        Err(toml_error) => serde_json::from_str(&contents).map_err(|_| format!("neither TOML nor JSON: {}", toml_error))?,
+    };
+    Ok(code2prompt(&value))
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        // Both tools also accept comma-separated strings on the command line, and some configs copy that.
+        Some(Value::String(list)) => list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn repomix(config: &Value) -> Imported {
+    let mut imported = Imported { include: strings(config.get("include")), ..Imported::default() };
+    if let Some(ignore) = config.get("ignore") {
+        imported.gitignore = ignore.get("useGitignore").and_then(Value::as_bool);
+        imported.exclude = strings(ignore.get("customPatterns"));
+        if ignore.get("useDefaultPatterns").and_then(Value::as_bool) == Some(false) {
+            imported.notes.push("ignore.useDefaultPatterns = false (toprompt's -i defaults and lockfile/minified skipping still apply; see --no-auto-skip)".to_string());
+        }
+    }
+    if let Some(output) = config.get("output").and_then(Value::as_object) {
+        for (key, value) in output {
+            match (key.as_str(), value) {
+                // Repomix's XML style is what --format repomix reproduces.
+                ("style", Value::String(style)) if style == "xml" => imported.format = Some("repomix".to_string()),
+                ("style", Value::String(style)) if style == "markdown" => imported.format = Some("markdown".to_string()),
+                ("filePath", Value::String(file)) => imported.notes.push(format!("output.filePath = \"{}\" (pass -o {})", file, file)),
+                _ => imported.notes.push(format!("output.{} = {}", key, value)),
+            }
+        }
+    }
+    imported
+}
+
+fn code2prompt(config: &Value) -> Imported {
+    let mut imported = Imported::default();
+    let Some(config) = config.as_object() else {
+        imported.notes.push("the file is not a table of settings".to_string());
+        return imported;
+    };
+    for (key, value) in config {
+        match key.as_str() {
+            "include_patterns" | "include" => imported.include.extend(strings(Some(value))),
+            "exclude_patterns" | "exclude" => imported.exclude.extend(strings(Some(value))),
+            "no_ignore" => imported.gitignore = value.as_bool().map(|no_ignore| !no_ignore),
+            // code2prompt's default template is what --format code2prompt reproduces.
+            "output_format" => match value.as_str() {
+                Some("markdown") => imported.format = Some("code2prompt".to_string()),
+                Some("xml") => imported.format = Some("xml".to_string()),
+                _ => imported.notes.push(format!("{} = {}", key, value)),
+            },
+            _ => imported.notes.push(format!("{} = {}", key, value)),
+        }
+    }
+    imported
+}
+
+// Translates gitignore-style globs into one regex over relative paths (`-R` syntax).
+fn globs_to_regex(globs: &[String]) -> String {
+    let alternatives: Vec<String> = globs.iter().map(|glob| glob_to_regex(glob)).collect();
+    if alternatives.len() == 1 { alternatives[0].clone() } else { format!("({})", alternatives.join("|")) }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let glob = glob.trim_start_matches("./").trim_start_matches('/');
+    // Like gitignore, a pattern without a slash matches at any depth.
+    let mut regex = if glob.contains('/') { "^".to_string() } else { "(^|/)".to_string() };
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '{' => regex.push('('),
+            '}' => regex.push(')'),
+            ',' => regex.push('|'),
+            _ => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    // A directory pattern (or one naming a directory) also covers everything below it.
+    regex.push_str("(/|$)");
+    regex
+}
+
+fn render(imported: &Imported, source: &str) -> String {
+    let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+    let mut out = String::new();
+    out.push_str(&format!("# toprompt project configuration, imported from {} (see `toprompt --help`).\n", source));
+    out.push_str("recursive = true\n");
+    if let Some(gitignore) = imported.gitignore {
+        out.push_str(&format!("gitignore = {}\n", gitignore));
+    }
+    if let Some(format) = &imported.format {
+        out.push_str(&format!("format = {}\n", quote(format)));
+    }
+    // "Everything" needs no pattern.
+    let include: Vec<String> = imported.include.iter().filter(|glob| !matches!(glob.as_str(), "**" | "**/*" | "*")).cloned().collect();
+    if !include.is_empty() {
+        out.push_str(&format!("# From the include globs: {}\n", include.join(", ")));
+        out.push_str(&format!("pattern = {}\n", quote(&globs_to_regex(&include))));
+    }
+    if !imported.exclude.is_empty() {
+        let exclude: Vec<String> = imported.exclude.iter().map(|pattern| quote(pattern)).collect();
+        out.push_str("# Gitignore-syntax patterns that are never bundled.\n");
+        out.push_str(&format!("exclude = [{}]\n", exclude.join(", ")));
+    }
+    if !imported.notes.is_empty() {
+        out.push_str("\n# Settings without a toprompt equivalent:\n");
+        for note in &imported.notes {
+            out.push_str(&format!("#   {}\n", note));
+        }
+    }
+    out
+}
@@ -0,0 +1,152 @@
+// Import/use/include discovery for --expand-imports. Only local files are resolved;
+// anything that looks like a third-party package is ignored.
+
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use regex::Regex;
+
+static RUST_MOD: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+([A-Za-z_][A-Za-z0-9_]*)\s*;").unwrap());
+static RUST_USE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+((?:crate|super|self)(?:::[A-Za-z_][A-Za-z0-9_]*)+)").unwrap());
+static PYTHON_IMPORT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?m)^\s*(?:from\s+(\.*[A-Za-z0-9_.]*)\s+import\s+([A-Za-z0-9_, ]+)|import\s+([A-Za-z0-9_.]+))").unwrap());
+static JS_IMPORT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?:from\s+|import\s*\(?\s*|require\s*\(\s*)["'](\.{1,2}/[^"']+)["']"#).unwrap());
+static C_INCLUDE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?m)^\s*#\s*include\s+"([^"]+)""#).unwrap());
+
+const JS_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs", "vue", "svelte"];
+
+// Returns the existing local files that `path` imports, in the order they are referenced.
+pub fn local_imports(path: &Path, contents: &str) -> Vec<PathBuf> {
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let mut found = Vec::new();
+    match ext {
+        "rs" => rust_imports(path, dir, contents, &mut found),
+        "py" => python_imports(dir, contents, &mut found),
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" | "vue" | "svelte" => {
+            for cap in JS_IMPORT.captures_iter(contents) {
+                push_first_existing(&mut found, js_candidates(&dir.join(&cap[1])));
+            }
+        }
+        "c" | "h" | "cpp" | "cc" | "cxx" | "hpp" | "m" | "mm" => {
+            for cap in C_INCLUDE.captures_iter(contents) {
+                push_first_existing(&mut found, vec![dir.join(&cap[1])]);
+            }
+        }
+        _ => {}
+    }
+    let mut unique = Vec::with_capacity(found.len());
+    for import in found {
+        if !unique.contains(&import) {
+            unique.push(import);
+        }
+    }
+    unique
+}
+
+fn push_first_existing(found: &mut Vec<PathBuf>, candidates: Vec<PathBuf>) {
+    if let Some(existing) = candidates.into_iter().find(|c| c.is_file()) {
+        found.push(existing);
+    }
+}
+
+// Directory that holds the children of the module defined by `path` (foo.rs -> foo/, mod.rs -> ./).
+fn rust_module_dir(path: &Path, dir: &Path) -> PathBuf {
+    match path.file_stem().and_then(|s| s.to_str()) {
+        Some("main") | Some("lib") | Some("mod") | None => dir.to_path_buf(),
+        Some(stem) => dir.join(stem),
+    }
+}
+
+fn rust_crate_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join("lib.rs").is_file() || d.join("main.rs").is_file())
+        .map(Path::to_path_buf)
+}
+
+fn rust_module_candidates(base: &Path, segments: &[&str]) -> Vec<PathBuf> {
+    // `use crate::a::b::Item` may name a module `a/b` or an item inside `a`, so try the longest path first.
+    let mut candidates = Vec::new();
+    for len in (1..=segments.len()).rev() {
+        let module_path: PathBuf = segments[..len].iter().collect();
+        candidates.push(base.join(&module_path).with_extension("rs"));
+        candidates.push(base.join(&module_path).join("mod.rs"));
+    }
+    candidates
+}
+
+fn rust_imports(path: &Path, dir: &Path, contents: &str, found: &mut Vec<PathBuf>) {
+    let module_dir = rust_module_dir(path, dir);
+    for cap in RUST_MOD.captures_iter(contents) {
+        push_first_existing(found, rust_module_candidates(&module_dir, &[&cap[1]]));
+    }
+    for cap in RUST_USE.captures_iter(contents) {
+        let mut segments: Vec<&str> = cap[1].split("::").collect();
+        let base = match segments.remove(0) {
+            "crate" => rust_crate_root(dir),
+            "self" => Some(module_dir.clone()),
+            _ => {
+                let mut base = module_dir.parent().map(Path::to_path_buf);
+                while segments.first() == Some(&"super") {
+                    segments.remove(0);
+                    base = base.and_then(|b| b.parent().map(Path::to_path_buf));
+                }
+                base
+            }
+        };
+        if let Some(base) = base
+            && !segments.is_empty()
+        {
+            push_first_existing(found, rust_module_candidates(&base, &segments));
+        }
+    }
+}
+
+fn python_imports(dir: &Path, contents: &str, found: &mut Vec<PathBuf>) {
+    for cap in PYTHON_IMPORT.captures_iter(contents) {
+        if let Some(module) = cap.get(3) {
+            push_first_existing(found, python_candidates(dir, module.as_str()));
+            continue;
+        }
+        let module = cap.get(1).map_or("", |m| m.as_str());
+        let dots = module.chars().take_while(|&c| c == '.').count();
+        let mut base = dir.to_path_buf();
+        for _ in 1..dots {
+            base = base.parent().map(Path::to_path_buf).unwrap_or(base);
+        }
+        let name = &module[dots..];
+        if name.is_empty() {
+            // `from . import a, b` imports sibling modules.
+            for imported in cap[2].split(',') {
+                push_first_existing(found, python_candidates(&base, imported.trim()));
+            }
+        } else {
+            push_first_existing(found, python_candidates(&base, name));
+        }
+    }
+}
+
+fn python_candidates(base: &Path, module: &str) -> Vec<PathBuf> {
+    let module_path: PathBuf = module.split('.').collect();
+    let mut candidates = vec![base.join(&module_path).with_extension("py"), base.join(&module_path).join("__init__.py")];
+    // Absolute imports are usually relative to the project root, i.e. the working directory.
+    if let Ok(cwd) = std::env::current_dir() {
+        candidates.push(cwd.join(&module_path).with_extension("py"));
+        candidates.push(cwd.join(&module_path).join("__init__.py"));
+    }
+    candidates
+}
+
+fn js_candidates(specifier: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![specifier.to_path_buf()];
+    for ext in JS_EXTENSIONS {
+        let mut with_ext = specifier.as_os_str().to_owned();
+        with_ext.push(".");
+        with_ext.push(ext);
+        candidates.push(PathBuf::from(with_ext));
+    }
+    for ext in JS_EXTENSIONS {
+        candidates.push(specifier.join(format!("index.{}", ext)));
+    }
+    candidates
+}
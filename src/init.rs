@@ -0,0 +1,180 @@
+// `toprompt init`: asks a few questions and writes a `.toprompt.toml` for the project, so a team can commit
+// shared defaults (ignores, format, token budget, profiles).
+
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use crate::config_file;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt init [--defaults] [--force]");
+    eprintln!("  Interactively creates {} in the current directory.", config_file::FILE_NAME);
+    eprintln!("  --defaults  Don't ask; write the suggested answers.");
+    eprintln!("  --force     Overwrite an existing {}.", config_file::FILE_NAME);
+}
+
+struct Answers {
+    gitignore: bool,
+    recursive: bool,
+    exclude: Vec<String>,
+    format: String,
+    max_total_tokens: Option<usize>,
+    // (name, -R pattern) pairs.
+    profiles: Vec<(String, String)>,
+}
+
+pub fn run(args: &[String]) {
+    let mut use_defaults = false;
+    let mut force = false;
+    for arg in args {
+        match arg.as_str() {
+            "--defaults" => use_defaults = true,
+            "--force" => force = true,
+            _ => {
+                eprintln!("Unknown argument for init: {}", arg);
+                print_usage();
+                std::process::exit(crate::EXIT_ERROR);
+            }
+        }
+    }
+    let path = Path::new(config_file::FILE_NAME);
+    let interactive = !use_defaults && io::stdin().is_terminal();
+    if path.exists() && !force && !(interactive && confirm(&format!("{} already exists. Overwrite it?", config_file::FILE_NAME), false)) {
+        eprintln!("{} already exists; use --force to overwrite it.", config_file::FILE_NAME);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+
+    let suggested_excludes = suggested_excludes(Path::new("."));
+    let answers = if interactive {
+        ask(&suggested_excludes)
+    } else {
+        Answers {
+            gitignore: true,
+            recursive: true,
+            exclude: suggested_excludes,
+            format: "markdown".to_string(),
+            max_total_tokens: None,
+            profiles: Vec::new(),
+        }
+    };
+
+    if let Err(e) = fs::write(path, render(&answers)) {
+        eprintln!("Error: could not write {}: {}", config_file::FILE_NAME, e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    println!(":: Wrote {} ::", config_file::FILE_NAME);
+    println!("Commit it to share these defaults; pass --no-config to ignore it for a run.");
+}
+
+// Build output and dependency directories for the ecosystems detected in `dir`.
+fn suggested_excludes(dir: &Path) -> Vec<String> {
+    let markers: [(&str, &[&str]); 5] = [
+        ("Cargo.toml", &["target/"]),
+        ("package.json", &["node_modules/", "dist/", "*.min.js"]),
+        ("pyproject.toml", &["__pycache__/", ".venv/", "*.egg-info/"]),
+        ("go.mod", &["vendor/"]),
+        ("pom.xml", &["target/"]),
+    ];
+    let mut excludes: Vec<String> = Vec::new();
+    for (marker, patterns) in markers {
+        if dir.join(marker).exists() {
+            for pattern in patterns {
+                if !excludes.iter().any(|existing| existing == pattern) {
+                    excludes.push(pattern.to_string());
+                }
+            }
+        }
+    }
+    excludes
+}
+
+fn ask(suggested_excludes: &[String]) -> Answers {
+    let gitignore = confirm("Apply .gitignore rules by default (-i)?", true);
+    let recursive = confirm("Recurse into subdirectories by default (-r)?", true);
+    let exclude = prompt("Patterns to always exclude (comma-separated, gitignore syntax)", &suggested_excludes.join(", "))
+        .split(',')
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    let format = loop {
+        let format = prompt(&format!("Output format ({})", crate::output::FORMATS.join("/")), "markdown");
+        if crate::output::FORMATS.contains(&format.as_str()) {
+            break format;
+        }
+        eprintln!("Please answer one of {}.", crate::output::FORMATS.join(", "));
+    };
+    let max_total_tokens = loop {
+        let budget = prompt("Token budget per bundle (empty for none)", "");
+        if budget.is_empty() {
+            break None;
+        }
+        match budget.parse() {
+            Ok(tokens) => break Some(tokens),
+            Err(_) => eprintln!("Please enter a number of tokens."),
+        }
+    };
+    let mut profiles = Vec::new();
+    loop {
+        let name = prompt("Add a profile? Name (empty to finish)", "");
+        if name.is_empty() {
+            break;
+        }
+        let pattern = loop {
+            let pattern = prompt(&format!("Regex of files included by '{}' (like -R)", name), "");
+            match regex::Regex::new(&pattern) {
+                Ok(_) if !pattern.is_empty() => break pattern,
+                Ok(_) => eprintln!("Please enter a pattern."),
+                Err(e) => eprintln!("Invalid regex: {}", e),
+            }
+        };
+        profiles.push((name, pattern));
+    }
+    Answers { gitignore, recursive, exclude, format, max_total_tokens, profiles }
+}
+
+fn prompt(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+        // EOF: take the default rather than looping forever.
+        println!();
+        return default.to_string();
+    }
+    let answer = answer.trim();
+    if answer.is_empty() { default.to_string() } else { answer.to_string() }
+}
+
+fn confirm(question: &str, default: bool) -> bool {
+    let answer = prompt(&format!("{} (y/n)", question), if default { "y" } else { "n" });
+    answer.to_lowercase().starts_with('y')
+}
+
+fn render(answers: &Answers) -> String {
+    let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+    let mut out = String::new();
+    out.push_str("# toprompt project configuration (see `toprompt --help`). Flags on the command line still apply on top.\n");
+    out.push_str(&format!("gitignore = {}\n", answers.gitignore));
+    out.push_str(&format!("recursive = {}\n", answers.recursive));
+    out.push_str(&format!("format = {}\n", quote(&answers.format)));
+    let exclude: Vec<String> = answers.exclude.iter().map(|pattern| quote(pattern)).collect();
+    out.push_str("# Gitignore-syntax patterns that are never bundled.\n");
+    out.push_str(&format!("exclude = [{}]\n", exclude.join(", ")));
+    match answers.max_total_tokens {
+        Some(tokens) => out.push_str(&format!("max_total_tokens = {}\n", tokens)),
+        None => out.push_str("# max_total_tokens = 100000\n"),
+    }
+    if answers.profiles.is_empty() {
+        out.push_str("\n# Profiles are selected with --profile <name> and override the settings above.\n");
+        out.push_str("# [profiles.tests]\n# pattern = \"(^|/)tests?/\"\n");
+    }
+    for (name, pattern) in &answers.profiles {
+        out.push_str(&format!("\n[profiles.{}]\npattern = {}\n", quote(name), quote(pattern)));
+    }
+    out
+}
@@ -0,0 +1,3 @@
+// The parts of toprompt usable on their own by other tools; the command-line program is src/main.rs.
+
+pub mod ignore;
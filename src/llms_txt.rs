@@ -0,0 +1,233 @@
+// `toprompt llms-txt`: writes an llms.txt index of a project (https://llmstxt.org): the project name, a one-line
+// summary, and a link with a short description for every file the usual traversal and filters select.
+// With --full it writes llms-full.txt instead, which carries the files' contents after the same header.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+use crate::bundle::{Bundle, BundleEntry};
+use crate::context;
+
+// Longest per-file description, in characters.
+const MAX_NOTE: usize = 120;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt llms-txt [--full] [--stdout] [--base-url <url>] [--no-gitignore] [-R <pattern>] [--profile <name> | --no-config] [<dir>]");
+    eprintln!("  Writes llms.txt in <dir> (default: the current directory): the project name and summary, then a");
+    eprintln!("  link and short description for each file, grouped by top-level directory. Files are selected like");
+    eprintln!("  `toprompt -ri <dir>` (including .toprompt.toml defaults).");
+    eprintln!("  --full            Write llms-full.txt, with the contents of every file, instead.");
+    eprintln!("  --stdout          Print the result instead of writing the file.");
+    eprintln!("  --base-url <url>  Prefix the links with <url> (e.g. the repository's raw file URL); links are relative otherwise.");
+    eprintln!("  --no-gitignore    Don't apply .gitignore/.hgignore files.");
+    eprintln!("  -R <pattern>      Only list files whose relative path matches the regex.");
+}
+
+pub fn run(args: &[String]) {
+    let mut config = Config::default();
+    crate::apply_config_file(&mut config, args);
+    config.recursive = true;
+    config.use_gitignore = true;
+
+    let mut full = false;
+    let mut to_stdout = false;
+    let mut base_url = String::new();
+    let mut dir = None;
+    let mut iter = args.iter().cloned();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--full" => full = true,
+            "--stdout" => to_stdout = true,
+            "--base-url" => base_url = crate::next_value(&mut iter, "--base-url"),
+            "--no-gitignore" => config.use_gitignore = false,
+            "-R" => config.regex_pattern = Some(crate::next_value(&mut iter, "-R")),
+            // Handled by apply_config_file.
+            "--profile" => {
+                iter.next();
+            }
+            "--no-config" => {}
+            "-h" | "--help" => {
+                print_usage();
+                return;
+            }
+            _ if arg.starts_with('-') || dir.is_some() => {
+                eprintln!("Unknown argument for llms-txt: {}", arg);
+                print_usage();
+                std::process::exit(crate::EXIT_ERROR);
+            }
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.unwrap_or_else(|| ".".to_string());
+    let root = match fs::canonicalize(&dir) {
+        Ok(root) if root.is_dir() => root,
+        _ => {
+            eprintln!("Error: '{}' is not a directory.", dir);
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    };
+    let compiled_regex = match &config.regex_pattern {
+        Some(pattern) => match config.case.regex(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Error: Invalid regex pattern '{}': {}", pattern, e);
+                std::process::exit(crate::EXIT_INVALID_FILTER);
+            }
+        },
+        None => None,
+    };
+
+    let mut bundle = Bundle::new();
+    if let Err(e) = crate::process_path(&dir, &mut bundle, &config, &compiled_regex) {
+        eprintln!("Error: {}", e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    let file_name = if full { "llms-full.txt" } else { "llms.txt" };
+    // A previously generated index is not part of the project.
+    bundle.entries.retain(|entry| {
+        entry.path.as_deref().and_then(Path::file_name).is_none_or(|name| name != "llms.txt" && name != "llms-full.txt")
+    });
+    if bundle.entries.is_empty() {
+        eprintln!("No files to list in '{}'.", dir);
+        std::process::exit(crate::EXIT_NOTHING_MATCHED);
+    }
+
+    let text = render(&bundle, &root, &base_url, full);
+    if to_stdout {
+        // A closed pipe (e.g. `| head`) is not an error worth reporting.
+        let _ = io::stdout().write_all(text.as_bytes());
+        return;
+    }
+    let out_path = root.join(file_name);
+    if let Err(e) = fs::write(&out_path, &text) {
+        eprintln!("Error: could not write {}: {}", out_path.display(), e);
+        std::process::exit(crate::EXIT_ERROR);
+    }
+    println!(":: Wrote {} ({} files, ~{} tokens) ::", crate::display_path(&out_path), bundle.file_count(), crate::tokens::estimate_tokens(&text));
+}
+
+fn render(bundle: &Bundle, root: &Path, base_url: &str, full: bool) -> String {
+    let mut out = format!("# {}\n", context::project_name(root));
+    if let Some(summary) = readme_summary(root) {
+        out.push_str(&format!("\n> {}\n", summary));
+    }
+
+    if full {
+        for entry in &bundle.entries {
+            out.push_str(&format!("\n{}\n", entry.segment.trim_end()));
+        }
+        return out;
+    }
+
+    // Documentation first, then one section per top-level directory, with the root's own files under "Files".
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in &bundle.entries {
+        let relative = relative_path(entry, root);
+        let section = if is_doc(&relative) {
+            "Docs".to_string()
+        } else {
+            match relative.split_once('/') {
+                Some((top, _)) => top.to_string(),
+                None => "Files".to_string(),
+            }
+        };
+        let mut item = format!("- [{}]({}{})", relative, base_url, relative);
+        if let Some(note) = describe(entry, &relative) {
+            item.push_str(&format!(": {}", note));
+        }
+        match sections.iter_mut().find(|(name, _)| *name == section) {
+            Some((_, items)) => items.push(item),
+            None => sections.push((section, vec![item])),
+        }
+    }
+    sections.sort_by_key(|(name, _)| match name.as_str() {
+        "Docs" => 0,
+        "Files" => 1,
+        _ => 2,
+    });
+    for (name, items) in sections {
+        out.push_str(&format!("\n## {}\n\n{}\n", name, items.join("\n")));
+    }
+    out
+}
+
+// The entry's path relative to the listed directory, with forward slashes.
+fn relative_path(entry: &BundleEntry, root: &Path) -> String {
+    let path: PathBuf = entry.path.clone().unwrap_or_else(|| PathBuf::from(&entry.display_name));
+    path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/")
+}
+
+fn is_doc(relative: &str) -> bool {
+    let lower = relative.to_lowercase();
+    lower.ends_with(".md") || lower.ends_with(".mdx") || lower.ends_with(".rst") || lower.starts_with("docs/")
+}
+
+// The first paragraph of the README at `root`, skipping headings, badges and HTML.
+fn readme_summary(root: &Path) -> Option<String> {
+    let readme = fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && crate::is_readme(path))
+        .min()?;
+    let contents = fs::read_to_string(readme).ok()?;
+    let paragraph: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty() || line.starts_with('#') || line.starts_with('[') || line.starts_with('!') || line.starts_with('<') || line.starts_with("==="))
+        .take_while(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("```"))
+        .collect();
+    (!paragraph.is_empty()).then(|| shorten(&paragraph.join(" "), 300))
+}
+
+// A short description of a file: the first heading of a document, or the first sentence of a source file's
+// leading comment or docstring.
+fn describe(entry: &BundleEntry, relative: &str) -> Option<String> {
+    if is_doc(relative) {
+        let mut in_fence = false;
+        let heading = entry
+            .contents
+            .lines()
+            .filter(|line| {
+                in_fence ^= line.trim_start().starts_with("```");
+                !in_fence
+            })
+            .find_map(|line| line.strip_prefix("# "))
+            .map(str::trim);
+        return heading.filter(|heading| !heading.is_empty()).map(|heading| shorten(heading, MAX_NOTE));
+    }
+    let mut comment = Vec::new();
+    for line in entry.contents.lines().map(str::trim) {
+        if line.starts_with("#!") || (comment.is_empty() && line.is_empty()) {
+            continue;
+        }
+        let text = ["//!", "///", "//", "/**", "/*", "*/", "*", "# ", "--", ";;", "\"\"\"", "'''"]
+            .iter()
+            .find_map(|opener| line.strip_prefix(opener))
+            .or((line == "#").then_some(""));
+        let Some(text) = text else { break };
+        let text = text.trim().trim_end_matches("*/").trim_end_matches("\"\"\"").trim_end_matches("'''").trim();
+        if text.is_empty() {
+            if comment.is_empty() {
+                continue;
+            }
+            break;
+        }
+        comment.push(text);
+    }
+    let comment = comment.join(" ");
+    let sentence = match comment.find(". ") {
+        Some(end) => &comment[..=end],
+        None => comment.as_str(),
+    };
+    (!sentence.is_empty()).then(|| shorten(sentence.trim(), MAX_NOTE))
+}
+
+fn shorten(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max_chars - 3).collect();
+    format!("{}...", cut.trim_end())
+}
@@ -1,29 +1,490 @@
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
 use regex::Regex;
+use toprompt::ignore;
+use bundle::{Bundle, BundleEntry};
+use output::Destination;
+
+mod budget;
+mod bundle;
+mod cache;
+mod clipboard;
+mod codeowners;
+mod config_command;
+mod config_file;
+mod context;
+mod daemon;
+mod deps;
+mod doctor;
+mod editor;
+mod filter;
+mod git;
+mod hot;
+mod html;
+mod image;
+mod import_config;
+mod imports;
+mod init;
+mod llms_txt;
+mod manifest;
+mod output;
+mod plugin;
+mod pr;
+mod preview;
+mod pseudonym;
+mod rank;
+mod remote;
+mod rich;
+mod serve;
+mod slots;
+mod symbols;
+mod test_files;
+mod timings;
+mod todos;
+mod token_tree;
+mod tokens;
+mod transform;
+mod web;
+mod workspace;
+
+// Which files --sample keeps in each directory.
+#[derive(Clone, Copy)]
+enum SampleMode {
+    // The first N by name.
+    First,
+    Largest,
+    Smallest,
+}
+
+impl SampleMode {
+    fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "first" => Some(SampleMode::First),
+            "largest" => Some(SampleMode::Largest),
+            "smallest" => Some(SampleMode::Smallest),
+            _ => None,
+        }
+    }
+}
+
+// What --max-files does when more files are selected.
+#[derive(Clone, Copy, PartialEq)]
+enum OverflowPolicy {
+    Error,
+    // Ask on a terminal; an error otherwise.
+    Prompt,
+    KeepLargest,
+    // The first N in bundle order, i.e. the order of the paths on the command line.
+    KeepByArgOrder,
+}
+
+impl OverflowPolicy {
+    fn parse(policy: &str) -> Option<Self> {
+        match policy {
+            "error" => Some(OverflowPolicy::Error),
+            "prompt" => Some(OverflowPolicy::Prompt),
+            "keep-largest" => Some(OverflowPolicy::KeepLargest),
+            "keep-by-arg-order" => Some(OverflowPolicy::KeepByArgOrder),
+            _ => None,
+        }
+    }
+}
 
 struct Config {
     use_gitignore: bool,
     verbose: bool,
     recursive: bool,
     regex_pattern: Option<String>,
+    // --filter expression; files it rejects are skipped like -R mismatches.
+    filter: Option<filter::Filter>,
+    // --lang: keep only files in these languages (fence names, see detect_language).
+    languages: Vec<String>,
+    // Case sensitivity of -R, --filter and ignore patterns (--ignore-case, --smart-case).
+    case: ignore::Case,
+    // Only files this CODEOWNERS owner (@user, @org/team or email) owns.
+    owner: Option<String>,
     use_xml: bool,
+    // Another tool's layout (--format aider|repomix|code2prompt) instead of toprompt's own.
+    preset: Option<output::Preset>,
+    dedup_content: bool,
+    // --bare: a single file is copied as its raw contents, without the path header and fences.
+    bare: bool,
+    // Markdown layout: text between sections, before each path, and the code fence (``` or ~~~).
+    separator: String,
+    header_prefix: String,
+    fence: String,
+    normalize: bool,
+    // --replace rules (and the config file's `replace`), applied to contents first.
+    rewrites: Vec<transform::Rewrite>,
+    // --strip-imports: collapse import/use/include blocks into a one-line marker.
+    strip_imports: bool,
+    // --minify-data: compact the whitespace of JSON, XML and YAML files.
+    minify_data: bool,
+    // --pipe-through commands, run on the raw contents before everything else.
+    pipe_through: Vec<plugin::External>,
+    // --transform-plugin stages (and the config file's), run in order after the built-in transforms.
+    transform_plugins: Vec<Box<dyn plugin::Transform>>,
+    // --format-plugin: lays out each file instead of the markdown/XML/preset formats.
+    formatter: Option<Box<dyn plugin::Formatter>>,
+    tab_width: usize,
+    max_line_length: Option<usize>,
+    max_lines: Option<(usize, transform::LineLimitMode)>,
+    // Keep only the N files with the most (or most recent) git activity.
+    hot: Option<(usize, hot::HotMode)>,
+    tail_logs: Option<usize>,
+    // Directories with more eligible files than this are listed instead of bundled.
+    summarize_over: Option<usize>,
+    // Keep at most N files per directory (--sample N[:first|largest|smallest]).
+    sample: Option<(usize, SampleMode)>,
+    table_preview: Option<usize>,
+    embed_images: bool,
+    expand_imports: Option<usize>,
+    with_tests: bool,
+    // --no-tests: skip test directories and files (see test_files::test_reason).
+    no_tests: bool,
+    // Append the TODO/FIXME/HACK comments found in the included files.
+    todos: bool,
+    // Prepend a summary of the package manifests at the given directories.
+    deps: bool,
+    // Print the bundled files as a tree with cumulative token counts.
+    token_tree: bool,
+    // Shrink the biggest files step by step when the bundle is over the size/token limit.
+    fit: bool,
+    // With -R, outline the files it does not select instead of leaving them out.
+    tiered: bool,
+    // Append the top-level symbols of the included (and summarized) files with path:line references.
+    symbol_index: bool,
+    // Put each directory's README first among its entries.
+    readmes_first: bool,
+    // Keep the files in the order of the path arguments even when --query or --hot select them.
+    keep_arg_order: bool,
+    // The config file's `priority` globs: matching files go first and survive --budget, --fit and --max-files.
+    priority: Vec<config_file::Glob>,
+    // Prepend a project overview (name, languages, totals, git state, command line).
+    context_header: bool,
+    // Append a footer with the time, version, commit and command line (--stamp).
+    stamp: bool,
+    // End the bundle with the SHA-256 of its content and report it (--checksum).
+    checksum: bool,
+    query: Option<String>,
+    top: usize,
+    top_tokens: Option<usize>,
+    changed_since_last: bool,
+    compare: Option<String>,
+    // Prefix each line with the short hash and author of its last change (git blame).
+    blame: bool,
+    // Read files as of this git revision instead of the working tree.
+    at: Option<String>,
+    no_cache: bool,
+    // Clone remote repositories again instead of reusing (and updating) the cached clones.
+    fresh_clone: bool,
+    edit: bool,
+    // --escape json: copy the bundle as one escaped string.
+    escape: Option<output::Escape>,
+    preview: Option<preview::PreviewMode>,
+    porcelain: bool,
+    trace_filters: bool,
+    // Append a section naming the paths that were filtered out.
+    list_excluded: bool,
+    // Replace the home directory, user name and host name in paths with placeholders (resolved once, by the flag).
+    anonymize_paths: Option<Anonymizer>,
+    // --pseudonymize, and the patterns naming what it renames (--pseudonymize-pattern, pseudonymize_patterns).
+    pseudonymize: bool,
+    pseudonym_patterns: Vec<Regex>,
+    // Canonical path whose filtering decisions are reported (--why).
+    why: Option<PathBuf>,
+    preview_length: preview::PreviewLength,
+    preview_mode: transform::LineLimitMode,
+    // Where the bundle is written (--clipboard, --stdout, -o), in the order given; the clipboard when none is.
+    destinations: Vec<Destination>,
+    // Also put an HTML rendering on the clipboard (--rich; macOS and Windows).
+    rich: bool,
+    // Also save the finished bundle under this name for `toprompt recopy` (--slot).
+    slot: Option<String>,
+    // Also write each top-level directory's (or workspace crate's) files to their own bundle here (--per-dir-output).
+    per_dir_output: Option<PathBuf>,
+    // Member crates of the enclosing Cargo workspace whose sources are bundled (--crate).
+    crates: Vec<String>,
+    // With several directory arguments, start each root's files with a section header (--root-headers).
+    root_headers: bool,
+    // Report the wall time of each phase of the run on stderr (--timings).
+    timings: bool,
+    max_total_size: Option<u64>,
+    // The config file's [rules."<glob>"] tables.
+    file_rules: Vec<config_file::FileRule>,
+    // --max-files N[:policy]
+    max_files: Option<(usize, OverflowPolicy)>,
+    // Files outside these sizes are skipped (--min-file-size, --max-file-size, --size MIN..MAX).
+    min_file_size: Option<u64>,
+    max_file_size: Option<u64>,
+    max_total_tokens: Option<usize>,
+    // Shares of --max-total-tokens per path prefix (--budget src/=60%,tests/=20%).
+    budget: Vec<budget::Share>,
+    // The model whose tokenizer ratio and context window are used for token counts (--model).
+    model: Option<&'static tokens::Model>,
+    // (model name, USD per million input tokens) from the config file's [prices] table.
+    prices: Vec<(String, f64)>,
+    // Always-excluded patterns from the config file, anchored at exclude_base.
+    exclude: Vec<String>,
+    exclude_base: Option<PathBuf>,
+    // Extra ignore files (gitignore syntax) read in every directory, e.g. .dockerignore.
+    ignore_files: Vec<String>,
+    // Exclusion lists from --ignore-from (path, contents), applied from each root path.
+    ignore_from: Vec<(PathBuf, String)>,
+    include_generated: bool,
+    // Skip lockfiles and minified assets found in directories.
+    auto_skip: bool,
+    // Traverse git submodules (and other nested repositories) when recursing.
+    submodules: bool,
+    // None: skip empty files in directory mode only.
+    skip_empty: Option<bool>,
+    // The --only-from list; its entries become `paths`.
+    only_from: Option<PathBuf>,
     paths: Vec<String>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            use_gitignore: false,
+            verbose: false,
+            recursive: false,
+            regex_pattern: None,
+            filter: None,
+            languages: Vec::new(),
+            case: ignore::Case::Sensitive,
+            owner: None,
+            use_xml: false,
+            preset: None,
+            dedup_content: false,
+            bare: false,
+            separator: "\n\n".to_string(),
+            header_prefix: "# ".to_string(),
+            fence: "```".to_string(),
+            normalize: false,
+            rewrites: Vec::new(),
+            strip_imports: false,
+            minify_data: false,
+            pipe_through: Vec::new(),
+            transform_plugins: Vec::new(),
+            formatter: None,
+            tab_width: 4,
+            max_line_length: None,
+            max_lines: None,
+            hot: None,
+            tail_logs: None,
+            summarize_over: None,
+            sample: None,
+            table_preview: None,
+            embed_images: false,
+            expand_imports: None,
+            with_tests: false,
+            no_tests: false,
+            todos: false,
+            deps: false,
+            symbol_index: false,
+            tiered: false,
+            fit: false,
+            token_tree: false,
+            readmes_first: false,
+            keep_arg_order: false,
+            priority: Vec::new(),
+            context_header: false,
+            stamp: false,
+            checksum: false,
+            query: None,
+            top: 10,
+            top_tokens: None,
+            changed_since_last: false,
+            compare: None,
+            blame: false,
+            at: None,
+            no_cache: false,
+            fresh_clone: false,
+            edit: false,
+            escape: None,
+            preview: None,
+            porcelain: false,
+            trace_filters: false,
+            list_excluded: false,
+            anonymize_paths: None,
+            pseudonymize: false,
+            pseudonym_patterns: Vec::new(),
+            why: None,
+            preview_length: preview::PreviewLength::Chars(500),
+            preview_mode: transform::LineLimitMode::Head,
+            destinations: Vec::new(),
+            rich: false,
+            slot: None,
+            per_dir_output: None,
+            crates: Vec::new(),
+            root_headers: false,
+            timings: false,
+            max_total_size: Some(DEFAULT_MAX_TOTAL_SIZE),
+            file_rules: Vec::new(),
+            max_files: None,
+            min_file_size: None,
+            max_file_size: None,
+            max_total_tokens: None,
+            budget: Vec::new(),
+            model: None,
+            prices: Vec::new(),
+            exclude: Vec::new(),
+            exclude_base: None,
+            ignore_files: Vec::new(),
+            ignore_from: Vec::new(),
+            include_generated: false,
+            auto_skip: true,
+            submodules: true,
+            skip_empty: None,
+            only_from: None,
+            paths: Vec::new(),
+        }
+    }
+}
+
+// Guards against accidentally bundling a whole monorepo; override with --max-total-size.
+const DEFAULT_MAX_TOTAL_SIZE: u64 = 10 * 1024 * 1024;
+
+// How config-file excludes are named when explaining ignore decisions.
+const EXCLUDE_SOURCE: &str = ".toprompt.toml exclude";
+
+// Exit codes, listed in --help so wrapper scripts can tell failures apart. Invalid arguments and other errors exit with 1.
+const EXIT_ERROR: i32 = 1;
+const EXIT_NOTHING_MATCHED: i32 = 2;
+const EXIT_OUTPUT_FAILED: i32 = 3;
+const EXIT_INVALID_FILTER: i32 = 4;
+const EXIT_TOO_LARGE: i32 = 5;
+const EXIT_EDIT_ABORTED: i32 = 6;
+
 fn print_usage() {
     eprintln!(
-        "Usage: {} [--xml] [-i] [-v] [-r] [-R <pattern>] <file1|dir1> [file2|dir2] ...",
-        env::args().next().unwrap_or_else(|| "toprompt".to_string())
+        "Usage: {} [--xml | --format <markdown|xml|aider|repomix|code2prompt>] [--dedup-content] [--bare] [--separator <text>] [--header-prefix <text>] [--fence-char <`|~>] [--normalize [--tab-width N]] [--strip-imports] [--minify-data] [--replace s/pattern/replacement/flags]... [--pipe-through <command>]... [--transform-plugin <command>]... [--format-plugin <command>] [--max-line-length N] [--max-lines N[:head|tail|split]] [--tail-logs N] [--summarize-over N] [--sample N[:first|largest|smallest]] [--table-preview N] [--embed-images] [--expand-imports[=depth]] [--with-tests | --no-tests] [--readmes-first] [--keep-arg-order] [--todos] [--symbol-index] [--tiered] [--deps] [--context-header] [--stamp] [--checksum] [--query <text> [--top N] [--top-tokens N]] [--hot N[:churn|recent]] [--changed-since-last] [--compare <ref>] [--blame] [--at <ref>] [--no-cache] [--fresh-clone] [--edit] [--escape json] [--preview[=full]] [--preview-chars N[:head|tail|split] | --preview-lines N[:head|tail|split]] [--stdout] [-o <file>]... [--clipboard] [--primary] [--rich] [--slot <name>] [--per-dir-output <dir>] [--crate <name>[,<name>...]]... [--root-headers] [--porcelain] [--ignore-file <name>]... [--ignore-from <file>]... [--only-from <file>] [--include-generated] [--no-auto-skip] [--no-submodules] [--skip-empty | --no-skip-empty] [--owner <@team|@user>] [--list-excluded] [--anonymize-paths] [--pseudonymize] [--pseudonymize-pattern <regex>]... [--trace-filters | --why <path>] [--profile <name> | --no-config] [--max-files N[:error|prompt|keep-largest|keep-by-arg-order]] [--min-file-size SIZE] [--max-file-size SIZE] [--size MIN..MAX] [--max-total-size SIZE] [--max-total-tokens N [--budget PATH=N%,...]] [--model <name>] [--fit] [--token-tree] [--timings] [-h] [-i] [-v] [-r] [-R <pattern>] [--filter <expr>] [--lang <names>] [--ignore-case | --smart-case] <file1|dir1|url> [file2|dir2] ...",
+        env::args_os().next().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| "toprompt".to_string())
     );
     eprintln!("  --xml          Format output using XML tags for each file.");
-    eprintln!("  -i             Use .gitignore files to exclude files/directories");
+    eprintln!("  --format <name>  markdown (default), xml, or the layout of another tool: aider, repomix (its XML output) or code2prompt (its default template).");
+    eprintln!("  --dedup-content  Also skip files whose contents are identical to an already included file.");
+    eprintln!("  --bare         When exactly one file is copied, copy just its contents, without the path header or code fences. Also `bare = true` in the config file.");
+    eprintln!("  --separator <text>  Text between files and sections instead of a blank line (\\n and \\t are expanded), e.g. '\\n---\\n'.");
+    eprintln!("  --header-prefix <text>  What precedes each file's path in markdown output instead of '# ', e.g. '## ' or 'File: '.");
+    eprintln!("  --fence-char <`|~>  Fence code blocks with backticks (default) or tildes. All three are also config file keys.");
+    eprintln!("  --normalize    Expand tabs, strip trailing whitespace and collapse runs of blank lines.");
+    eprintln!("  --replace s/pattern/replacement/flags  Rewrite every file's contents line by line like sed (regex pattern; & and \\1 in the replacement; flags g and i), e.g. to scrub hostnames. Repeatable; also `replace = [...]` in the config file.");
+    eprintln!("  --pipe-through <command>  Preprocess every file with a shell command before anything else: its contents go to stdin, the path is in TOPROMPT_PATH, and what it prints is embedded. Files the command fails on are kept as they were. Repeatable.");
+    eprintln!("  --transform-plugin <command>  Pipe every file's contents through a command (after the built-in transforms) and use what it prints, e.g. a proprietary redaction step. It gets TOPROMPT_STAGE, TOPROMPT_PATH and TOPROMPT_LANGUAGE; a non-zero exit aborts. Repeatable; also `transform_plugins = [...]` in the config file.");
+    eprintln!("  --format-plugin <command>  Let a command lay out each file: it reads the file's text on stdin (with the same variables) and prints the file's whole section. Also `format_plugin` in the config file.");
+    eprintln!("  --minify-data  Compact whitespace in .json and .xml data files and drop blank and comment lines from .yaml files; code is left untouched.");
+    eprintln!("  --strip-imports  Replace top-level import/use/#include blocks with a one-line \"(N imports elided)\" comment (pub use re-exports are kept).");
+    eprintln!("  --tab-width N  Tab width used by --normalize (default: 4).");
+    eprintln!("  --max-line-length N  Truncate lines longer than N characters (e.g. minified code).");
+    eprintln!("  --max-lines N[:head|tail|split]  Keep only the first (default), last, or first+last N lines of longer files.");
+    eprintln!("  --tail-logs N  Keep only the last N lines of .log files (ANSI escape codes are always stripped from logs).");
+    eprintln!("  --summarize-over N  List the file names and sizes of directories with more than N eligible files instead of their contents.");
+    eprintln!("  --sample N[:first|largest|smallest]  Include at most N files from each directory: the first N by name (default), or the largest/smallest, to show a big codebase's conventions.");
+    eprintln!("  --table-preview N  Include only the header and first N rows of .csv/.tsv files.");
+    eprintln!("  --embed-images Embed images as base64 data (images are otherwise listed with format and dimensions only).");
+    eprintln!("  --expand-imports[=depth]  Also include local files imported by the given files (use/mod/import/require/#include), transitively up to depth (default: 1).");
+    eprintln!("  --with-tests   Also include each file's conventional test counterpart (foo_test.go, test_foo.py, foo.spec.ts, tests/foo.rs, ...).");
+    eprintln!("  --no-tests     Leave out tests: test/, tests/, __tests__/ and spec/ directories, test-named files (foo_test.go, test_foo.py, foo.spec.ts, FooTest.java, ...) and #[cfg(test)]-only Rust files.");
+    eprintln!("  --readmes-first  Put the README of each directory before the directory's other files and subdirectories.");
+    eprintln!("  --keep-arg-order  Keep files in the order the paths were given (files of a directory in traversal order), also when --query or --hot pick them, which otherwise reorder by relevance or activity.");
+    eprintln!("  --todos        Append a list of the TODO/FIXME/HACK comments in the included files, with path and line.");
+    eprintln!("  --symbol-index Append an index of the top-level functions, types and constants of the included files (and of directories listed by --summarize-over), as path:line kind name (universal-ctags is used for languages without built-in rules).");
+    eprintln!("  --tiered       With -R, include the files it does not match as outlines (top-level signatures and their doc comments), within --max-total-tokens if set.");
+    eprintln!("  --deps         Prepend the dependencies (name and version) declared in Cargo.toml, package.json, pyproject.toml or go.mod at the given directories (or the current one).");
+    eprintln!("  --context-header  Prepend the project name, main languages, file/line totals, git branch and latest commit, and the command used.");
+    eprintln!("  --checksum     End the bundle with the SHA-256 of everything before that line (its separator excluded) and show it in the report, to match a model response to the exact bundle.");
+    eprintln!("  --stamp        Append a footer with the generation time (UTC), toprompt version, git commit (noting uncommitted changes) and the command used.");
+    eprintln!("  --query <text> Rank files by keyword relevance (BM25) to the query and keep only the best matches, most relevant first.");
+    eprintln!("  --top N        Number of files kept by --query (default: 10).");
+    eprintln!("  --top-tokens N Also stop adding --query matches once ~N tokens are used.");
+    eprintln!("  --hot N[:churn|recent]  Keep only the N files changed in the most commits (default) or most recently, per git log, hottest first.");
+    eprintln!("  --changed-since-last  Only include files whose contents changed since the previous run in this directory.");
+    eprintln!("  --compare <ref>  Include each file both as it was at the git ref and as it is in the working tree.");
+    eprintln!("  --blame        Prefix each line with the short hash and author of the commit that last changed it (like git blame).");
+    eprintln!("  --at <ref>     Bundle the files as they are at a commit, tag or branch (the tree is extracted into the cache directory) instead of the working tree.");
+    eprintln!("  --no-cache     Don't read or write the on-disk cache of formatted files.");
+    eprintln!("  --fresh-clone  Clone remote repository arguments again instead of updating the cached clone.");
+    eprintln!("  --edit         Open the bundle in $EDITOR first and copy the buffer as saved when the editor exits.");
+    eprintln!("  --escape json  Copy the finished bundle as a single JSON string literal (quoted; newlines, tabs and quotes escaped), ready to paste into an API request body.");
+    eprintln!("  --preview[=full]  Page the first lines (or all) of the bundle after copying, highlighted with bat if installed ($PAGER or less otherwise).");
+    eprintln!("  --preview-chars N[:head|tail|split]  Length of the -v preview in characters (default: 500:head); tail shows the end of the bundle.");
+    eprintln!("  --preview-lines N[:head|tail|split]  Same, in lines.");
+    eprintln!("  --stdout       Write the bundle to stdout instead of the clipboard.");
+    eprintln!("  -o, --output <file>  Write the bundle to a file instead of the clipboard. Repeatable.");
+    eprintln!("  --clipboard    Also copy to the clipboard when --stdout or -o is given; destinations combine, e.g. -o context.md --stdout --clipboard.");
+    eprintln!("  --primary      Copy to the primary selection (middle-click paste on X11/Wayland) instead of the clipboard; add --clipboard for both.");
+    eprintln!("  --rich         Also put an HTML version (headings, highlighted code blocks) on the clipboard, so pasting into docs or chat apps keeps the formatting while plain-text targets get the markdown (macOS and Windows).");
+    eprintln!("  --slot <name>  Also save the bundle as a named slot; `toprompt recopy <name>` copies it again later, e.g. to switch between context packs.");
+    eprintln!("  --crate <name>  Bundle a member crate of the enclosing Cargo workspace: its Cargo.toml, build.rs, src/, tests/, benches/ and examples/, after an overview of all members. Repeatable or comma-separated.");
+    eprintln!("  --root-headers  With several directory paths (e.g. backend/ frontend/), start each one's files with a section header naming it. Files are always shown under their root's label, so two src/index.ts stay apart.");
+    eprintln!("  --per-dir-output <dir>  Write one bundle file per top-level directory (per member crate in a Cargo workspace) into <dir>, e.g. <dir>/src.md, to paste modules into separate conversations. Each keeps the preamble and appendix (tree, summaries, instructions); files directly in the directory go to <dir>/@root.md. The clipboard is skipped unless --clipboard, --stdout or -o is also given.");
+    eprintln!("  --porcelain    Print only one stable tab-separated record per file to stdout: status (included/duplicate/auto-skipped/empty/unchanged), path, bytes, tokens. With --checksum, a final `checksum<TAB>sha256<TAB><hex><TAB>-` record.");
+    eprintln!("  --ignore-file <name>  Also apply ignore files with this name (e.g. .dockerignore, .npmignore, .eslintignore, .prettierignore) in every directory, using gitignore rules. Repeatable.");
+    eprintln!("  --ignore-from <file>  Exclude paths matching the patterns in <file> (gitignore syntax, relative to each given path), e.g. a team-wide list kept outside the repo. Repeatable.");
+    eprintln!("  --only-from <file>    Include exactly the files listed in <file> (one path per line, # comments allowed) instead of paths on the command line; fails if any entry is missing.");
+    eprintln!("  --include-generated  With -i, keep files marked linguist-generated or linguist-vendored in .gitattributes (skipped by default).");
+    eprintln!("  --no-auto-skip Keep lockfiles (Cargo.lock, package-lock.json, yarn.lock, ...) and minified assets (*.min.js, *.map, ...) found in directories; they are skipped and listed by default.");
+    eprintln!("  --no-submodules  Don't recurse into git submodules (with -i, each submodule is otherwise read with its own .gitignore files only).");
+    eprintln!("  --skip-empty   Skip empty and whitespace-only files, including named ones (by default only files found in directories are skipped).");
+    eprintln!("  --no-skip-empty  Keep empty and whitespace-only files.");
+    eprintln!("  --owner <@team|@user>  Only include files the owner is assigned in CODEOWNERS (.github/, the repository root or docs/).");
+    eprintln!("  --list-excluded  Append a section naming the files and directories that exist but were filtered out, with the reason.");
+    eprintln!("  --pseudonymize  Rename the names matched by pseudonymize_patterns in {} (or --pseudonymize-pattern) to Name1, Name2, ... consistently across all files and paths. -v prints the legend.", config_file::FILE_NAME);
+    eprintln!("  --pseudonymize-pattern <regex>  A name to pseudonymize, e.g. 'Acme(Corp)?'; implies --pseudonymize. Repeatable.");
+    eprintln!("  --anonymize-paths  Replace the home directory with ~ and the user and host names with \"user\"/\"host\" in file headers, listings and other paths in the bundle.");
+    eprintln!("  --trace-filters  Print to stderr, for every candidate file and directory, the rule that included or excluded it.");
+    eprintln!("  --why <path>   Same, only for <path> (and the directories leading to it).");
+    eprintln!("  --profile <name>  Apply the [profiles.<name>] table of .toprompt.toml on top of its defaults.");
+    eprintln!("  --no-config    Ignore .toprompt.toml.");
+    eprintln!("  --max-files N[:policy]  Cap the number of files. Over the cap: prompt (default; ask on a terminal, else fail), error, keep-largest, or keep-by-arg-order (the first N, in the order of the given paths). Dropped files are reported.");
+    eprintln!("  --min-file-size SIZE  Skip files smaller than SIZE, e.g. stubs and empty __init__.py files.");
+    eprintln!("  --max-file-size SIZE  Skip files larger than SIZE, e.g. data dumps and fixtures.");
+    eprintln!("  --size MIN..MAX  Both at once, e.g. 1kb..200kb (either bound may be left out: 1kb.., ..200kb).");
+    eprintln!("  --max-total-size SIZE  Refuse (or ask, when interactive) to output bundles larger than SIZE (default: 10mb, \"none\" disables).");
+    eprintln!("  --max-total-tokens N   Same, for the estimated token count (default: none).");
+    eprintln!("  --budget PATH=N%,...  Split --max-total-tokens between path prefixes (e.g. src/=60%,tests/=20%; other files share the rest); each part keeps its files in order while they fit, then unused tokens go to the files left over.");
+    eprintln!("  --model <name> Count tokens with the ratio of this model's tokenizer and warn when the bundle exceeds its context window: gpt-4o, gpt-4, claude-3.5, llama3, llama3.1, gemini-1.5.");
+    eprintln!("  --fit          When over the limit, strip comments, then remove blank lines, then outline (signatures only), biggest files first, until the bundle fits; the changes are reported.");
+    eprintln!("  --token-tree   After copying, print the directory tree with the tokens of each file and directory, heaviest first (colored by share; NO_COLOR disables).");
+    eprintln!("  --timings      Print to stderr the wall time spent traversing, filtering, reading, formatting, counting tokens and writing the output, to diagnose slow runs.");
+    eprintln!("  -h, --help     Show this help and exit.");
+    eprintln!("  -i             Use .gitignore (and Mercurial .hgignore) files to exclude files/directories");
     eprintln!("  -v             Verbose output (show ignored files, detailed success messages, and preview)");
     eprintln!("  -r             Recursively process subdirectories");
     eprintln!("  -R <pattern>   Recursively process subdirectories, matching files against regex pattern (applied to relative paths)");
+    eprintln!("  --lang <names>  Keep only files in these languages, e.g. rust,python (the code fence names; extensions like py work too). Extensionless scripts are recognized by their shebang.");
+    eprintln!("  --ignore-case  Match -R, --filter and ignore/exclude patterns regardless of case (-R readme matches README.md).");
+    eprintln!("  --smart-case   Like --ignore-case, except for patterns containing an uppercase letter.");
+    eprintln!("  --filter <expr>  Keep only files matching an expression of ext, name, lang (=, != and comma lists), path (~ regex, !~, =), size (<, >, ..., or =1kb..200kb) and mtime (age: <, >, ...) predicates joined with and/or/not and parentheses, e.g. 'ext=rs and size<50kb and not path~\"tests/\"'.");
+    eprintln!("\nSubcommands:");
+    eprintln!("  toprompt pr <url|number>      # Copy a GitHub pull request (description, changed files, diff) via gh");
+    eprintln!("  toprompt config check         # Validate .toprompt.toml and show the settings in effect");
+    eprintln!("  toprompt config explain <path> # Show which gitignore line, exclude, -R pattern or recursion setting decides <path>");
+    eprintln!("  toprompt doctor               # Check clipboard tools, display/SSH session, git, gh, curl, .toprompt.toml and the cache directory");
+    eprintln!("  toprompt init                 # Create a .toprompt.toml with project defaults (ignores, format, token budget, profiles)");
+    eprintln!("  toprompt import-config <file>  # Translate a repomix.config.json or code2prompt config into .toprompt.toml");
+    eprintln!("  toprompt llms-txt [--full]     # Write an llms.txt index (or llms-full.txt with contents) of the project's files");
+    eprintln!("  toprompt serve --editor       # JSON-RPC server over stdio for editor plugins");
+    eprintln!("  toprompt daemon [--socket <path>]  # The same JSON-RPC server on a unix socket, keeping files and listings cached in memory");
+    eprintln!("  toprompt recopy <name>        # Copy the bundle saved with --slot <name> again (also `paste`; --list shows the slots)");
+    eprintln!("\nPaths may also be git repository URLs (https://github.com/owner/repo[#ref]), which are shallow-cloned into the cache directory,");
+    eprintln!("or other http(s):// URLs, which are fetched with curl and converted from HTML to markdown.");
+    eprintln!("Defaults are read from .toprompt.toml in the current directory or its nearest ancestor.");
+    eprintln!("A file containing a `// toprompt:ignore-file` comment line is left out; `// toprompt:begin` ... `// toprompt:end`");
+    eprintln!("comment lines (any comment style) limit a file to the regions between them.");
+    eprintln!("\nExit codes:");
+    eprintln!("  0  Success");
+    eprintln!("  {}  Invalid arguments or another error", EXIT_ERROR);
+    eprintln!("  {}  No files were included (nothing matched, or everything was filtered out)", EXIT_NOTHING_MATCHED);
+    eprintln!("  {}  The bundle could not be copied/written to a destination; unless it also went to stdout, it was printed there instead (not with --porcelain)", EXIT_OUTPUT_FAILED);
+    eprintln!("  {}  Invalid -R regex pattern or --filter expression", EXIT_INVALID_FILTER);
+    eprintln!("  {}  The bundle exceeds --max-total-size/--max-total-tokens/--max-files", EXIT_TOO_LARGE);
+    eprintln!("  {}  --edit failed or the edited bundle was empty", EXIT_EDIT_ABORTED);
     eprintln!("\nExample combined flags: -ri, -rv, -iv, -riv (and permutations)");
     eprintln!("\nExamples:");
     eprintln!("  toprompt file.txt             # Copy specific file (prints 'file.txt')");
@@ -33,40 +494,130 @@ fn print_usage() {
 }
 
 fn main() {
+    let args = command_line_args();
+    if args.first().map(String::as_str) == Some("pr") {
+        pr::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("config") {
+        config_command::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("doctor") {
+        doctor::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("import-config") {
+        import_config::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("init") {
+        init::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("llms-txt") {
+        llms_txt::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("serve") {
+        serve::run(&args[1..]);
+        return;
+    }
+    if args.first().map(String::as_str) == Some("daemon") {
+        daemon::run(&args[1..]);
+        return;
+    }
+    if matches!(args.first().map(String::as_str), Some("recopy" | "paste")) {
+        slots::run(&args[1..]);
+        return;
+    }
+
     let config = parse_args();
+    if config.timings {
+        timings::start();
+    }
+    if let Some(model) = config.model {
+        tokens::set_model(model);
+    }
 
     if config.paths.is_empty() {
         print_usage();
-        std::process::exit(1);
+        std::process::exit(EXIT_ERROR);
     }
 
     let compiled_regex = match &config.regex_pattern {
-        Some(pattern_str) => match Regex::new(pattern_str) {
+        Some(pattern_str) => match config.case.regex(pattern_str) {
             Ok(re) => Some(re),
             Err(e) => {
                 eprintln!("Error: Invalid regex pattern '{}': {}", pattern_str, e);
                 print_usage();
-                std::process::exit(1);
+                std::process::exit(EXIT_INVALID_FILTER);
             }
         },
         None => None,
     };
 
-    let mut formatted_content = String::new();
-    let mut successful_files = 0;
-    let mut file_index = 0;
-    let mut copied_file_names: Vec<String> = Vec::new(); // To store names of copied files
+    let mut bundle = Bundle::new();
+    bundle.separator = config.separator.clone();
+    if !config.no_cache {
+        let cache = cache::Cache::open();
+        cache.prune();
+        bundle.cache = Some(cache);
+    }
+
+    if !config.budget.is_empty() && config.max_total_tokens.is_none() {
+        eprintln!("Error: --budget splits --max-total-tokens, so it needs --max-total-tokens N (or max_total_tokens in {}).", config_file::FILE_NAME);
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    if config.no_tests && config.with_tests {
+        eprintln!("Error: --no-tests and --with-tests contradict each other.");
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    if config.pseudonymize && config.pseudonym_patterns.is_empty() {
+        eprintln!("Error: --pseudonymize needs the names to rename: --pseudonymize-pattern <regex> or pseudonymize_patterns in {}.", config_file::FILE_NAME);
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    if config.tiered && config.regex_pattern.is_none() {
+        eprintln!("Error: --tiered needs -R <pattern> to choose the files included in full.");
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    if let Some(owner) = &config.owner {
+        // The repository of the first given path that has one, or else of the working directory.
+        let starts: Vec<PathBuf> = config.paths.iter().map(PathBuf::from).chain(env::current_dir()).filter_map(|path| fs::canonicalize(path).ok()).collect();
+        match starts.iter().find_map(|start| codeowners::CodeOwners::find(start)) {
+            Some(codeowners) => {
+                if config.verbose {
+                    println!("Selecting files owned by {} per {}", owner, codeowners.path.display());
+                }
+                bundle.codeowners = Some(codeowners);
+            }
+            None => {
+                eprintln!("Error: --owner needs a CODEOWNERS file (.github/CODEOWNERS, CODEOWNERS or docs/CODEOWNERS), and none was found.");
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+    }
 
+    bundle.root_labels = root_labels(&config.paths);
+    bundle.keep_contents = needs_contents(&config);
+    // Without a pass over the whole bundle, files are written out as they are read. If the destination cannot
+    // be opened now, it is written at the end as usual, which reports the error.
+    if output::can_stream(&config) {
+        bundle.stream = output::Stream::open(&config.destinations[0], &config).ok();
+    }
+
+    // --only-from entries that a filter (binary, size, -R, ...) kept out of the bundle.
+    let mut not_included = Vec::new();
     for path_str in config.paths.iter() {
-        match process_path(
-            path_str,
-            &mut formatted_content,
-            &mut file_index,
-            &mut successful_files,
-            &config,
-            &compiled_regex,
-            &mut copied_file_names, // Pass the a mutable reference
-        ) {
+        if bundle.stream_stopped() {
+            break;
+        }
+        let before = bundle.file_count();
+        match timings::time(timings::Phase::Traversal, || process_path(path_str, &mut bundle, &config, &compiled_regex)) {
             Ok(_) => {}
             Err(e) => {
                 if config.verbose { // Only print processing errors if verbose, or they are critical like path not found.
@@ -74,96 +625,854 @@ fn main() {
                 }
             }
         }
+        if config.only_from.is_some() && bundle.file_count() == before {
+            not_included.push(path_str.as_str());
+        }
+    }
+    if !not_included.is_empty() {
+        eprintln!(
+            "Warning: {} files listed in --only-from were not included (use --why <path> to see which filter skipped them): {}",
+            not_included.len(),
+            not_included.join(", ")
+        );
+    }
+
+    if let Some(why) = &config.why
+        && !bundle.why_reached
+    {
+        eprintln!("--why: '{}' was not reached; it is not under any of the given paths, or a parent directory was excluded.", display_relative(why));
+    }
+    if bundle.stream_stopped()
+        && let Some(Err(stop)) = bundle.stream.take().map(|stream| stream.finish(&[]))
+    {
+        exit_stream_stopped(stop, &config);
+    }
+
+    if let Some(depth) = config.expand_imports {
+        expand_imports(&mut bundle, &config, depth);
+    }
+    if config.with_tests {
+        include_companion_tests(&mut bundle, &config);
+    }
+    // Where each file sits in argument order, to restore it after ranking (--keep-arg-order).
+    let arg_order: HashMap<String, usize> = bundle.entries.iter().enumerate().map(|(i, entry)| (entry.display_name.clone(), i)).collect();
+    let mut query_dropped = 0;
+    if let Some(query) = &config.query {
+        query_dropped = rank::apply_query(&mut bundle, query, config.top, config.top_tokens, config.verbose);
+    }
+    let mut hot_dropped = 0;
+    if let Some((count, mode)) = config.hot {
+        hot_dropped = hot::apply(&mut bundle, count, mode, config.verbose);
+    }
+    if config.keep_arg_order {
+        bundle.entries.sort_by_key(|entry| arg_order.get(&entry.display_name).copied().unwrap_or(usize::MAX));
+    }
+    if !config.priority.is_empty() {
+        bundle.entries.sort_by_key(|entry| priority_rank(entry, &config).unwrap_or(usize::MAX));
+    }
+    let mut over_budget = Vec::new();
+    if let Some(total) = config.max_total_tokens
+        && !config.budget.is_empty()
+    {
+        over_budget = budget::apply(&mut bundle, &config.budget, total, |entry| priority_rank(entry, &config).is_some(), config.verbose);
+    }
+    let (outlined, outlines_left_out) = if config.tiered { add_outlines(&mut bundle, &config) } else { (0, 0) };
+    let over_max_files = cap_file_count(&mut bundle, &config);
+
+    // Only --changed-since-last reads the manifest, and only it records what was sent (see save_manifest).
+    let previous_manifest = config.changed_since_last.then(manifest::load);
+    let mut unchanged_files = Vec::new();
+    if let Some(previous) = &previous_manifest {
+        unchanged_files = manifest::drop_unchanged(previous, &mut bundle);
+        if !unchanged_files.is_empty() {
+            bundle.preamble.push(format!("Unchanged since the previous copy (omitted): {}", unchanged_files.join(", ")));
+        }
+    }
+
+    let mut roots: Vec<&Path> = config.paths.iter().map(Path::new).filter(|path| path.is_dir()).collect();
+    if roots.is_empty() {
+        roots.push(Path::new("."));
+    }
+    if config.deps
+        && let Some(summary) = deps::summarize(&roots, &config)
+    {
+        bundle.preamble.insert(0, summary);
+    }
+    if !config.crates.is_empty()
+        && let Some(root) = env::current_dir().ok().and_then(|cwd| workspace::find_root(&cwd))
+    {
+        bundle.preamble.insert(0, workspace::overview(&root, &config.crates, &config));
+    }
+    if config.context_header {
+        bundle.preamble.insert(0, context::header(&bundle, roots[0], &config));
+    }
+    if config.todos {
+        let todos = todos::collect(&bundle);
+        if !todos.is_empty() {
+            bundle.appendix.push(todos::format(&todos, config.use_xml));
+        }
+    }
+    if config.symbol_index
+        && let Some(index) = symbols::index(&bundle, &config)
+    {
+        bundle.appendix.push(index);
+    }
+    if config.list_excluded && !bundle.excluded.is_empty() {
+        let section = output::format_excluded(&bundle.excluded, config.use_xml);
+        bundle.appendix.push(section);
+    }
+    if config.stamp {
+        bundle.appendix.push(context::stamp(roots[0], &config));
+    }
+    if let Some(preset) = config.preset {
+        preset.wrap(&mut bundle, roots[0], &config);
     }
 
+    let successful_files = bundle.file_count();
     if successful_files == 0 {
         eprintln!("No files were successfully processed.");
+        if config.query.is_some() {
+            eprintln!("No file matched the --query terms.");
+        }
+        if !unchanged_files.is_empty() {
+            eprintln!("All {} file(s) are unchanged since the previous run.", unchanged_files.len());
+        }
         if config.regex_pattern.is_some() && !config.paths.is_empty() {
             eprintln!("Check your regex pattern and paths. Regex is applied to paths relative to the input directory arguments.");
         }
-        std::process::exit(1);
+        if let Some(filter) = &config.filter {
+            eprintln!("Check your --filter '{}'; its path predicates see paths relative to the input directory arguments.", filter.source);
+        }
+        if let Some(stream) = bundle.stream.take() {
+            stream.discard();
+        }
+        std::process::exit(EXIT_NOTHING_MATCHED);
+    }
+
+    if config.fit {
+        let applied = fit_to_budget(&mut bundle, &config);
+        if !applied.is_empty() && !config.porcelain {
+            eprintln!("Reduced the bundle to fit the size/token limit:");
+            for (reducer, files) in &applied {
+                eprintln!("  {} ({}): {}", reducer, files.len(), files.join(", "));
+            }
+        }
+    }
+    // Last of the content changes, so nothing (not even --fit's outlines, read from disk) brings a real name back.
+    if config.pseudonymize {
+        let legend = pseudonym::apply(&mut bundle, &config.pseudonym_patterns);
+        if !config.porcelain {
+            eprintln!(":: Pseudonymized {} name(s) ::", legend.len());
+            if config.verbose {
+                for (pseudonym, name) in &legend {
+                    eprintln!("  {} = {}", pseudonym, name);
+                }
+            }
+        }
+    }
+    if config.bare {
+        match bundle.entries.as_mut_slice() {
+            [entry] => {
+                entry.segment = entry.contents.trim_end().to_string();
+                entry.tokens = tokens::estimate_tokens(&entry.segment);
+            }
+            entries if !config.porcelain => eprintln!("Note: --bare only applies to a single file; {} files keep their headers.", entries.len()),
+            _ => {}
+        }
+    }
+    if config.root_headers {
+        if bundle.root_labels.is_empty() && !config.porcelain {
+            eprintln!("Note: --root-headers only applies when several directories are given.");
+        }
+        add_root_headers(&mut bundle, &config);
+    }
+    // A streamed bundle was checked as it was written.
+    if bundle.stream.is_none() {
+        enforce_size_cap(&bundle, &config);
+    }
+    if let Some(model) = config.model
+        && bundle.total_tokens() > model.context_window
+    {
+        eprintln!(
+            "Warning: the bundle is ~{} tokens, more than the {}-token context window of {}.",
+            bundle.total_tokens(), model.context_window, model.name
+        );
+    }
+
+    if config.edit {
+        match editor::edit_bundle(&bundle, config.use_xml) {
+            Ok(edited) if edited.trim().is_empty() => {
+                eprintln!("The edited bundle is empty; nothing was copied.");
+                std::process::exit(EXIT_EDIT_ABORTED);
+            }
+            Ok(edited) => bundle.edited = Some(edited.trim_end().to_string()),
+            Err(e) => {
+                eprintln!("Error: --edit failed: {}", e);
+                std::process::exit(EXIT_EDIT_ABORTED);
+            }
+        }
+    }
+    // After --edit, so the checksum covers the text that is actually sent.
+    let checksum = if config.checksum {
+        match output::checksum(&bundle) {
+            Ok(checksum) => {
+                let line = output::format_checksum(&checksum, config.use_xml);
+                match &mut bundle.edited {
+                    Some(edited) => {
+                        edited.push_str(&bundle.separator);
+                        edited.push_str(&line);
+                    }
+                    None => bundle.appendix.push(line),
+                }
+                Some(checksum)
+            }
+            Err(e) => {
+                eprintln!("Error: --checksum failed: {}", e);
+                std::process::exit(EXIT_ERROR);
+            }
+        }
+    } else {
+        None
+    };
+    // After --edit, so the escaped text is exactly what the user saw and saved.
+    if let Some(escape) = config.escape {
+        match escape.apply(&bundle) {
+            Ok(escaped) => bundle.edited = Some(escaped),
+            Err(e) => {
+                eprintln!("Error: --escape failed: {}", e);
+                std::process::exit(EXIT_ERROR);
+            }
+        }
     }
 
-    match copy_to_clipboard(&formatted_content) {
+    // Saved before writing, so the slot is kept even if a destination fails.
+    if let Some(slot) = &config.slot
+        && let Err(e) = slots::save(slot, &bundle)
+    {
+        eprintln!("Warning: could not save slot '{}': {}", slot, e);
+    }
+    if let Some(dir) = &config.per_dir_output {
+        // Relative to the one directory given, or to the working directory when several are.
+        let base = if roots.len() == 1 { roots[0] } else { Path::new(".") };
+        match timings::time(timings::Phase::Output, || output::write_per_dir(&bundle, base, dir, &config)) {
+            Ok(written) if !config.porcelain => {
+                println!(":: Wrote {} bundle(s) to {} ::", written.len(), dir.display());
+                for (name, files, tokens) in written {
+                    println!("{} ({} files, ~{} tokens)", name, files, tokens);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Failed to write the per-directory bundles to {}: {}", dir.display(), e);
+                std::process::exit(EXIT_OUTPUT_FAILED);
+            }
+        }
+        // With --porcelain the records are still printed below.
+        if config.destinations.is_empty() && !config.porcelain {
+            save_manifest(previous_manifest.as_ref(), &bundle, &config);
+            report_timings();
+            return;
+        }
+    }
+    let destination_name = config.destinations.iter().map(Destination::describe).collect::<Vec<_>>().join(", ");
+    let to_stdout = config.destinations.contains(&Destination::Stdout);
+    // A streamed bundle only has its appendix left to write. Otherwise every destination is attempted, so one
+    // failing (e.g. no clipboard tool) still leaves the others written.
+    let failures: Vec<(&Destination, Box<dyn std::error::Error>)> = match bundle.stream.take() {
+        Some(stream) => {
+            if let Err(stop) = timings::time(timings::Phase::Output, || stream.finish(&bundle.appendix)) {
+                exit_stream_stopped(stop, &config);
+            }
+            Vec::new()
+        }
+        None => config
+            .destinations
+            .iter()
+            .filter_map(|destination| timings::time(timings::Phase::Output, || output::write_bundle(&bundle, destination, &config)).err().map(|e| (destination, e)))
+            .collect(),
+    };
+    let write_result = if failures.is_empty() { Ok(()) } else { Err(failures) };
+    let written = write_result.is_ok();
+    if written {
+        save_manifest(previous_manifest.as_ref(), &bundle, &config);
+    }
+    match write_result {
+        Ok(_) if config.porcelain => {
+            if let Err(e) = output::write_porcelain(&bundle, &unchanged_files, checksum.as_deref(), &mut io::stdout().lock()) {
+                eprintln!("Failed to write output: {}", e);
+                std::process::exit(EXIT_OUTPUT_FAILED);
+            }
+        }
+        // When the bundle itself went to stdout, keep stdout clean of status messages.
+        Ok(_) if to_stdout => {
+            if config.verbose {
+                eprintln!("Wrote {} file(s) to {}.", successful_files, destination_name);
+            }
+            if let Some(checksum) = &checksum {
+                eprintln!(":: SHA-256 {} ::", checksum);
+            }
+        }
         Ok(_) => { // Successfully copied to clipboard
             if config.verbose {
                 println!(
-                    "\nSuccessfully copied {} file(s) to clipboard!",
-                    successful_files
+                    "\nSuccessfully copied {} file(s) to {}!",
+                    successful_files, destination_name
                 );
                 if config.use_gitignore { println!("(.gitignore rules were applied)"); }
                 if config.use_xml { println!("(XML format was used)"); }
                 if config.recursive { println!("(Recursive mode was active)"); }
-                if config.regex_pattern.is_some() {
-                    println!("(Regex filter '{}' was applied)", config.regex_pattern.as_ref().unwrap());
+                if let Some(pattern) = &config.regex_pattern {
+                    println!("(Regex filter '{}' was applied)", pattern);
+                }
+                if let Some(filter) = &config.filter {
+                    println!("(--filter '{}' was applied)", filter.source);
+                }
+                if !bundle.skipped_duplicates.is_empty() {
+                    println!("(Skipped {} duplicate file(s))", bundle.skipped_duplicates.len());
+                }
+                if !bundle.skipped_empty.is_empty() {
+                    println!("(Skipped {} empty file(s))", bundle.skipped_empty.len());
+                }
+                if !bundle.auto_skipped.is_empty() {
+                    println!("(Auto-skipped {} lockfile/minified file(s): {}; use --no-auto-skip to keep them)", bundle.auto_skipped.len(), bundle.auto_skipped.join(", "));
+                }
+                if let Some(query) = &config.query {
+                    println!("(Ranked by query '{}'; {} less relevant file(s) left out)", query, query_dropped);
+                }
+                if config.hot.is_some() && hot_dropped > 0 {
+                    println!("(Kept the files with the most git activity; {} other file(s) left out)", hot_dropped);
+                }
+                if !over_max_files.is_empty() {
+                    println!("(Left out {} file(s) over --max-files: {})", over_max_files.len(), over_max_files.join(", "));
+                }
+                if !over_budget.is_empty() {
+                    println!("(Left out {} file(s) over their --budget share: {})", over_budget.len(), over_budget.join(", "));
+                }
+                if outlined > 0 || outlines_left_out > 0 {
+                    println!("(Outlined {} file(s) not matched by -R; {} more left out to stay within --max-total-tokens)", outlined, outlines_left_out);
+                }
+                if !unchanged_files.is_empty() {
+                    println!("(Left out {} file(s) unchanged since the previous run: {})", unchanged_files.len(), unchanged_files.join(", "));
+                }
+                if let Some(cost) = cost_report(bundle.total_tokens(), &config) {
+                    println!("(Estimated input cost: {})", cost);
+                }
+                if let Some(checksum) = &checksum {
+                    println!("(SHA-256 of the bundle: {})", checksum);
+                }
+                if let Some(model) = config.model {
+                    println!(
+                        "(~{} tokens with the {} tokenizer of {}: {}% of its {}-token context window)",
+                        bundle.total_tokens(), model.tokenizer, model.name, bundle.total_tokens() * 100 / model.context_window, model.context_window
+                    );
                 }
                 println!("\nCopied files:");
-                for name in &copied_file_names {
+                for name in bundle.file_names() {
                     println!("{}", name);
                 }
-                println!(
-                    "\n--- Clipboard Contents Preview (first 500 chars) ---\n"
-                );
-                let preview = if formatted_content.len() > 500 {
-                    &formatted_content[..500]
-                } else {
-                    &formatted_content
-                };
-                println!("{}...", preview);
+                if config.preview.is_none() {
+                    println!(
+                        "\n--- Output Preview ({}) ---\n",
+                        preview::describe(config.preview_length, config.preview_mode)
+                    );
+                    println!("{}", preview::excerpt(&bundle, config.preview_length, config.preview_mode));
+                }
             } else { // Not verbose, successfully copied
-                println!(":: Copied {} files ::", successful_files);
+                if matches!(config.destinations.as_slice(), [Destination::Clipboard]) {
+                    println!(":: Copied {} files ::", successful_files);
+                } else if config.destinations.iter().all(|destination| matches!(destination, Destination::Clipboard | Destination::Primary)) {
+                    println!(":: Copied {} files to the {} ::", successful_files, destination_name);
+                } else {
+                    println!(":: Wrote {} files to {} ::", successful_files, destination_name);
+                }
                 // Iterate over the first 10 names, or fewer if the list is shorter.
-                for name in copied_file_names.iter().take(10) {
+                for name in bundle.file_names().take(10) {
                     println!("{}", name);
                 }
 
                 // If there were more than 10 files in total, print "..."
-                if copied_file_names.len() > 10 {
+                if bundle.file_count() > 10 {
                     println!("...");
                 }
+                if !bundle.skipped_duplicates.is_empty() {
+                    println!(":: Skipped {} duplicate(s) ::", bundle.skipped_duplicates.len());
+                }
+                if !bundle.skipped_empty.is_empty() {
+                    println!(":: Skipped {} empty file(s) ::", bundle.skipped_empty.len());
+                }
+                if !bundle.auto_skipped.is_empty() {
+                    println!(":: Auto-skipped {} lockfile/minified file(s) (--no-auto-skip keeps them) ::", bundle.auto_skipped.len());
+                    for name in bundle.auto_skipped.iter().take(5) {
+                        println!("{}", name);
+                    }
+                    if bundle.auto_skipped.len() > 5 {
+                        println!("...");
+                    }
+                }
+                if config.query.is_some() && query_dropped > 0 {
+                    println!(":: Left out {} less relevant file(s) ::", query_dropped);
+                }
+                if config.hot.is_some() && hot_dropped > 0 {
+                    println!(":: Left out {} less active file(s) ::", hot_dropped);
+                }
+                if !over_max_files.is_empty() {
+                    println!(":: Left out {} file(s) over --max-files ::", over_max_files.len());
+                    for name in over_max_files.iter().take(5) {
+                        println!("{}", name);
+                    }
+                    if over_max_files.len() > 5 {
+                        println!("...");
+                    }
+                }
+                if !over_budget.is_empty() {
+                    println!(":: Left out {} file(s) over their --budget share ::", over_budget.len());
+                    for name in over_budget.iter().take(5) {
+                        println!("{}", name);
+                    }
+                    if over_budget.len() > 5 {
+                        println!("...");
+                    }
+                }
+                if outlined > 0 || outlines_left_out > 0 {
+                    println!(":: Outlined {} other file(s), left out {} over the token budget ::", outlined, outlines_left_out);
+                }
+                if !unchanged_files.is_empty() {
+                    println!(":: Left out {} unchanged file(s) ::", unchanged_files.len());
+                }
+                if let Some(model) = config.model {
+                    println!(":: ~{} tokens, {}% of {}'s context window ::", bundle.total_tokens(), bundle.total_tokens() * 100 / model.context_window, model.name);
+                }
+                if let Some(cost) = cost_report(bundle.total_tokens(), &config) {
+                    println!(":: Estimated input cost: {} ::", cost);
+                }
+                if let Some(checksum) = &checksum {
+                    println!(":: SHA-256 {} ::", checksum);
+                }
             }
         }
-        Err(e) => { // Failed to copy to clipboard
-            eprintln!("Failed to copy to clipboard: {}", e);
+        Err(failures) => { // Failed to write to some of the destinations
+            for (destination, e) in &failures {
+                eprintln!("Failed to copy to {}: {}", destination.describe(), e);
+            }
+            if config.porcelain {
+                std::process::exit(EXIT_OUTPUT_FAILED);
+            }
+            // Stdout itself failed, or the bundle already went there along with the failed destinations.
+            if to_stdout {
+                std::process::exit(EXIT_OUTPUT_FAILED);
+            }
+            let failed_names = failures.iter().map(|(destination, _)| destination.describe()).collect::<Vec<_>>().join(", ");
             // Always inform about processed files, then show content for manual copy
-            println!("\nFiles processed (but not copied to clipboard):");
-            for name in &copied_file_names {
+            println!("\nFiles processed (but not copied to {}):", failed_names);
+            for name in bundle.file_names() {
                 println!("{}", name);
             }
-            println!("\n--- Output (not copied to clipboard) ---\n");
-            println!("{}", formatted_content);
+            println!("\n--- Output (not copied to {}) ---\n", failed_names);
+            if let Err(e) = output::write_bundle(&bundle, &Destination::Stdout, &config) {
+                eprintln!("Failed to write output: {}", e);
+            }
+            std::process::exit(EXIT_OUTPUT_FAILED);
         }
     }
+
+    if written && config.token_tree {
+        // Keep stdout clean when it carries the bundle or porcelain records.
+        let to_stderr = config.porcelain || to_stdout;
+        let color = env::var_os("NO_COLOR").is_none() && if to_stderr { io::stderr().is_terminal() } else { io::stdout().is_terminal() };
+        let tree = token_tree::render(&bundle, color);
+        if to_stderr { eprint!("{}", tree) } else { print!("{}", tree) }
+    }
+
+    if written
+        && let Some(mode) = config.preview
+        && let Err(e) = preview::show(&bundle, mode, config.use_xml)
+    {
+        eprintln!("Warning: could not show the preview: {}", e);
+    }
+    report_timings();
+}
+
+// Records the files this run emitted, once they were written, so an aborted, capped or failed run
+// leaves them to be sent again by the next --changed-since-last run.
+fn save_manifest(previous: Option<&BTreeMap<String, u64>>, bundle: &Bundle, config: &Config) {
+    if let Some(previous) = previous
+        && let Err(e) = manifest::save(previous, bundle)
+        && config.verbose
+    {
+        eprintln!("Warning: could not save run manifest: {}", e);
+    }
+}
+
+// --timings: the phase breakdown on stderr, so stdout stays clean for the bundle or porcelain records.
+fn report_timings() {
+    if let Some(report) = timings::finish() {
+        eprint!("{}", report.format());
+    }
+}
+
+// Adds an output destination once; giving the same one twice would write the bundle twice.
+fn add_destination(config: &mut Config, destination: Destination) {
+    if !config.destinations.contains(&destination) {
+        config.destinations.push(destination);
+    }
+}
+
+// The command-line arguments after the program name. Non-UTF-8 arguments are converted lossily
+// (env::args would panic on them), so such a path is reported as not found instead.
+fn command_line_args() -> Vec<String> {
+    env::args_os().skip(1).map(|arg| arg.to_string_lossy().into_owned()).collect()
 }
 
 fn parse_args() -> Config {
-    let mut config = Config {
-        use_gitignore: false,
-        verbose: false,
-        recursive: false,
-        regex_pattern: None,
-        use_xml: false,
-        paths: Vec::new(),
-    };
+    let mut config = Config::default();
+    let args = command_line_args();
+    apply_config_file(&mut config, &args);
 
-    let mut iter = env::args().skip(1).peekable();
+    let mut filter_expression = None;
+    let mut iter = args.into_iter().peekable();
     while let Some(arg) = iter.next() {
         if arg == "--xml" {
             config.use_xml = true;
+            config.preset = None;
+        } else if arg == "--format" {
+            let value = next_value(&mut iter, "--format");
+            if !set_format(&mut config, &value) {
+                eprintln!("Error: --format must be one of {}, not '{}'", output::FORMATS.join(", "), value);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }
+        } else if arg == "--dedup-content" {
+            config.dedup_content = true;
+        } else if arg == "--bare" {
+            config.bare = true;
+        } else if arg == "--separator" {
+            config.separator = unescape(&next_value(&mut iter, &arg));
+        } else if arg == "--header-prefix" {
+            config.header_prefix = unescape(&next_value(&mut iter, &arg));
+        } else if arg == "--fence-char" {
+            let value = next_value(&mut iter, &arg);
+            config.fence = fence_of(&value).unwrap_or_else(|| {
+                eprintln!("Error: --fence-char must be ` (backtick) or ~ (tilde), not '{}'.", value);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            });
+        } else if arg == "--expand-imports" {
+            config.expand_imports = Some(1);
+        } else if let Some(depth) = arg.strip_prefix("--expand-imports=") {
+            config.expand_imports = Some(parse_count("--expand-imports", depth));
+        } else if arg == "--with-tests" {
+            config.with_tests = true;
+        } else if arg == "--no-tests" {
+            config.no_tests = true;
+        } else if arg == "--context-header" {
+            config.context_header = true;
+        } else if arg == "--stamp" {
+            config.stamp = true;
+        } else if arg == "--checksum" {
+            config.checksum = true;
+        } else if arg == "--deps" {
+            config.deps = true;
+        } else if arg == "--readmes-first" {
+            config.readmes_first = true;
+        } else if arg == "--keep-arg-order" {
+            config.keep_arg_order = true;
+        } else if arg == "--symbol-index" {
+            config.symbol_index = true;
+        } else if arg == "--token-tree" {
+            config.token_tree = true;
+        } else if arg == "--fit" {
+            config.fit = true;
+        } else if arg == "--tiered" {
+            config.tiered = true;
+        } else if arg == "--todos" {
+            config.todos = true;
+        } else if arg == "--query" {
+            config.query = Some(next_value(&mut iter, &arg));
+        } else if arg == "--top" {
+            let value = next_value(&mut iter, &arg);
+            config.top = parse_count(&arg, &value);
+        } else if arg == "--top-tokens" {
+            let value = next_value(&mut iter, &arg);
+            config.top_tokens = Some(parse_count(&arg, &value));
+        } else if arg == "--changed-since-last" {
+            config.changed_since_last = true;
+        } else if arg == "--compare" {
+            config.compare = Some(next_value(&mut iter, &arg));
+        } else if arg == "--at" {
+            config.at = Some(next_value(&mut iter, &arg));
+        } else if arg == "--blame" {
+            config.blame = true;
+        } else if arg == "--no-cache" {
+            config.no_cache = true;
+        } else if arg == "--fresh-clone" {
+            config.fresh_clone = true;
+        } else if arg == "--escape" {
+            let value = next_value(&mut iter, &arg);
+            config.escape = Some(output::Escape::parse(&value).unwrap_or_else(|| {
+                eprintln!("Error: --escape supports 'json', not '{}'.", value);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }));
+        } else if arg == "--edit" {
+            config.edit = true;
+        } else if arg == "--preview" {
+            config.preview = Some(preview::PreviewMode::Head);
+        } else if arg == "--preview=full" {
+            config.preview = Some(preview::PreviewMode::Full);
+        } else if arg == "--hot" {
+            let value = next_value(&mut iter, &arg);
+            let (count, mode) = value.split_once(':').unwrap_or((&value, "churn"));
+            let Some(mode) = hot::HotMode::parse(mode) else {
+                eprintln!("Error: --hot mode must be churn or recent, but got '{}'.", mode);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            config.hot = Some((parse_count(&arg, count), mode));
+        } else if arg == "--sample" {
+            let value = next_value(&mut iter, &arg);
+            let (count, mode) = value.split_once(':').unwrap_or((&value, "first"));
+            let Some(mode) = SampleMode::parse(mode) else {
+                eprintln!("Error: --sample mode must be one of first, largest or smallest, but got '{}'.", mode);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            config.sample = Some((parse_count(&arg, count), mode));
+        } else if arg == "--preview-chars" || arg == "--preview-lines" {
+            let value = next_value(&mut iter, &arg);
+            let (count, mode) = value.split_once(':').unwrap_or((&value, "head"));
+            let Some(mode) = transform::LineLimitMode::parse(mode) else {
+                eprintln!("Error: {} mode must be one of head, tail or split, but got '{}'.", arg, mode);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            let count = parse_count(&arg, count);
+            config.preview_length = if arg == "--preview-chars" {
+                preview::PreviewLength::Chars(count)
+            } else {
+                preview::PreviewLength::Lines(count)
+            };
+            config.preview_mode = mode;
+        } else if arg == "--ignore-file" {
+            let name = next_value(&mut iter, &arg);
+            if name.contains(['/', '\\']) {
+                eprintln!("Error: --ignore-file takes a file name like .dockerignore (read in every directory), not a path.");
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }
+            config.ignore_files.push(name);
+        } else if arg == "--ignore-from" {
+            let path = PathBuf::from(next_value(&mut iter, &arg));
+            add_ignore_from(&mut config, path);
+        } else if arg == "--only-from" {
+            config.only_from = Some(PathBuf::from(next_value(&mut iter, &arg)));
+        } else if arg == "--include-generated" {
+            config.include_generated = true;
+        } else if arg == "--no-auto-skip" {
+            config.auto_skip = false;
+        } else if arg == "--no-submodules" {
+            config.submodules = false;
+        } else if arg == "--skip-empty" {
+            config.skip_empty = Some(true);
+        } else if arg == "--no-skip-empty" {
+            config.skip_empty = Some(false);
+        } else if arg == "--owner" {
+            let owner = next_value(&mut iter, &arg);
+            // "org/team" and "user" mean the same as "@org/team" and "@user"; emails are kept as given.
+            config.owner = Some(if owner.contains('@') { owner } else { format!("@{}", owner) });
+        } else if arg == "--anonymize-paths" {
+            config.anonymize_paths = Some(Anonymizer::from_env());
+        } else if arg == "--pseudonymize" {
+            config.pseudonymize = true;
+        } else if arg == "--pseudonymize-pattern" {
+            let pattern = next_value(&mut iter, &arg);
+            match Regex::new(&pattern) {
+                Ok(regex) => config.pseudonym_patterns.push(regex),
+                Err(e) => {
+                    eprintln!("Error: invalid --pseudonymize-pattern '{}': {}", pattern, e);
+                    print_usage();
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+            config.pseudonymize = true;
+        } else if arg == "--list-excluded" {
+            config.list_excluded = true;
+        } else if arg == "--trace-filters" {
+            config.trace_filters = true;
+        } else if arg == "--why" {
+            let value = next_value(&mut iter, &arg);
+            match fs::canonicalize(&value) {
+                Ok(path) => config.why = Some(path),
+                Err(e) => {
+                    eprintln!("Error: --why '{}': {}", value, e);
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+        } else if arg == "--porcelain" {
+            config.porcelain = true;
+        } else if arg == "--clipboard" {
+            add_destination(&mut config, Destination::Clipboard);
+        } else if arg == "--primary" {
+            add_destination(&mut config, Destination::Primary);
+        } else if arg == "--rich" {
+            config.rich = true;
+        } else if arg == "--slot" {
+            let name = next_value(&mut iter, &arg);
+            if !slots::is_valid_name(&name) {
+                eprintln!("Error: --slot names may only contain letters, digits, '-', '_' and '.' (not first), but got '{}'.", name);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }
+            config.slot = Some(name);
+        } else if arg == "--crate" {
+            let value = next_value(&mut iter, &arg);
+            config.crates.extend(value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string));
+        } else if arg == "--timings" {
+            config.timings = true;
+        } else if arg == "--root-headers" {
+            config.root_headers = true;
+        } else if arg == "--per-dir-output" {
+            config.per_dir_output = Some(PathBuf::from(next_value(&mut iter, &arg)));
+        } else if arg == "--stdout" {
+            add_destination(&mut config, Destination::Stdout);
+        } else if arg == "-o" || arg == "--output" {
+            add_destination(&mut config, Destination::File(PathBuf::from(next_value(&mut iter, &arg))));
+        } else if arg == "--max-total-size" {
+            let value = next_value(&mut iter, &arg);
+            config.max_total_size = if value == "none" { None } else { Some(parse_size_arg(&arg, &value)) };
+        } else if arg == "--max-files" {
+            let value = next_value(&mut iter, &arg);
+            let (count, policy) = value.split_once(':').unwrap_or((&value, "prompt"));
+            let Some(policy) = OverflowPolicy::parse(policy) else {
+                eprintln!("Error: the --max-files policy must be one of error, prompt, keep-largest or keep-by-arg-order, but got '{}'.", policy);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            config.max_files = Some((parse_count(&arg, count), policy));
+        } else if arg == "--min-file-size" {
+            config.min_file_size = Some(parse_size_arg(&arg, &next_value(&mut iter, &arg)));
+        } else if arg == "--max-file-size" {
+            config.max_file_size = Some(parse_size_arg(&arg, &next_value(&mut iter, &arg)));
+        } else if arg == "--size" {
+            let value = next_value(&mut iter, &arg);
+            let Some((min, max)) = parse_size_range(&value) else {
+                eprintln!("Error: --size expects a range like 1kb..200kb, 1kb.. or ..200kb, but got '{}'.", value);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            config.min_file_size = min;
+            config.max_file_size = max;
+        } else if arg == "--max-total-tokens" {
+            let value = next_value(&mut iter, &arg);
+            config.max_total_tokens = if value == "none" { None } else { Some(parse_count(&arg, &value)) };
+        } else if arg == "--budget" {
+            let value = next_value(&mut iter, &arg);
+            config.budget = match budget::parse(&value) {
+                Ok(shares) => shares,
+                Err(e) => {
+                    eprintln!("Error: invalid --budget '{}': {}", value, e);
+                    print_usage();
+                    std::process::exit(EXIT_ERROR);
+                }
+            };
+        } else if arg == "--model" {
+            let value = next_value(&mut iter, &arg);
+            config.model = tokens::find_model(&value);
+            if config.model.is_none() {
+                let known: Vec<&str> = tokens::MODELS.iter().map(|model| model.name).collect();
+                eprintln!("Error: unknown --model '{}' (known: {})", value, known.join(", "));
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            }
+        } else if arg == "--embed-images" {
+            config.embed_images = true;
+        } else if arg == "--normalize" {
+            config.normalize = true;
+        } else if arg == "--replace" {
+            let rule = next_value(&mut iter, &arg);
+            match transform::Rewrite::parse(&rule) {
+                Ok(rewrite) => config.rewrites.push(rewrite),
+                Err(e) => {
+                    eprintln!("Error: invalid --replace '{}': {}", rule, e);
+                    print_usage();
+                    std::process::exit(EXIT_ERROR);
+                }
+            }
+        } else if arg == "--pipe-through" {
+            let command = next_value(&mut iter, &arg);
+            config.pipe_through.push(plugin::External { command, dir: None });
+        } else if arg == "--transform-plugin" {
+            let command = next_value(&mut iter, &arg);
+            config.transform_plugins.push(Box::new(plugin::External { command, dir: None }));
+        } else if arg == "--format-plugin" {
+            let command = next_value(&mut iter, &arg);
+            config.formatter = Some(Box::new(plugin::External { command, dir: None }));
+        } else if arg == "--strip-imports" {
+            config.strip_imports = true;
+        } else if arg == "--minify-data" {
+            config.minify_data = true;
+        } else if arg == "--tab-width" {
+            let value = next_value(&mut iter, &arg);
+            config.tab_width = parse_count(&arg, &value);
+        } else if arg == "--max-line-length" {
+            let value = next_value(&mut iter, &arg);
+            config.max_line_length = Some(parse_count(&arg, &value));
+        } else if arg == "--max-lines" {
+            let value = next_value(&mut iter, &arg);
+            let (count, mode) = value.split_once(':').unwrap_or((&value, "head"));
+            let Some(mode) = transform::LineLimitMode::parse(mode) else {
+                eprintln!("Error: --max-lines mode must be one of head, tail or split, but got '{}'.", mode);
+                print_usage();
+                std::process::exit(EXIT_ERROR);
+            };
+            config.max_lines = Some((parse_count(&arg, count), mode));
+        } else if arg == "--tail-logs" {
+            let value = next_value(&mut iter, &arg);
+            config.tail_logs = Some(parse_count(&arg, &value));
+        } else if arg == "--summarize-over" {
+            let value = next_value(&mut iter, &arg);
+            config.summarize_over = Some(parse_count(&arg, &value));
+        } else if arg == "--table-preview" {
+            let value = next_value(&mut iter, &arg);
+            config.table_preview = Some(parse_count(&arg, &value));
         } else if arg == "-R" {
             if let Some(pattern) = iter.next() {
-                if pattern.starts_with('-') && pattern.len() > 1 && pattern.chars().nth(1).map_or(false, |c| c.is_alphabetic() && c != 'R') {
+                if pattern.starts_with('-') && pattern.len() > 1 && pattern.chars().nth(1).is_some_and(|c| c.is_alphabetic() && c != 'R') {
                     eprintln!("Error: -R flag requires a regex pattern, but got '{}'. Did you forget to provide a pattern or quote it?", pattern);
                     print_usage();
-                    std::process::exit(1);
+                    std::process::exit(EXIT_INVALID_FILTER);
                 }
                 config.regex_pattern = Some(pattern);
                 config.recursive = true;
             } else {
                 eprintln!("Error: -R flag requires a regex pattern.");
                 print_usage();
-                std::process::exit(1);
+                std::process::exit(EXIT_INVALID_FILTER);
             }
+        } else if arg == "--filter" {
+            // Parsed after the loop, once the case flags are known.
+            filter_expression = Some(next_value(&mut iter, &arg));
+        } else if arg == "--lang" {
+            // Extensions work as aliases: --lang py,ts is --lang python,typescript.
+            config.languages = next_value(&mut iter, &arg)
+                .split(',')
+                .map(|name| name.trim().trim_start_matches('.').to_lowercase())
+                .filter(|name| !name.is_empty())
+                .map(|name| match get_language_from_extension(&format!("file.{}", name)) {
+                    "" => name,
+                    language => language.to_string(),
+                })
+                .collect();
+        } else if arg == "--ignore-case" {
+            config.case = ignore::Case::Insensitive;
+        } else if arg == "--smart-case" {
+            config.case = ignore::Case::Smart;
+        } else if arg == "--profile" {
+            // Applied with the config file before the other flags.
+            next_value(&mut iter, &arg);
+        } else if arg == "--no-config" {
+        } else if arg == "-h" || arg == "--help" {
+            print_usage();
+            std::process::exit(0);
         } else if arg.starts_with('-') && arg.len() > 1 {
             for char_code in arg.chars().skip(1) {
                 match char_code {
@@ -173,7 +1482,7 @@ fn parse_args() -> Config {
                     _ => {
                         eprintln!("Unknown flag component in '{}': -{}", arg, char_code);
                         print_usage();
-                        std::process::exit(1);
+                        std::process::exit(EXIT_ERROR);
                     }
                 }
             }
@@ -182,26 +1491,517 @@ fn parse_args() -> Config {
         } else {
             eprintln!("Unknown or malformed argument: {}", arg);
             print_usage();
-            std::process::exit(1);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+    if config.destinations.is_empty() && config.per_dir_output.is_none() {
+        config.destinations.push(Destination::Clipboard);
+    }
+    if config.rich && !clipboard::supports_rich() {
+        eprintln!("Note: --rich needs macOS or Windows; only plain text is copied here.");
+        config.rich = false;
+    }
+    if config.porcelain && (config.verbose || config.destinations.contains(&Destination::Stdout)) {
+        eprintln!("Error: --porcelain owns stdout and cannot be combined with -v or --stdout.");
+        print_usage();
+        std::process::exit(EXIT_ERROR);
+    }
+    if let Some(expression) = filter_expression {
+        match filter::Filter::parse(&expression, config.case) {
+            Ok(filter) => config.filter = Some(filter),
+            Err(e) => {
+                eprintln!("Error: invalid --filter '{}': {}", expression, e);
+                print_usage();
+                std::process::exit(EXIT_INVALID_FILTER);
+            }
+        }
+    }
+    if !config.crates.is_empty() {
+        add_crate_paths(&mut config);
+    }
+    if let Some(list) = &config.only_from {
+        if !config.paths.is_empty() {
+            eprintln!("Error: --only-from includes exactly the files it lists, so it cannot be combined with paths on the command line.");
+            print_usage();
+            std::process::exit(EXIT_ERROR);
         }
+        config.paths = read_only_from(list);
     }
     config
 }
 
-fn process_path(
-    path_str: &str,
-    formatted_content: &mut String,
-    file_index: &mut usize,
-    successful_files: &mut usize,
-    config: &Config,
-    compiled_regex: &Option<Regex>,
-    copied_file_names: &mut Vec<String>, // Added parameter
-) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(path_str);
-    let absolute_path = fs::canonicalize(path).map_err(|e| format!("Path error for '{}': {}. Ensure it exists and is accessible.", path_str, e))?;
-
-
-    if absolute_path.is_file() {
+// --crate: adds the manifest, build script and src/tests/benches/examples of each named member of the enclosing
+// Cargo workspace to the paths, and traverses them recursively.
+fn add_crate_paths(config: &mut Config) {
+    let Some(root) = env::current_dir().ok().and_then(|cwd| workspace::find_root(&cwd)) else {
+        eprintln!("Error: --crate needs a Cargo workspace, but no Cargo.toml with a [workspace] table was found here or above.");
+        std::process::exit(EXIT_ERROR);
+    };
+    let members = workspace::members(&root);
+    for name in &config.crates {
+        let Some(member) = members.iter().find(|member| member.name == *name) else {
+            let names: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+            eprintln!("Error: the workspace at {} has no member crate '{}'; its members are: {}.", display_relative(&root), name, names.join(", "));
+            std::process::exit(EXIT_ERROR);
+        };
+        config.paths.extend(workspace::crate_paths(member).iter().map(|path| display_relative(path)));
+    }
+    config.recursive = true;
+}
+
+// Applies the project's .toprompt.toml and the --profile it names, unless --no-config is given.
+fn apply_config_file(config: &mut Config, args: &[String]) {
+    let profile = args.iter().position(|arg| arg == "--profile").and_then(|i| args.get(i + 1)).map(String::as_str);
+    let config_path = if args.iter().any(|arg| arg == "--no-config") { None } else { config_file::find() };
+    let Some(config_path) = config_path else {
+        if let Some(profile) = profile {
+            eprintln!("Error: --profile {} needs a {} file (and no --no-config).", profile, config_file::FILE_NAME);
+            std::process::exit(EXIT_ERROR);
+        }
+        return;
+    };
+    let config_dir = config_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    let settings = match config_file::load(&config_path) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Error: invalid {}: {}", config_path.display(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    if let Err(e) = settings.apply(config, &config_dir, profile) {
+        eprintln!("Error: {} in {}", e, config_path.display());
+        std::process::exit(EXIT_ERROR);
+    }
+}
+
+// Applies a --format name (one of output::FORMATS); false if it is unknown.
+fn set_format(config: &mut Config, name: &str) -> bool {
+    if !output::FORMATS.contains(&name) {
+        return false;
+    }
+    config.preset = output::Preset::parse(name);
+    // Repomix's layout is XML; the others are markdown.
+    config.use_xml = name == "xml" || name == "repomix";
+    true
+}
+
+// Reads an --ignore-from exclusion list, exiting if it can't be read.
+fn add_ignore_from(config: &mut Config, path: PathBuf) {
+    match fs::read_to_string(&path) {
+        Ok(contents) => config.ignore_from.push((path, contents)),
+        Err(e) => {
+            eprintln!("Error: could not read --ignore-from '{}': {}", path.display(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+// Reads an --only-from list: one path per line (relative to the working directory), with blank lines and
+// `#` comments ignored. Exits if the list can't be read or any entry is not an existing file.
+fn read_only_from(list: &Path) -> Vec<String> {
+    let contents = match fs::read_to_string(list) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: could not read --only-from '{}': {}", list.display(), e);
+            std::process::exit(EXIT_ERROR);
+        }
+    };
+    let entries: Vec<String> =
+        contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string).collect();
+    let problems: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| match fs::metadata(entry) {
+            Ok(metadata) if metadata.is_file() => None,
+            Ok(_) => Some(format!("  {} (not a file)", entry)),
+            Err(e) => Some(format!("  {} ({})", entry, e)),
+        })
+        .collect();
+    if !problems.is_empty() {
+        eprintln!("Error: {} of the {} entries in --only-from '{}' cannot be included:", problems.len(), entries.len(), list.display());
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        std::process::exit(EXIT_ERROR);
+    }
+    if entries.is_empty() {
+        eprintln!("Error: --only-from '{}' lists no files.", list.display());
+        std::process::exit(EXIT_ERROR);
+    }
+    entries
+}
+
+// Expands \n, \t and \\ in a flag value, so separators can span lines.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('\\') | None => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+        }
+    }
+    out
+}
+
+// The three-character fence for a --fence-char value.
+fn fence_of(value: &str) -> Option<String> {
+    match value {
+        "`" | "backtick" => Some("```".to_string()),
+        "~" | "tilde" => Some("~~~".to_string()),
+        _ => None,
+    }
+}
+
+// Takes the value that must follow `flag`, exiting with usage information if it is missing.
+fn next_value(iter: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    match iter.next() {
+        Some(value) => value,
+        None => {
+            eprintln!("Error: {} flag requires a value.", flag);
+            print_usage();
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+fn parse_count(flag: &str, value: &str) -> usize {
+    match value.parse() {
+        Ok(n) => n,
+        Err(_) => {
+            eprintln!("Error: {} expects a non-negative number, but got '{}'.", flag, value);
+            print_usage();
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+fn parse_size_arg(flag: &str, value: &str) -> u64 {
+    match parse_size(value) {
+        Some(size) => size,
+        None => {
+            eprintln!("Error: {} expects a size like 500kb, 10mb or 1gb, but got '{}'.", flag, value);
+            print_usage();
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+// Parses size ranges such as `1kb..200kb`, `1kb..` or `..200kb` into (min, max); None unless at least one
+// bound is given and min <= max.
+fn parse_size_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (min, max) = value.split_once("..")?;
+    let bound = |bound: &str| if bound.trim().is_empty() { Some(None) } else { parse_size(bound).map(Some) };
+    let (min, max) = (bound(min)?, bound(max)?);
+    let ordered = min.zip(max).is_none_or(|(min, max)| min <= max);
+    ((min.is_some() || max.is_some()) && ordered).then_some((min, max))
+}
+
+// Parses sizes such as `2048`, `500kb`, `10MB` or `1.5g` (binary multiples).
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_ascii_lowercase();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    (number >= 0.0).then_some((number * multiplier as f64) as u64)
+}
+
+// The config file's [rules] that apply to `path`, in glob order.
+fn matching_rules<'a>(path: &'a Path, config: &'a Config) -> impl Iterator<Item = &'a config_file::FileRule> {
+    config.file_rules.iter().filter(move |rule| rule.matches(path))
+}
+
+// Why the size limits or an `exclude`/`max_size` in [rules] rule the file out, if they do.
+fn policy_exclusion(path: &Path, config: &Config) -> Option<String> {
+    if let Some(reason) = outside_size_range(path, config) {
+        return Some(reason);
+    }
+    if let Some(rule) = matching_rules(path, config).find(|rule| rule.exclude) {
+        return Some(format!("excluded by [rules.\"{}\"] in {}", rule.glob.pattern, config_file::FILE_NAME));
+    }
+    let rule = matching_rules(path, config).filter(|rule| rule.max_size.is_some()).last()?;
+    let (max, size) = (rule.max_size?, fs::metadata(path).ok()?.len());
+    (size > max).then(|| format!("{} is over the max_size {} of [rules.\"{}\"]", format_size(size), format_size(max), rule.glob.pattern))
+}
+
+// Why --min-file-size/--max-file-size rule the file out, if they do.
+fn outside_size_range(path: &Path, config: &Config) -> Option<String> {
+    if config.min_file_size.is_none() && config.max_file_size.is_none() {
+        return None;
+    }
+    let size = fs::metadata(path).ok()?.len();
+    match (config.min_file_size, config.max_file_size) {
+        (Some(min), _) if size < min => Some(format!("{} is under --min-file-size {}", format_size(size), format_size(min))),
+        (_, Some(max)) if size > max => Some(format!("{} is over --max-file-size {}", format_size(size), format_size(max))),
+        _ => None,
+    }
+}
+
+// Formats a byte count for humans, e.g. 1.5 MB.
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+// The position of the first `priority` glob matching the entry's file, if any.
+fn priority_rank(entry: &BundleEntry, config: &Config) -> Option<usize> {
+    let path = entry.path.as_deref()?;
+    config.priority.iter().position(|glob| glob.matches(path))
+}
+
+// Shrinks the biggest files with escalating reducers (strip comments, then remove blank lines, then outline)
+// until the bundle fits --max-total-size/--max-total-tokens. Returns the files each reducer was applied to.
+fn fit_to_budget(bundle: &mut Bundle, config: &Config) -> Vec<(&'static str, Vec<String>)> {
+    let fits = |bundle: &Bundle| {
+        config.max_total_size.is_none_or(|max| bundle.total_size() as u64 <= max)
+            && config.max_total_tokens.is_none_or(|max| bundle.total_tokens() <= max)
+    };
+    type Reducer = fn(&Path, &str) -> Option<String>;
+    let reducers: [(&str, Reducer); 3] = [
+        ("comments stripped", |path, contents| Some(transform::strip_comments(contents, path))),
+        ("blank lines removed", |_, contents| Some(transform::remove_blank_lines(contents))),
+        ("outlined", |path, _| symbols::outline(path)),
+    ];
+    // Only plain file segments are rebuilt: --compare diffs, --tiered outlines and images (which keep no contents)
+    // are left alone. Telling them apart must not re-run a --format-plugin.
+    let rebuildable = |entry: &BundleEntry| {
+        entry.path.is_some()
+            && config.compare.is_none()
+            && !entry.contents.is_empty()
+            && entry.segment != format_outline(&entry.display_name, &entry.contents, config)
+            && priority_rank(entry, config).is_none()
+    };
+    let mut applied = Vec::new();
+    for (name, reduce) in reducers {
+        if fits(bundle) {
+            break;
+        }
+        let mut order: Vec<usize> = (0..bundle.entries.len()).filter(|&i| rebuildable(&bundle.entries[i])).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(bundle.entries[i].tokens));
+        let mut files = Vec::new();
+        for i in order {
+            if fits(bundle) {
+                break;
+            }
+            let entry = &bundle.entries[i];
+            let Some(path) = entry.path.clone() else {
+                continue;
+            };
+            let filepath_str = path.to_string_lossy();
+            let Some(reduced) = reduce(&path, &entry.contents) else {
+                continue;
+            };
+            if tokens::estimate_tokens(&reduced) >= tokens::estimate_tokens(&entry.contents) {
+                continue;
+            }
+            let segment = if name == "outlined" {
+                format_outline(&entry.display_name, &reduced, config)
+            } else {
+                format_segment(&entry.display_name, &filepath_str, &reduced, config)
+            };
+            let entry = &mut bundle.entries[i];
+            entry.tokens = tokens::estimate_tokens(&segment);
+            entry.segment = segment;
+            entry.contents = reduced;
+            files.push(entry.display_name.clone());
+        }
+        if !files.is_empty() {
+            applied.push((name, files));
+        }
+    }
+    applied
+}
+
+// "~$0.42 for claude-3.5, ~$0.35 for gpt-4o" from the configured prices, each counted with that model's
+// tokenizer ratio when it is a known --model preset; None without prices.
+fn cost_report(total_tokens: usize, config: &Config) -> Option<String> {
+    if config.prices.is_empty() {
+        return None;
+    }
+    let costs: Vec<String> = config
+        .prices
+        .iter()
+        .map(|(name, price)| {
+            let tokens = tokens::convert(total_tokens, tokens::find_model(name));
+            let cost = tokens as f64 * price / 1_000_000.0;
+            if cost > 0.0 && cost < 0.01 { format!("<$0.01 for {}", name) } else { format!("~${:.2} for {}", cost, name) }
+        })
+        .collect();
+    Some(costs.join(", "))
+}
+
+// Applies --max-files: errors, asks, or drops the files over the cap by its policy. Returns the dropped names.
+fn cap_file_count(bundle: &mut Bundle, config: &Config) -> Vec<String> {
+    let Some((max, policy)) = config.max_files else {
+        return Vec::new();
+    };
+    let count = bundle.file_count();
+    if count <= max {
+        return Vec::new();
+    }
+    let pinned: Vec<bool> = bundle.entries.iter().map(|entry| priority_rank(entry, config).is_some()).collect();
+    let keep: Vec<bool> = match policy {
+        OverflowPolicy::Error | OverflowPolicy::Prompt => {
+            eprintln!("Warning: {} files were selected, more than --max-files {}.", count, max);
+            if policy == OverflowPolicy::Prompt && io::stdin().is_terminal() {
+                eprint!("Include all {} files anyway? (y/n): ", count);
+                let _ = io::stderr().flush();
+                let mut response = String::new();
+                if io::stdin().read_line(&mut response).is_ok() && response.trim().to_lowercase().starts_with('y') {
+                    return Vec::new();
+                }
+            } else {
+                eprintln!("Narrow the selection, raise --max-files, or pass a policy that drops files: --max-files {}:keep-largest or {}:keep-by-arg-order.", max, max);
+            }
+            std::process::exit(EXIT_TOO_LARGE);
+        }
+        OverflowPolicy::KeepByArgOrder | OverflowPolicy::KeepLargest => {
+            // `priority` files are always kept and count towards the cap.
+            let mut candidates: Vec<usize> = (0..count).filter(|&i| !pinned[i]).collect();
+            if policy == OverflowPolicy::KeepLargest {
+                candidates.sort_by_key(|&i| std::cmp::Reverse(bundle.entries[i].contents.len()));
+            }
+            let mut keep = pinned.clone();
+            for &i in candidates.iter().take(max.saturating_sub(pinned.iter().filter(|&&pinned| pinned).count())) {
+                keep[i] = true;
+            }
+            keep
+        }
+    };
+    let mut dropped = Vec::new();
+    let mut keep = keep.into_iter();
+    bundle.entries.retain(|entry| {
+        let kept = keep.next().unwrap_or(true);
+        if !kept {
+            if config.verbose {
+                println!("Over --max-files: {}", entry.display_name);
+            }
+            dropped.push(entry.display_name.clone());
+        }
+        kept
+    });
+    dropped
+}
+
+// Stops (or asks, when interactive) before emitting a bundle larger than the configured caps.
+// Reports why a streamed bundle stopped short and exits. A file destination is left as it was; what already
+// went to stdout cannot be taken back.
+fn exit_stream_stopped(stop: output::Stop, config: &Config) -> ! {
+    match stop {
+        output::Stop::TooLarge(reason) => {
+            eprintln!("Warning: the bundle is too large: {}.", reason);
+            if config.destinations.contains(&Destination::Stdout) {
+                eprintln!("The output written to stdout so far is incomplete.");
+            }
+            eprintln!("Narrow the selection, or raise the limit with --max-total-size/--max-total-tokens (\"none\" disables it).");
+            std::process::exit(EXIT_TOO_LARGE);
+        }
+        output::Stop::Failed(e) => {
+            eprintln!("Failed to copy to {}: {}", config.destinations[0].describe(), e);
+            std::process::exit(EXIT_OUTPUT_FAILED);
+        }
+    }
+}
+
+// Whether a pass after the traversal reads the entries' unformatted contents (see Bundle::keep_contents).
+fn needs_contents(config: &Config) -> bool {
+    config.context_header
+        || config.bare
+        || config.fit
+        || config.pseudonymize
+        || config.todos
+        || config.changed_since_last
+        || config.query.is_some()
+        || matches!(config.max_files, Some((_, OverflowPolicy::KeepLargest)))
+}
+
+fn enforce_size_cap(bundle: &Bundle, config: &Config) {
+    let total_size = bundle.total_size() as u64;
+    let total_tokens = bundle.total_tokens();
+    let mut exceeded = Vec::new();
+    if let Some(max) = config.max_total_size
+        && total_size > max
+    {
+        exceeded.push(format!("{} exceeds --max-total-size {}", format_size(total_size), format_size(max)));
+    }
+    if let Some(max) = config.max_total_tokens
+        && total_tokens > max
+    {
+        exceeded.push(format!("~{} tokens exceeds --max-total-tokens {}", total_tokens, max));
+    }
+    if exceeded.is_empty() {
+        return;
+    }
+
+    eprintln!("Warning: the bundle is too large: {}.", exceeded.join("; "));
+    eprintln!("Biggest contributors:");
+    let mut by_size: Vec<&BundleEntry> = bundle.entries.iter().collect();
+    by_size.sort_by_key(|entry| std::cmp::Reverse(entry.segment.len()));
+    for entry in by_size.iter().take(10) {
+        eprintln!("  {:>10}  ~{:>8} tokens  {}", format_size(entry.segment.len() as u64), entry.tokens, entry.display_name);
+    }
+
+    if io::stdin().is_terminal() {
+        eprint!("Continue anyway? (y/n): ");
+        let _ = io::stderr().flush();
+        let mut response = String::new();
+        if io::stdin().read_line(&mut response).is_ok() && response.trim().to_lowercase().starts_with('y') {
+            return;
+        }
+    } else {
+        eprintln!("Narrow the selection, or raise the limit with --max-total-size/--max-total-tokens (\"none\" disables it).");
+    }
+    std::process::exit(EXIT_TOO_LARGE);
+}
+
+fn process_path(
+    path_str: &str,
+    bundle: &mut Bundle,
+    config: &Config,
+    compiled_regex: &Option<Regex>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(repo) = remote::RemoteRepo::parse(path_str) {
+        let clone_dir = remote::fetch(&repo, config.fresh_clone, config.verbose)?;
+        bundle.display_root = Some((clone_dir.clone(), repo.label()));
+        let result = process_path(&clone_dir.to_string_lossy(), bundle, config, compiled_regex);
+        bundle.display_root = None;
+        return result;
+    }
+    if web::is_url(path_str) {
+        return process_url(path_str, bundle, config);
+    }
+    if let Some(git_ref) = &config.at
+        && bundle.display_root.is_none()
+    {
+        return process_path_at(path_str, git_ref, bundle, config, compiled_regex);
+    }
+
+    let path = Path::new(path_str);
+    let absolute_path = fs::canonicalize(path).map_err(|e| format!("Path error for '{}': {}. Ensure it exists and is accessible.", path_str, e))?;
+
+
+    if absolute_path.is_file() {
         if let Some(rgx) = compiled_regex {
             let normalized_path_str_to_match = path_str.replace('\\', "/");
             if !rgx.is_match(&normalized_path_str_to_match) {
@@ -211,42 +2011,61 @@ fn process_path(
                         normalized_path_str_to_match, path_str
                     );
                 }
+                skip_unmatched(&absolute_path, &format!("-R '{}' does not match '{}'", rgx, normalized_path_str_to_match), bundle, config);
                 return Ok(());
             }
         }
 
-        match process_file(absolute_path.to_str().unwrap(), config) {
-            Ok((file_content_segment, display_name_str)) => { // Expect tuple
-                if *file_index > 0 {
-                    formatted_content.push_str("\n\n");
+        if let Some(filter) = &config.filter {
+            let normalized_path = path_str.replace('\\', "/");
+            if !filter.matches(&absolute_path, normalized_path.trim_start_matches("./")) {
+                if config.verbose {
+                    println!("Skipping file (--filter did not match): {}", path_str);
                 }
-                formatted_content.push_str(&file_content_segment);
-                *successful_files += 1;
-                *file_index += 1;
-                copied_file_names.push(display_name_str); // Collect display name
+                skip_unmatched(&absolute_path, &format!("--filter '{}' does not match", filter.source), bundle, config);
+                return Ok(());
+            }
+        }
+
+        if !language_selected(&absolute_path, config) {
+            if config.verbose {
+                println!("Skipping file (not in --lang {}): {}", config.languages.join(","), path_str);
             }
-            Err(e) => return Err(e),
+            skip_unmatched(&absolute_path, &format!("not in --lang {}", config.languages.join(",")), bundle, config);
+            return Ok(());
+        }
+        if let Some(reason) = policy_exclusion(&absolute_path, config) {
+            if config.verbose {
+                println!("Skipping file ({}): {}", reason, path_str);
+            }
+            bundle.exclude(config, &absolute_path, &reason);
+            return Ok(());
+        }
+        if config.no_tests
+            && let Some(reason) = test_files::test_reason(&absolute_path, &path_str.replace('\\', "/"))
+        {
+            if config.verbose {
+                println!("Skipping test file (--no-tests): {}", path_str);
+            }
+            bundle.exclude(config, &absolute_path, &format!("--no-tests: {}", reason));
+            return Ok(());
+        }
+
+        if !is_owned(&absolute_path, bundle, config) {
+            return Ok(());
+        }
+
+        let before = bundle.file_count();
+        process_file(&absolute_path, false, bundle, config)?;
+        if bundle.file_count() > before {
+            bundle.trace(config, &absolute_path, true, "given on the command line");
         }
     } else if absolute_path.is_dir() {
-        let gitignore = if config.use_gitignore {
-            let mut gitignore = GitIgnore::with_defaults(&absolute_path);
-            let loaded = load_gitignore(&absolute_path);
-            gitignore.merge(loaded);
-            gitignore
-        } else {
-            GitIgnore::empty()
-        };
-        process_directory(
-            &absolute_path,
-            &absolute_path,
-            formatted_content,
-            file_index,
-            successful_files,
-            config,
-            &gitignore,
-            compiled_regex,
-            copied_file_names, // Pass it down
-        )?;
+        let gitignore = base_matcher(config, &absolute_path);
+        bundle.in_directory = true;
+        let result = process_directory(&absolute_path, &absolute_path, bundle, config, &gitignore, compiled_regex);
+        bundle.in_directory = false;
+        result?;
     } else {
         return Err(format!(
             "'{}' (resolved to '{}') is neither a file nor a directory that can be processed",
@@ -258,317 +2077,1102 @@ fn process_path(
     Ok(())
 }
 
+// The rules that apply before any ignore file is read: built-in defaults (-i), config excludes and --ignore-from lists.
+fn base_matcher(config: &Config, root: &Path) -> ignore::Matcher {
+    let mut matcher = if config.use_gitignore { ignore::Matcher::with_defaults(root) } else { ignore::Matcher::new(root) };
+    matcher.set_case(config.case);
+    if let Some(exclude_base) = &config.exclude_base {
+        matcher.add_patterns(config.exclude.iter().map(String::as_str), exclude_base, EXCLUDE_SOURCE);
+    }
+    for (list, contents) in &config.ignore_from {
+        matcher.add_patterns(contents.lines(), root, &list.display().to_string());
+    }
+    check_matcher(&mut matcher, root);
+    matcher
+}
+
+// Reports the patterns a matcher left out, then compiles it once its ignore files are loaded; failing to
+// would silently ignore nothing.
+fn check_matcher(matcher: &mut ignore::Matcher, dir: &Path) {
+    for invalid in matcher.take_invalid() {
+        eprintln!("Warning: ignoring invalid pattern '{}' ({}:{}): {}", invalid.pattern, invalid.source, invalid.line, invalid.error);
+    }
+    if let Err(e) = matcher.build() {
+        eprintln!("Error: the ignore rules that apply in '{}' are unusable: {}", dir.display(), e);
+        std::process::exit(EXIT_INVALID_FILTER);
+    }
+}
+
+// --at: processes the same path in the tree of `git_ref`, extracted into the cache, keeping the
+// working-tree display names. The path may have been deleted from the working tree since.
+fn process_path_at(
+    path_str: &str,
+    git_ref: &str,
+    bundle: &mut Bundle,
+    config: &Config,
+    compiled_regex: &Option<Regex>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cwd = fs::canonicalize(env::current_dir()?)?;
+    let requested = cwd.join(path_str);
+    let existing = requested.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(&cwd);
+    let existing_canonical = fs::canonicalize(existing)?;
+    let repo = git::toplevel(&existing_canonical)?;
+    let mut relative = existing_canonical.strip_prefix(&repo).unwrap_or(Path::new("")).to_path_buf();
+    // (Extending rather than joining, which would add a trailing slash for an empty remainder.)
+    relative.extend(requested.strip_prefix(existing).unwrap_or(Path::new("")).components());
+    let (tree, commit) = git::export_tree(&repo, git_ref)?;
+    let target = tree.join(&relative);
+    if !target.exists() {
+        return Err(format!("'{}' does not exist at {}", path_str, git_ref).into());
+    }
+    if config.verbose {
+        println!("Reading '{}' as of {} ({})", path_str, git_ref, &commit[..7]);
+    }
+    let note = format!("Files as of git revision {} (commit {}), not the working tree.", git_ref, &commit[..12]);
+    if !bundle.preamble.contains(&note) {
+        bundle.preamble.push(note);
+    }
+
+    // Name files as they would be named in the working directory.
+    bundle.display_root = Some(match cwd.strip_prefix(&repo) {
+        Ok(cwd_relative) => (tree.join(cwd_relative), String::new()),
+        Err(_) => (tree.clone(), display_path(&repo)),
+    });
+    let result = process_path(&target.to_string_lossy(), bundle, config, compiled_regex);
+    bundle.display_root = None;
+    result
+}
+
+// README, README.md, readme.rst, README.ja.md, ...
+fn is_readme(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    name == "readme" || name.starts_with("readme.")
+}
+
 fn process_directory(
     dir_to_process: &Path,
     cmd_arg_base_dir: &Path,
-    formatted_content: &mut String,
-    file_index: &mut usize,
-    successful_files: &mut usize,
+    bundle: &mut Bundle,
     config: &Config,
-    parent_gitignore: &GitIgnore,
+    parent_gitignore: &ignore::Matcher,
     compiled_regex: &Option<Regex>,
-    copied_file_names: &mut Vec<String>, // Added parameter
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if config.use_gitignore {
-        let dir_relative_to_cmd_arg_base = dir_to_process.strip_prefix(cmd_arg_base_dir).unwrap_or(dir_to_process);
-        if parent_gitignore.should_ignore(&dir_relative_to_cmd_arg_base, true, cmd_arg_base_dir) {
-            if config.verbose {
-                println!("Ignoring directory (via .gitignore): {}", dir_to_process.display());
-            }
-            return Ok(());
+    // The matcher only has rules with -i or config excludes.
+    let dir_relative_to_cmd_arg_base = dir_to_process.strip_prefix(cmd_arg_base_dir).unwrap_or(dir_to_process);
+    if let Some(decision) = parent_gitignore.explain(dir_relative_to_cmd_arg_base, true)
+        && decision.ignored
+    {
+        if config.verbose {
+            println!("Ignoring directory (via .gitignore): {}", dir_to_process.display());
         }
+        bundle.exclude(config, dir_to_process, &format!("directory ignored by {}", describe_rule(&decision)));
+        return Ok(());
     }
+    bundle.trace(config, dir_to_process, true, "directory traversed");
 
     let mut current_gitignore = parent_gitignore.clone();
-    if config.use_gitignore && dir_to_process.join(".gitignore").exists() {
-        let new_gitignore = load_gitignore(dir_to_process);
-        current_gitignore.merge(new_gitignore);
-        if config.verbose {
-            println!("Loaded .gitignore from: {}", dir_to_process.join(".gitignore").display());
+    if config.use_gitignore && current_gitignore.add_ignore_file(&dir_to_process.join(".gitignore")) && config.verbose {
+        println!("Loaded .gitignore from: {}", dir_to_process.join(".gitignore").display());
+    }
+    if config.use_gitignore && current_gitignore.add_hgignore_file(&dir_to_process.join(".hgignore")) && config.verbose {
+        println!("Loaded .hgignore from: {}", dir_to_process.join(".hgignore").display());
+    }
+    if config.use_gitignore
+        && !config.include_generated
+        && current_gitignore.add_attributes_file(&dir_to_process.join(".gitattributes"))
+        && config.verbose
+    {
+        println!("Loaded .gitattributes from: {}", dir_to_process.join(".gitattributes").display());
+    }
+    for name in &config.ignore_files {
+        if current_gitignore.add_ignore_file(&dir_to_process.join(name)) && config.verbose {
+            println!("Loaded {} from: {}", name, dir_to_process.join(name).display());
         }
     }
+    check_matcher(&mut current_gitignore, dir_to_process);
 
-    let mut entries: Vec<_> = fs::read_dir(dir_to_process)?
-        .filter_map(|e| e.ok())
-        .collect();
-    entries.sort_by_key(|e| e.path());
+    let mut entries = match &bundle.cache {
+        Some(cache) => cache.list_dir(dir_to_process)?,
+        None => cache::read_dir(dir_to_process)?,
+    };
+    // Sort by the UTF-8 form of the file name so the order is identical on every platform
+    // (OsStr ordering differs between Windows and Unix).
+    entries.sort_by_cached_key(|(path, _)| path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+    if config.readmes_first {
+        // Stable, so several READMEs (README.md, README.ja.md) keep their name order.
+        entries.sort_by_key(|(path, file_type)| file_type.is_dir() || !is_readme(path));
+    }
 
-    let filtered_entries: Vec<_> = entries
-        .into_iter()
-        .filter(|entry| {
-            if !config.use_gitignore {
-                return true;
-            }
-            let entry_abs_path = entry.path();
-            let path_relative_to_cmd_arg_base = entry_abs_path.strip_prefix(cmd_arg_base_dir).unwrap_or(&entry_abs_path);
-            let should_ignore = current_gitignore.should_ignore(&path_relative_to_cmd_arg_base, entry_abs_path.is_dir(), cmd_arg_base_dir);
-            if config.verbose && should_ignore {
-                println!("Ignoring (via .gitignore): {}", path_relative_to_cmd_arg_base.display());
-            }
-            !should_ignore
-        })
-        .collect();
+    let filtered_entries: Vec<_> = timings::time(timings::Phase::Filtering, || {
+        entries
+            .into_iter()
+            .map(|(entry_abs_path, file_type)| {
+                let is_symlink = file_type.is_symlink();
+                let is_dir = if is_symlink { entry_abs_path.is_dir() } else { file_type.is_dir() };
+                let is_file = if is_symlink { entry_abs_path.is_file() } else { file_type.is_file() };
+                (entry_abs_path, is_dir, is_file, is_symlink)
+            })
+            .filter(|(entry_abs_path, is_dir, _, _)| {
+                let path_relative_to_cmd_arg_base = entry_abs_path.strip_prefix(cmd_arg_base_dir).unwrap_or(entry_abs_path);
+                if !current_gitignore.is_ignored(path_relative_to_cmd_arg_base, *is_dir) {
+                    return true;
+                }
+                if config.verbose {
+                    println!("Ignoring (via .gitignore): {}", path_relative_to_cmd_arg_base.display());
+                }
+                if let Some(decision) = current_gitignore.explain(path_relative_to_cmd_arg_base, *is_dir) {
+                    bundle.exclude(config, entry_abs_path, &format!("ignored by {}", describe_rule(&decision)));
+                }
+                false
+            })
+            .collect()
+    });
 
-    if filtered_entries.len() > 10 && dir_to_process == cmd_arg_base_dir {
-        if config.verbose { // Only show confirmation prompt if verbose
-            println!(
-                "\nWarning: Directory '{}' contains {} items (after .gitignore if used).",
-                dir_to_process.display(),
-                filtered_entries.len()
-            );
-            print!("Do you want to process all files in this directory level{}? (y/n): ",
-                if config.recursive {" and its subdirectories (if applicable)"} else {""}
-            );
-            io::stdout().flush()?;
-            let mut response = String::new();
-            io::stdin().read_line(&mut response)?;
-            if !response.trim().to_lowercase().starts_with('y') {
-                println!("Skipping directory '{}'", dir_to_process.display());
-                return Ok(());
-            }
+    // Only show confirmation prompt if verbose
+    if filtered_entries.len() > 10 && dir_to_process == cmd_arg_base_dir && config.verbose {
+        println!(
+            "\nWarning: Directory '{}' contains {} items (after .gitignore if used).",
+            dir_to_process.display(),
+            filtered_entries.len()
+        );
+        print!("Do you want to process all files in this directory level{}? (y/n): ",
+            if config.recursive {" and its subdirectories (if applicable)"} else {""}
+        );
+        io::stdout().flush()?;
+        let mut response = String::new();
+        io::stdin().read_line(&mut response)?;
+        if !response.trim().to_lowercase().starts_with('y') {
+            println!("Skipping directory '{}'", dir_to_process.display());
+            return Ok(());
+        }
+    }
+
+    // Whether each file is selected, decided once: --summarize-over and --sample count the selected files
+    // before the loop below reports and bundles them.
+    let skips: Vec<Option<Skip>> = timings::time(timings::Phase::Filtering, || {
+        filtered_entries
+            .iter()
+            .map(|(entry_abs_path, _, is_file, _)| {
+                let path_relative_to_cmd_arg = entry_abs_path.strip_prefix(cmd_arg_base_dir).unwrap_or(entry_abs_path);
+                let regex_path = path_relative_to_cmd_arg.to_string_lossy().replace('\\', "/");
+                if *is_file { file_skip(entry_abs_path, &regex_path, bundle, config, compiled_regex) } else { None }
+            })
+            .collect()
+    });
+
+    // The selected files, counted only by --summarize-over and --sample.
+    let eligible: Vec<PathBuf> = if config.summarize_over.is_some() || config.sample.is_some() {
+        filtered_entries
+            .iter()
+            .zip(&skips)
+            .filter(|((_, _, is_file, _), skip)| *is_file && skip.is_none())
+            .map(|((entry_abs_path, _, _, _), _)| entry_abs_path.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    // With --summarize-over, a directory holding too many eligible files contributes only a listing of them.
+    let summarize = config.summarize_over.is_some_and(|limit| eligible.len() > limit);
+    // With --sample, the eligible files this directory contributes (eligible is already in name order).
+    let sampled: Option<Vec<PathBuf>> = config.sample.filter(|(count, _)| eligible.len() > *count).map(|(count, mode)| {
+        let mut ranked = eligible.clone();
+        let size = |path: &PathBuf| fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+        match mode {
+            SampleMode::First => {}
+            SampleMode::Largest => ranked.sort_by_key(|path| std::cmp::Reverse(size(path))),
+            SampleMode::Smallest => ranked.sort_by_key(size),
         }
+        ranked.truncate(count);
+        ranked
+    });
+    if summarize {
+        let files: Vec<(String, u64)> = eligible
+            .iter()
+            .map(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                (name, fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0))
+            })
+            .collect();
+        if config.verbose {
+            println!("Listing directory without contents ({} files): {}", files.len(), dir_to_process.display());
+        }
+        bundle.summarized.extend(eligible.iter().cloned());
+        let display_name = anonymize(config, &format!("{}/", display_relative(dir_to_process)));
+        let (segment, contents) = format_listing(&display_name, &files, config);
+        let tokens = tokens::estimate_tokens(&segment);
+        bundle.add_file(BundleEntry {
+            display_name,
+            path: None,
+            contents,
+            segment,
+            tokens,
+        });
     }
 
-    for entry in filtered_entries {
-        let entry_abs_path = entry.path();
-        if entry_abs_path.is_file() {
+    bundle.entries.reserve(filtered_entries.len());
+    for ((entry_abs_path, is_dir, is_file, is_symlink), skip) in filtered_entries.into_iter().zip(skips) {
+        if bundle.stream_stopped() {
+            break;
+        }
+        if is_file {
             let mut process_this_file = true;
-            if let Some(rgx) = compiled_regex {
-                let path_relative_to_cmd_arg = entry_abs_path.strip_prefix(cmd_arg_base_dir).unwrap_or(&entry_abs_path);
-                let path_to_match_str = path_relative_to_cmd_arg.to_string_lossy();
-                let normalized_path_to_match = path_to_match_str.replace('\\', "/");
-
-                if !rgx.is_match(&normalized_path_to_match) {
-                    if config.verbose {
-                        println!(
-                            "Skipping file (regex -R did not match relative path '{}'): {}",
-                            normalized_path_to_match, entry_abs_path.display()
-                        );
+            if let Some(skip) = skip {
+                if config.verbose {
+                    println!("{}: {}", skip.notice, entry_abs_path.display());
+                }
+                match skip.kind {
+                    SkipKind::Unmatched => skip_unmatched(&entry_abs_path, &skip.reason, bundle, config),
+                    SkipKind::Excluded => bundle.exclude(config, &entry_abs_path, &skip.reason),
+                    SkipKind::AutoSkipped => {
+                        bundle.exclude(config, &entry_abs_path, &skip.reason);
+                        bundle.auto_skipped.push(display_relative(&entry_abs_path));
                     }
-                    process_this_file = false;
                 }
+                process_this_file = false;
+            }
+            if process_this_file && summarize {
+                let reason = format!("directory has {} eligible files, over --summarize-over; listed without contents", eligible.len());
+                bundle.trace(config, &entry_abs_path, false, &reason);
+                process_this_file = false;
+            }
+            if process_this_file
+                && let Some(sampled) = &sampled
+                && !sampled.contains(&entry_abs_path)
+            {
+                if config.verbose {
+                    println!("Not sampled: {}", entry_abs_path.display());
+                }
+                bundle.exclude(config, &entry_abs_path, &format!("not among the {} files --sample keeps from its directory's {}", sampled.len(), eligible.len()));
+                process_this_file = false;
             }
 
             if process_this_file {
-                match process_file(entry_abs_path.to_str().unwrap(), config) {
-                    Ok((file_content_segment, display_name_str)) => { // Expect tuple
-                        if *file_index > 0 {
-                            formatted_content.push_str("\n\n");
-                        }
-                        formatted_content.push_str(&file_content_segment);
-                        *successful_files += 1;
-                        *file_index += 1;
-                        copied_file_names.push(display_name_str); // Collect display name
+                let before = bundle.file_count();
+                match process_file(&entry_abs_path, is_symlink, bundle, config) {
+                    Ok(_) if bundle.file_count() > before => {
+                        let reason = match current_gitignore.explain(entry_abs_path.strip_prefix(cmd_arg_base_dir).unwrap_or(&entry_abs_path), false) {
+                            Some(decision) => format!("re-included by {}", describe_rule(&decision)),
+                            None if compiled_regex.is_some() => "matches -R and no ignore rule".to_string(),
+                            None => "no ignore rule matches".to_string(),
+                        };
+                        bundle.trace(config, &entry_abs_path, true, &reason);
                     }
+                    Ok(_) => {}
                     Err(e) => {
                         if config.verbose {
-                           eprintln!("Error processing file '{}': {}", entry_abs_path.display(), e);
+                            eprintln!("Error processing file '{}': {}", entry_abs_path.display(), e);
                         }
+                        bundle.exclude(config, &entry_abs_path, &format!("could not be read: {}", e));
                     }
                 }
             }
-        } else if entry_abs_path.is_dir() {
-            if config.recursive {
-                process_directory(
-                    &entry_abs_path,
-                    cmd_arg_base_dir,
-                    formatted_content,
-                    file_index,
-                    successful_files,
-                    config,
-                    &current_gitignore,
-                    compiled_regex,
-                    copied_file_names, // Pass it down
-                )?;
+        } else if is_dir && config.recursive {
+            let dir_name = entry_abs_path.file_name().unwrap_or_default().to_string_lossy();
+            if config.no_tests && test_files::is_test_dir(&dir_name) {
+                if config.verbose {
+                    println!("Skipping test directory (--no-tests): {}", entry_abs_path.display());
+                }
+                bundle.exclude(config, &entry_abs_path, &format!("--no-tests: {}/ holds tests", dir_name));
+                continue;
             }
+            let is_submodule = entry_abs_path.join(".git").exists();
+            if is_submodule && !config.submodules {
+                if config.verbose {
+                    println!("Skipping git submodule (--no-submodules): {}", entry_abs_path.display());
+                }
+                bundle.exclude(config, &entry_abs_path, "git submodule, and --no-submodules is set");
+                continue;
+            }
+            // A submodule is a repository of its own: the parent's ignore files stop at its boundary.
+            let submodule_gitignore;
+            let gitignore = if is_submodule && config.use_gitignore {
+                submodule_gitignore = base_matcher(config, cmd_arg_base_dir);
+                &submodule_gitignore
+            } else {
+                &current_gitignore
+            };
+            // Paths below a symlinked directory are no longer canonical, so files there must be resolved for deduplication.
+            if is_symlink {
+                bundle.symlinked_dir_depth += 1;
+            }
+            let result = process_directory(&entry_abs_path, cmd_arg_base_dir, bundle, config, gitignore, compiled_regex);
+            if is_symlink {
+                bundle.symlinked_dir_depth -= 1;
+            }
+            result?;
+        } else if is_dir {
+            bundle.exclude(config, &entry_abs_path, "subdirectory, and recursion is off (-r)");
         }
     }
     Ok(())
 }
 
-#[derive(Clone)]
-struct GitIgnore {
-    patterns: Vec<GitIgnorePattern>,
-    effective_base_dir: PathBuf,
+// Why a file found in a directory is left out, with the verbose notice and the reason reported for it.
+struct Skip {
+    kind: SkipKind,
+    notice: String,
+    reason: String,
 }
 
-#[derive(Clone)]
-struct GitIgnorePattern {
-    pattern: String,
-    raw_pattern: String,
-    is_negation: bool,
-    is_directory: bool,
-    is_absolute: bool,
-    contains_slash: bool,
-    defined_in_dir: PathBuf,
+enum SkipKind {
+    // Not selected by -R, --filter or --lang (with --tiered it may still be outlined).
+    Unmatched,
+    // Excluded by a policy, --no-tests or --owner.
+    Excluded,
+    // A lockfile or minified asset, left out unless --no-auto-skip.
+    AutoSkipped,
 }
 
-impl GitIgnore {
-    fn empty() -> Self {
-        GitIgnore {
-            patterns: Vec::new(),
-            effective_base_dir: PathBuf::new(),
-        }
+// Applies the per-file selection rules to a file found in a directory, in the order they are reported.
+fn file_skip(path: &Path, regex_path: &str, bundle: &Bundle, config: &Config, compiled_regex: &Option<Regex>) -> Option<Skip> {
+    let skip = |kind, notice: String, reason: String| Some(Skip { kind, notice, reason });
+    if let Some(rgx) = compiled_regex
+        && !rgx.is_match(regex_path)
+    {
+        let notice = format!("Skipping file (regex -R did not match relative path '{}')", regex_path);
+        return skip(SkipKind::Unmatched, notice, format!("-R '{}' does not match '{}'", rgx, regex_path));
     }
-
-    fn with_defaults(operation_base_dir: &Path) -> Self {
-        let mut patterns = Vec::new();
-        patterns.push(GitIgnorePattern::new(".git/".to_string(), operation_base_dir));
-        patterns.push(GitIgnorePattern::new(".gitignore".to_string(), operation_base_dir));
-        GitIgnore {
-            patterns,
-            effective_base_dir: operation_base_dir.to_path_buf(),
-        }
+    if let Some(filter) = &config.filter
+        && !filter.matches(path, regex_path)
+    {
+        return skip(SkipKind::Unmatched, "Skipping file (--filter did not match)".to_string(), format!("--filter '{}' does not match", filter.source));
+    }
+    if !language_selected(path, config) {
+        let reason = format!("not in --lang {}", config.languages.join(","));
+        return skip(SkipKind::Unmatched, format!("Skipping file ({})", reason), reason);
+    }
+    if let Some(reason) = policy_exclusion(path, config) {
+        return skip(SkipKind::Excluded, format!("Skipping file ({})", reason), reason);
+    }
+    if config.no_tests
+        && let Some(reason) = test_files::test_reason(path, regex_path)
+    {
+        return skip(SkipKind::Excluded, "Skipping test file (--no-tests)".to_string(), format!("--no-tests: {}", reason));
     }
+    if config.auto_skip
+        && let Some(decision) = ignore::machine_generated(&path.file_name().unwrap_or_default().to_string_lossy())
+    {
+        let reason = format!("lockfile/minified heuristic {}; --no-auto-skip keeps it", describe_rule(&decision));
+        return skip(SkipKind::AutoSkipped, "Auto-skipping lockfile/minified asset".to_string(), reason);
+    }
+    if let (Some(owner), Some(codeowners)) = (&config.owner, &bundle.codeowners)
+        && let Err(reason) = codeowners.check(path, owner)
+    {
+        return skip(SkipKind::Excluded, format!("Skipping file ({})", reason), reason);
+    }
+    None
+}
 
-    fn merge(&mut self, other: GitIgnore) {
-        self.patterns.extend(other.patterns);
+// With --owner, whether the owner owns `path` in CODEOWNERS; files they don't own are reported as excluded.
+fn is_owned(path: &Path, bundle: &mut Bundle, config: &Config) -> bool {
+    let (Some(owner), Some(codeowners)) = (&config.owner, &bundle.codeowners) else {
+        return true;
+    };
+    let Err(reason) = codeowners.check(path, owner) else {
+        return true;
+    };
+    if config.verbose {
+        println!("Skipping file ({}): {}", reason, path.display());
     }
+    bundle.exclude(config, path, &reason);
+    false
+}
 
-    fn should_ignore(&self, path_to_check_relative_to_cmd_base: &Path, is_item_dir: bool, overall_cmd_arg_base_dir: &Path) -> bool {
-        let mut ignored = false;
-        for pattern_rule in &self.patterns {
-            let abs_path_to_check = overall_cmd_arg_base_dir.join(path_to_check_relative_to_cmd_base);
-            if let Ok(path_relative_to_pattern_def_dir) = abs_path_to_check.strip_prefix(&pattern_rule.defined_in_dir) {
-                let path_str_to_match = path_relative_to_pattern_def_dir.to_string_lossy().replace('\\', "/");
-                if pattern_rule.matches(&path_str_to_match, is_item_dir) {
-                    ignored = !pattern_rule.is_negation;
+// Pulls in local files imported by the files already in the bundle, transitively up to `depth` levels.
+fn expand_imports(bundle: &mut Bundle, config: &Config, depth: usize) {
+    let mut frontier = bundle.included_paths();
+    for _ in 0..depth {
+        let mut next_frontier = Vec::new();
+        for importer in &frontier {
+            let Ok(contents) = fs::read_to_string(importer) else {
+                continue;
+            };
+            for import in imports::local_imports(importer, &contents) {
+                let Ok(canonical_import) = fs::canonicalize(&import) else {
+                    continue;
+                };
+                if bundle.seen_paths.contains(&canonical_import) {
+                    continue;
+                }
+                if config.verbose {
+                    println!("Including import: {} (from {})", import.display(), importer.display());
                 }
-            } else if !pattern_rule.is_absolute && !pattern_rule.contains_slash {
-                let path_str_to_match = path_to_check_relative_to_cmd_base.to_string_lossy().replace('\\', "/");
-                if pattern_rule.matches_against_any_component(&path_str_to_match, is_item_dir) {
-                     ignored = !pattern_rule.is_negation;
+                let count_before = bundle.file_count();
+                match process_file(&canonical_import, false, bundle, config) {
+                    Ok(_) if bundle.file_count() > count_before => next_frontier.push(canonical_import),
+                    Ok(_) => {}
+                    Err(e) => {
+                        if config.verbose {
+                            eprintln!("Error processing import '{}': {}", import.display(), e);
+                        }
+                    }
                 }
             }
         }
-        ignored
+        if next_frontier.is_empty() {
+            break;
+        }
+        frontier = next_frontier;
     }
 }
 
-impl GitIgnorePattern {
-    fn new(raw_pattern_str: String, pattern_defined_in_dir_param: &Path) -> Self {
-        let mut pattern = raw_pattern_str.trim().to_string();
-        if pattern.is_empty() || pattern.starts_with('#') {
-            return GitIgnorePattern {
-                pattern: String::new(),
-                raw_pattern: String::new(),
-                is_negation: false,
-                is_directory: false,
-                is_absolute: false,
-                contains_slash: false,
-                defined_in_dir: pattern_defined_in_dir_param.to_path_buf(),
+// Adds the conventional test file(s) for each included source file.
+fn include_companion_tests(bundle: &mut Bundle, config: &Config) {
+    for source in bundle.included_paths() {
+        for test_file in test_files::companion_tests(&source) {
+            let Ok(canonical_test) = fs::canonicalize(&test_file) else {
+                continue;
             };
-        }
-        let is_negation = pattern.starts_with('!');
-        if is_negation { pattern = pattern[1..].to_string(); }
-        let is_absolute = pattern.starts_with('/');
-        if is_absolute { pattern = pattern[1..].to_string(); }
-        let is_directory = pattern.ends_with('/');
-        if is_directory { pattern = pattern[..pattern.len() - 1].to_string(); }
-        let contains_slash = !is_absolute && pattern.contains('/');
-        GitIgnorePattern {
-            pattern, raw_pattern: raw_pattern_str, is_negation, is_directory, is_absolute, contains_slash,
-            defined_in_dir: pattern_defined_in_dir_param.to_path_buf(),
+            if bundle.seen_paths.contains(&canonical_test) {
+                continue;
+            }
+            if config.verbose {
+                println!("Including test file: {} (for {})", test_file.display(), source.display());
+            }
+            if let Err(e) = process_file(&canonical_test, false, bundle, config)
+                && config.verbose
+            {
+                eprintln!("Error processing test file '{}': {}", test_file.display(), e);
+            }
         }
     }
+}
 
-    fn matches(&self, path_str_relative_to_def_dir: &str, is_item_dir: bool) -> bool {
-        if self.pattern.is_empty() { return false; }
-        if self.is_directory && !is_item_dir { return false; }
-        if self.is_absolute || self.contains_slash {
-            self.simple_glob_match(&self.pattern, path_str_relative_to_def_dir)
-        } else {
-            Path::new(path_str_relative_to_def_dir).file_name()
-                .and_then(|n| n.to_str())
-                .map_or(false, |filename_str| self.simple_glob_match(&self.pattern, filename_str)) ||
-            self.simple_glob_match(&self.pattern, path_str_relative_to_def_dir)
-        }
+// Records a file that -R did not select: with --tiered it is outlined later, otherwise it is excluded.
+fn skip_unmatched(path: &Path, reason: &str, bundle: &mut Bundle, config: &Config) {
+    if !config.tiered {
+        bundle.exclude(config, path, reason);
+        return;
     }
+    bundle.trace(config, path, false, &format!("{}; outlined instead (--tiered)", reason));
+    let display_name = display_name_for(path, bundle, config);
+    bundle.outline_candidates.push((path.to_path_buf(), display_name));
+}
 
-    fn matches_against_any_component(&self, path_str: &str, is_item_dir: bool) -> bool {
-        if self.pattern.is_empty() { return false; }
-        if self.is_directory && !is_item_dir { return false; }
-        if Path::new(path_str).file_name()
-            .and_then(|n| n.to_str())
-            .map_or(false, |name_part| self.simple_glob_match(&self.pattern, name_part)) {
-            return true;
+// Adds outlines (signatures and doc comments) of the --tiered candidates while they fit in --max-total-tokens.
+// Returns (outlined, left out for the budget).
+fn add_outlines(bundle: &mut Bundle, config: &Config) -> (usize, usize) {
+    let mut remaining = config.max_total_tokens.map(|max| max.saturating_sub(bundle.total_tokens()));
+    let (mut outlined, mut left_out) = (0, 0);
+    for (path, display_name) in std::mem::take(&mut bundle.outline_candidates) {
+        // Already included in full, e.g. through --expand-imports.
+        if bundle.seen_paths.contains(&path) {
+            continue;
         }
-        if !path_str.contains('/') && self.simple_glob_match(&self.pattern, path_str) {
-            return true;
+        let Some(outline) = symbols::outline(&path) else {
+            continue;
+        };
+        let segment = format_outline(&display_name, &outline, config);
+        let tokens = tokens::estimate_tokens(&segment);
+        if let Some(remaining) = &mut remaining {
+            if tokens > *remaining {
+                left_out += 1;
+                continue;
+            }
+            *remaining -= tokens;
         }
-        false
+        if config.verbose {
+            println!("Outlining: {}", display_name);
+        }
+        bundle.add_file(BundleEntry { display_name, path: Some(path), contents: outline, segment, tokens });
+        outlined += 1;
+    }
+    (outlined, left_out)
+}
+
+fn format_outline(display_name: &str, outline: &str, config: &Config) -> String {
+    if config.use_xml {
+        format!("<file path=\"{}\" outline=\"true\">\n{}\n</file>", display_name, outline.trim_end())
+    } else {
+        let fence = fence_for(outline, config);
+        let language = get_language_from_extension(display_name);
+        format!("{}{} (outline)\n{}{}\n{}\n{}", config.header_prefix, display_name, fence, language, outline.trim_end(), fence)
     }
+}
 
-    fn simple_glob_match(&self, pattern: &str, text: &str) -> bool {
-        if pattern == "*" { return !text.contains('/'); }
-        if pattern.is_empty() { return text.is_empty(); }
-        if text.is_empty() { return pattern == "*" || pattern.is_empty(); }
-        if !pattern.contains('*') && !pattern.contains('?') {
-            return pattern == text;
+// The header name of a file: relative to the working directory, or labelled under a remote/--at root.
+fn display_name_for(path_obj: &Path, bundle: &Bundle, config: &Config) -> String {
+    anonymize(config, &match &bundle.display_root {
+        Some((root, label)) if path_obj.starts_with(root) => {
+            let relative = display_path(path_obj.strip_prefix(root).unwrap_or(path_obj));
+            if label.is_empty() { relative } else { format!("{}/{}", label, relative) }
         }
-        let pattern_parts: Vec<&str> = pattern.split('*').collect();
-        if pattern_parts.is_empty() { return true; }
-        let mut text_idx = 0;
-        for (i, part) in pattern_parts.iter().enumerate() {
-            if part.is_empty() {
-                if i == 0 && pattern_parts.len() == 1 { return !text.contains('/'); }
-                continue;
+        _ => match root_label(path_obj, bundle) {
+            Some((root, label)) => format!("{}/{}", label, display_path(path_obj.strip_prefix(root).unwrap_or(path_obj))),
+            None => display_path(
+                env::current_dir()
+                    .ok()
+                    .and_then(|cwd| path_obj.strip_prefix(&cwd).ok())
+                    .unwrap_or(path_obj),
+            ),
+        },
+    })
+}
+
+// The innermost directory argument containing `path` and its label, when several were given.
+fn root_label<'a>(path: &Path, bundle: &'a Bundle) -> Option<(&'a Path, &'a str)> {
+    bundle
+        .root_labels
+        .iter()
+        .filter(|(root, _)| path.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+        .map(|(root, label)| (root.as_path(), label.as_str()))
+}
+
+// Labels for the directory arguments when there are several, so files from different roots can be told apart.
+// A root inside the working directory keeps its relative path (as without labels); any other root, including
+// the working directory itself, is named by its last components, as few as keep the labels distinct.
+fn root_labels(paths: &[String]) -> Vec<(PathBuf, String)> {
+    let mut roots: Vec<PathBuf> = paths.iter().filter(|path| Path::new(path).is_dir()).filter_map(|path| fs::canonicalize(path).ok()).collect();
+    roots.dedup();
+    if roots.len() < 2 {
+        return Vec::new();
+    }
+    let cwd = env::current_dir().ok().and_then(|cwd| fs::canonicalize(cwd).ok()).unwrap_or_default();
+    let inside = |root: &Path| root != cwd && root.starts_with(&cwd);
+    let tail = |root: &Path, depth: usize| {
+        let names: Vec<String> = root
+            .components()
+            .filter_map(|part| match part {
+                std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        names[names.len().saturating_sub(depth)..].join("/")
+    };
+    let mut depths = vec![1; roots.len()];
+    loop {
+        let labels: Vec<String> = roots
+            .iter()
+            .zip(&depths)
+            .map(|(root, depth)| if inside(root) { display_path(root.strip_prefix(&cwd).unwrap_or(root)) } else { tail(root, *depth) })
+            .collect();
+        let mut lengthened = false;
+        for (i, root) in roots.iter().enumerate() {
+            let collides = labels.iter().enumerate().any(|(j, label)| j != i && *label == labels[i]);
+            if collides && !inside(root) && depths[i] < root.components().count() {
+                depths[i] += 1;
+                lengthened = true;
             }
-            if i == 0 && !pattern.starts_with('*') {
-                if !text.starts_with(part) { return false; }
-                text_idx = part.len();
+        }
+        if !lengthened {
+            return roots.into_iter().zip(labels).collect();
+        }
+    }
+}
+
+// --root-headers: opens each run of files from the same directory argument with a header naming the root
+// (in XML, wraps the run in a <root> element). The header is part of the run's first file segment.
+fn add_root_headers(bundle: &mut Bundle, config: &Config) {
+    let labels: Vec<Option<String>> = bundle
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_deref().and_then(|path| root_label(path, bundle)).map(|(_, label)| label.to_string()))
+        .collect();
+    let count = labels.len();
+    for (i, entry) in bundle.entries.iter_mut().enumerate() {
+        let label = &labels[i];
+        let opens = label.is_some() && (i == 0 || labels[i - 1] != *label);
+        let closes = label.is_some() && (i + 1 == count || labels[i + 1] != *label);
+        if let Some(label) = label.as_deref().filter(|_| opens) {
+            let label = anonymize(config, label);
+            entry.segment = if config.use_xml {
+                format!("<root label=\"{}\">\n{}", label, entry.segment)
             } else {
-                if let Some(found_pos) = text[text_idx..].find(part) {
-                    text_idx += found_pos + part.len();
-                } else { return false; }
-            }
+                format!("{}{}/{}{}", config.header_prefix, label, bundle.separator, entry.segment)
+            };
+        }
+        if config.use_xml && closes {
+            entry.segment.push_str("\n</root>");
+        }
+        if opens || (config.use_xml && closes) {
+            entry.tokens = tokens::estimate_tokens(&entry.segment);
         }
-        if !pattern.ends_with('*') && text_idx != text.len() { return false; }
-        true
     }
 }
 
-fn load_gitignore(dir_containing_gitignore: &Path) -> GitIgnore {
-    let gitignore_path = dir_containing_gitignore.join(".gitignore");
-    let mut patterns = Vec::new();
-    if let Ok(contents) = fs::read_to_string(&gitignore_path) {
-        for line in contents.lines() {
-            let line_trimmed = line.trim();
-            if line_trimmed.is_empty() || line_trimmed.starts_with('#') { continue; }
-            patterns.push(GitIgnorePattern::new(line_trimmed.to_string(), dir_containing_gitignore));
+// Formats a single file and appends it to the bundle, unless it is a duplicate of one already included.
+// Takes a Path rather than a str so files whose names are not valid UTF-8 are still read; only the
+// display name is rendered lossily.
+// `is_symlink` comes from the directory listing (false for canonical paths), so no file is stat-ed just to find out.
+fn process_file(path_obj: &Path, is_symlink: bool, bundle: &mut Bundle, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let filepath_str = &*path_obj.to_string_lossy();
+    let display_name = display_name_for(path_obj, bundle, config);
+    if let Some(format) = image::image_format(path_obj) {
+        return process_image(path_obj, format, display_name, bundle, config);
+    }
+
+    // Traversal starts from canonicalized CLI roots, so a path only needs resolving if a symlink is involved.
+    let canonical_path = if bundle.symlinked_dir_depth > 0 || is_symlink {
+        fs::canonicalize(path_obj)?
+    } else {
+        path_obj.to_path_buf()
+    };
+    // --compare and --blame output depends on git state, which the cache key doesn't capture.
+    let cache_key = match &bundle.cache {
+        Some(cache) if config.compare.is_none() && !config.blame => cache.key(&canonical_path, &format!("{}\0{}", display_name, cache_fingerprint(config))),
+        _ => None,
+    };
+    if let Some(key) = &cache_key
+        && let Some(cached) = bundle.cache.as_ref().and_then(|cache| cache.get(key))
+    {
+        if bundle.is_empty_file(&canonical_path, &cached.contents, &display_name, config)
+            || bundle.is_duplicate(&canonical_path, cached.contents.as_bytes(), &display_name, config)
+        {
+            return Ok(());
+        }
+        bundle.add_file(BundleEntry {
+            display_name,
+            path: Some(canonical_path),
+            contents: cached.contents,
+            segment: cached.segment,
+            tokens: cached.tokens,
+        });
+        return Ok(());
+    }
+
+    // Normalize line endings so the bundle is byte-identical regardless of checkout platform.
+    let mut contents = timings::time(timings::Phase::Reading, || fs::read_to_string(path_obj))?;
+    if bundle.is_empty_file(&canonical_path, &contents, &display_name, config) {
+        return Ok(());
+    }
+    if contents.contains('\r') {
+        contents = contents.replace("\r\n", "\n");
+    }
+    let markers = transform::find_markers(&contents);
+    if let transform::Markers::IgnoreFile = markers {
+        if config.verbose {
+            println!("Skipping file (marked toprompt:ignore-file): {}", display_name);
+        }
+        bundle.exclude(config, &canonical_path, "marked toprompt:ignore-file");
+        return Ok(());
+    }
+    // Annotate before transforms, while lines still correspond to git's.
+    if config.blame {
+        match git::blame(&canonical_path) {
+            Ok(blame) => contents = annotate_blame(&contents, &blame),
+            Err(e) => {
+                if config.verbose {
+                    eprintln!("Warning: no blame for '{}': {}", display_name, e);
+                }
+            }
+        }
+    }
+    if let transform::Markers::Regions(regions) = &markers {
+        contents = transform::keep_regions(&contents, regions);
+    }
+    let contents = timings::time(timings::Phase::Formatting, || apply_transforms(contents, path_obj, config));
+    if bundle.is_duplicate(&canonical_path, contents.as_bytes(), &display_name, config) {
+        return Ok(());
+    }
+
+    let formatted_segment = if let Some(git_ref) = &config.compare {
+        let previous = git::show_file(&canonical_path, git_ref)?.map(|old| apply_transforms(old, path_obj, config));
+        timings::time(timings::Phase::Formatting, || format_comparison(&display_name, filepath_str, git_ref, previous.as_deref(), &contents, config))
+    } else {
+        timings::time(timings::Phase::Formatting, || format_segment(&display_name, filepath_str, &contents, config))
+    };
+    let tokens = tokens::estimate_tokens(&formatted_segment);
+    let entry = BundleEntry { display_name, path: Some(canonical_path), contents, segment: formatted_segment, tokens };
+    if let (Some(cache), Some(key)) = (&bundle.cache, &cache_key) {
+        let cached = cache::CachedSegment { contents: entry.contents.clone(), segment: entry.segment.clone(), tokens };
+        if let Err(e) = cache.put(key, &cached)
+            && config.verbose
+        {
+            eprintln!("Warning: could not write cache entry for '{}': {}", entry.display_name, e);
         }
     }
-    GitIgnore { patterns, effective_base_dir: dir_containing_gitignore.to_path_buf() }
+    bundle.add_file(entry);
+    Ok(())
 }
 
-// Returns (formatted_content_for_this_file, display_name_string)
-fn process_file(filepath_str: &str, config: &Config) -> Result<(String, String), Box<dyn std::error::Error>> {
-    let contents = fs::read_to_string(filepath_str)?;
-    let path_obj = Path::new(filepath_str);
-    let display_name = env::current_dir()
-        .ok()
-        .and_then(|cwd| path_obj.strip_prefix(&cwd).ok())
-        .unwrap_or(path_obj);
+// Prefixes each line with "hash author" from `blame`, padding authors to a common width.
+fn annotate_blame(contents: &str, blame: &[(String, String)]) -> String {
+    const MAX_AUTHOR: usize = 20;
+    let width = blame.iter().map(|(_, author)| author.chars().count().min(MAX_AUTHOR)).max().unwrap_or(0);
+    let mut annotated = String::with_capacity(contents.len() + blame.len() * (width + 10));
+    for (i, line) in contents.lines().enumerate() {
+        let (hash, author) = blame.get(i).map(|(hash, author)| (hash.as_str(), author.as_str())).unwrap_or(("", ""));
+        let author: String = author.chars().take(MAX_AUTHOR).collect();
+        annotated.push_str(format!("{:<7} {:<width$}  {}", hash, author, line).trim_end());
+        annotated.push('\n');
+    }
+    annotated
+}
 
-    let formatted_segment = if config.use_xml {
+// The code fence language: a config rule's `language`, otherwise the one for the extension.
+fn file_language<'a>(path: &'a Path, config: &'a Config) -> &'a str {
+    matching_rules(path, config)
+        .filter_map(|rule| rule.language.as_deref())
+        .last()
+        .unwrap_or_else(|| get_language_from_extension(&path.to_string_lossy()))
+}
+
+// Formats one file's contents with its header, sizing the buffer up front to avoid reallocation.
+fn format_segment(display_name: &str, filepath_str: &str, contents: &str, config: &Config) -> String {
+    let body = contents.trim_end();
+    let language = file_language(Path::new(filepath_str), config);
+    if let Some(formatter) = &config.formatter {
+        return formatter.format(display_name, language, body).unwrap_or_else(|e| {
+            eprintln!("Error: format plugin '{}' failed on {}: {}", formatter.name(), display_name, e);
+            std::process::exit(EXIT_ERROR);
+        });
+    }
+    if let Some(preset) = config.preset {
+        return preset.format_file(display_name, language, body);
+    }
+    let mut segment = String::with_capacity(body.len() + 2 * display_name.len() + 32);
+    if config.use_xml {
+        segment.push_str("<file path=\"");
+        segment.push_str(display_name);
+        segment.push_str("\">\n");
+        segment.push_str(body);
+        segment.push_str("\n</file>");
+    } else {
+        segment.push_str(&config.header_prefix);
+        segment.push_str(display_name);
+        segment.push('\n');
+        segment.push_str(&config.fence);
+        segment.push_str(language);
+        segment.push('\n');
+        segment.push_str(body);
+        segment.push('\n');
+        segment.push_str(&config.fence);
+    }
+    segment
+}
+
+// A listing of a directory's files and sizes, standing in for their contents; returns (segment, listing).
+fn format_listing(display_name: &str, files: &[(String, u64)], config: &Config) -> (String, String) {
+    let width = files.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let listing: String = files.iter().map(|(name, size)| format!("{:<width$}  {}\n", name, format_size(*size))).collect();
+    let segment = if config.use_xml {
+        format!("<directory path=\"{}\" files=\"{}\" contents=\"omitted\">\n{}</directory>", display_name, files.len(), listing)
+    } else {
+        format!("{}{} ({} files, contents omitted)\n{}\n{}{}", config.header_prefix, display_name, files.len(), config.fence, listing, config.fence)
+    };
+    (segment, listing)
+}
+
+// Options that change how a file is formatted; part of the cache key.
+fn cache_fingerprint(config: &Config) -> String {
+    format!(
+        "{}:{}:{:?}:{:?}:{}:{}:{}:{}:{}:{:?}:{:?}:{:?}:{:?}:{:?}:{:?}",
+        config.model.map_or("default", |model| model.name), config.use_xml, config.preset, config.header_prefix, config.fence, config.normalize, config.strip_imports, config.minify_data, config.tab_width, config.max_line_length, config.max_lines, config.tail_logs, config.table_preview,
+        config.rewrites.iter().map(|rewrite| rewrite.rule.as_str()).chain(config.file_rules.iter().map(|rule| rule.fingerprint.as_str())).collect::<Vec<_>>(),
+        config.pipe_through.iter().map(|pipe| pipe.command.as_str()).chain(config.transform_plugins.iter().map(|plugin| plugin.name())).chain(config.formatter.as_ref().map(|formatter| formatter.name())).collect::<Vec<_>>()
+    )
+}
+
+// Applies the configured content transforms, in a fixed order.
+fn apply_transforms(mut contents: String, path_obj: &Path, config: &Config) -> String {
+    for pipe in &config.pipe_through {
+        match pipe.run("pipe", &path_obj.to_string_lossy(), file_language(path_obj, config), &contents) {
+            Ok(output) => contents = output,
+            Err(e) => eprintln!("Warning: --pipe-through '{}' failed on {} ({}); keeping it unchanged", pipe.command, path_obj.display(), e),
+        }
+    }
+    if !config.rewrites.is_empty() {
+        contents = transform::apply_rewrites(&contents, &config.rewrites);
+    }
+    if transform::is_log_file(path_obj) {
+        contents = transform::strip_ansi_codes(&contents);
+        if let Some(tail_lines) = config.tail_logs {
+            contents = transform::limit_lines(&contents, tail_lines, transform::LineLimitMode::Tail);
+        }
+    }
+    if let Some(max_rows) = config.table_preview
+        && transform::is_table_file(path_obj)
+    {
+        contents = transform::preview_table(&contents, max_rows);
+    }
+    let rules: Vec<&config_file::FileRule> = matching_rules(path_obj, config).collect();
+    for transform in rules.iter().flat_map(|rule| &rule.transforms) {
+        contents = match transform.as_str() {
+            "normalize" => transform::normalize_whitespace(&contents, config.tab_width),
+            "strip-comments" => transform::strip_comments(&contents, path_obj),
+            "strip-imports" => transform::strip_imports(&contents, path_obj),
+            "remove-blank-lines" => transform::remove_blank_lines(&contents),
+            "minify-data" => transform::minify_data(&contents, path_obj),
+            _ => contents,
+        };
+    }
+    if config.strip_imports {
+        contents = transform::strip_imports(&contents, path_obj);
+    }
+    if config.minify_data {
+        contents = transform::minify_data(&contents, path_obj);
+    }
+    if config.normalize {
+        contents = transform::normalize_whitespace(&contents, config.tab_width);
+    }
+    for plugin in &config.transform_plugins {
+        let language = file_language(path_obj, config);
+        contents = plugin.transform(path_obj, language, &contents).unwrap_or_else(|e| {
+            eprintln!("Error: transform plugin '{}' failed on {}: {}", plugin.name(), path_obj.display(), e);
+            std::process::exit(EXIT_ERROR);
+        });
+    }
+    if let Some(max_chars) = rules.iter().rev().find_map(|rule| rule.max_line_length).or(config.max_line_length) {
+        contents = transform::truncate_long_lines(&contents, max_chars);
+    }
+    if let Some((max_lines, mode)) = rules.iter().rev().find_map(|rule| rule.max_lines).or(config.max_lines) {
+        contents = transform::limit_lines(&contents, max_lines, mode);
+    }
+    contents
+}
+
+// Formats the version of a file at `git_ref` next to its working-tree version (--compare).
+fn format_comparison(display_name: &str, filepath_str: &str, git_ref: &str, previous: Option<&str>, current: &str, config: &Config) -> String {
+    if config.use_xml {
+        let previous_xml = match previous {
+            Some(old) => format!("<version ref=\"{}\">\n{}\n</version>", git_ref, old.trim_end()),
+            None => format!("<version ref=\"{}\" missing=\"true\" />", git_ref),
+        };
         format!(
-            "<file path=\"{}\">\n{}\n</file>",
-            display_name.display(),
-            contents.trim_end()
+            "<file path=\"{}\">\n{}\n<version ref=\"working tree\">\n{}\n</version>\n</file>",
+            display_name, previous_xml, current.trim_end()
         )
     } else {
         let language = get_language_from_extension(filepath_str);
+        let previous_md = match previous {
+            Some(old) => format!("{}{}\n{}\n{}", config.fence, language, old.trim_end(), config.fence),
+            None => format!("(file does not exist at {})", git_ref),
+        };
         format!(
-            "# {}\n```{}\n{}\n```",
-            display_name.display(),
-            language,
-            contents.trim_end()
+            "{}{}\n## Before ({})\n{}\n## After (working tree)\n{}{}\n{}\n{}",
+            config.header_prefix, display_name, git_ref, previous_md, config.fence, language, current.trim_end(), config.fence
         )
+    }
+}
+
+// Fetches a webpage (converted to markdown when it is HTML) and adds it as a document.
+fn process_url(url: &str, bundle: &mut Bundle, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if config.verbose {
+        println!("Fetching {}", url);
+    }
+    let document = web::fetch(url)?;
+    if bundle.is_duplicate(Path::new(url), document.content.as_bytes(), url, config) {
+        return Ok(());
+    }
+
+    let formatted_segment = if config.use_xml {
+        let title_attr = document.title.as_ref().map(|t| format!(" title=\"{}\"", t.replace('"', "&quot;"))).unwrap_or_default();
+        format!("<document url=\"{}\"{}>\n{}\n</document>", document.url, title_attr, document.content.trim_end())
+    } else {
+        let language = if document.is_html {
+            "markdown"
+        } else {
+            get_language_from_extension(url.split(['?', '#']).next().unwrap_or(url))
+        };
+        let title_line = document.title.as_ref().map(|t| format!("{}\n", t)).unwrap_or_default();
+        // Converted pages often contain their own code fences, so the outer fence must be longer.
+        let fence = fence_for(&document.content, config);
+        format!("{}{}\n{}{}{}\n{}\n{}", config.header_prefix, document.url, title_line, fence, language, document.content.trim_end(), fence)
+    };
+    bundle.add_file(BundleEntry {
+        display_name: url.to_string(),
+        path: None,
+        contents: document.content,
+        tokens: tokens::estimate_tokens(&formatted_segment),
+        segment: formatted_segment,
+    });
+    Ok(())
+}
+
+// Emits a manifest-style entry (format, dimensions, size) for an image instead of its raw bytes.
+fn process_image(path_obj: &Path, format: &'static str, display_name: String, bundle: &mut Bundle, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = fs::read(path_obj)?;
+    let canonical_path = fs::canonicalize(path_obj)?;
+    if bundle.is_duplicate(&canonical_path, &bytes, &display_name, config) {
+        return Ok(());
+    }
+
+    let info = image::inspect(&bytes, format);
+    let dimensions = info.dimensions.map(|(w, h)| format!("{}x{}", w, h));
+    let formatted_segment = if config.use_xml {
+        let dimension_attrs = info.dimensions
+            .map(|(w, h)| format!(" width=\"{}\" height=\"{}\"", w, h))
+            .unwrap_or_default();
+        if config.embed_images {
+            format!(
+                "<image path=\"{}\" format=\"{}\"{} bytes=\"{}\" encoding=\"base64\">\n{}\n</image>",
+                display_name, info.format, dimension_attrs, bytes.len(), image::base64_encode(&bytes)
+            )
+        } else {
+            format!("<image path=\"{}\" format=\"{}\"{} bytes=\"{}\" />", display_name, info.format, dimension_attrs, bytes.len())
+        }
+    } else {
+        let mut segment = format!(
+            "{}{}\n[image: {}, {}, {} bytes]",
+            config.header_prefix, display_name, info.format, dimensions.as_deref().unwrap_or("unknown dimensions"), bytes.len()
+        );
+        if config.embed_images {
+            segment.push_str(&format!(
+                "\n![{}](data:image/{};base64,{})",
+                display_name, info.format, image::base64_encode(&bytes)
+            ));
+        }
+        segment
     };
-    Ok((formatted_segment, display_name.display().to_string()))
+    let tokens = tokens::estimate_tokens(&formatted_segment);
+    bundle.add_file(BundleEntry { display_name, path: Some(canonical_path), contents: String::new(), segment: formatted_segment, tokens });
+    Ok(())
+}
+
+// Returns a fence (of --fence-char) longer than any run of that character inside `contents`.
+fn fence_for(contents: &str, config: &Config) -> String {
+    let fence_char = config.fence.chars().next().unwrap_or('`');
+    let longest_run = contents
+        .split(|c| c != fence_char)
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    fence_char.to_string().repeat(longest_run.max(2) + 1)
+}
+
+// A rule as shown by --trace-filters and `config explain`, e.g. 'target/' (.gitignore:3).
+fn describe_rule(decision: &ignore::Decision) -> String {
+    format!("'{}' ({}:{})", decision.pattern, decision.source, decision.line)
+}
+
+// Renders a path for headers and listings using '/' separators on every platform.
+fn display_path(path: &Path) -> String {
+    path.display().to_string().replace('\\', "/")
+}
+
+// `path` relative to the working directory when it is inside it ("." for the directory itself).
+fn display_relative(path: &Path) -> String {
+    match env::current_dir().ok().and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf)) {
+        Some(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Some(relative) => display_path(&relative),
+        None => display_path(path),
+    }
+}
+
+// --anonymize-paths: the home directory and the user and host names to hide, read from the environment once.
+struct Anonymizer {
+    home: Option<String>,
+    // Path components replaced by a placeholder: the user name by "user", the host name by "host".
+    names: Vec<(String, &'static str)>,
+}
+
+impl Anonymizer {
+    fn from_env() -> Self {
+        let non_empty = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let home = non_empty(env::var("HOME").ok().or_else(|| env::var("USERPROFILE").ok()))
+            .map(|home| if cfg!(windows) { home.replace('\\', "/") } else { home })
+            .map(|home| home.trim_end_matches('/').to_string())
+            .filter(|home| !home.is_empty());
+        let user = non_empty(env::var("USER").ok().or_else(|| env::var("USERNAME").ok()));
+        let host = non_empty(env::var("HOSTNAME").ok().or_else(|| fs::read_to_string("/etc/hostname").ok()));
+        let names = [(user, "user"), (host, "host")].into_iter().filter_map(|(name, placeholder)| Some((name?, placeholder))).collect();
+        Anonymizer { home, names }
+    }
+
+    // Rewrites one absolute path: the home directory prefix to "~", then components naming the user or the host.
+    fn rewrite(&self, path: &str) -> String {
+        let path = match &self.home {
+            Some(home) if path == home => "~".to_string(),
+            Some(home) if path.strip_prefix(home.as_str()).is_some_and(|rest| rest.starts_with('/')) => format!("~{}", &path[home.len()..]),
+            _ => path.to_string(),
+        };
+        path.split('/')
+            .map(|component| self.names.iter().find(|(name, _)| name == component).map_or(component, |(_, placeholder)| *placeholder))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+// --anonymize-paths: rewrites the absolute paths in `text` (a path, or a message containing paths). Relative
+// paths are left alone, so `src/app/main.rs` keeps its name when the user happens to be called "app".
+fn anonymize(config: &Config, text: &str) -> String {
+    let Some(anonymizer) = &config.anonymize_paths else {
+        return text.to_string();
+    };
+    let text = if cfg!(windows) { text.replace('\\', "/") } else { text.to_string() };
+    // Where a path may start and end within a message: e.g. 'ignored by /home/me/.gitignore:3' or root="/home/me".
+    let opens = |c: char| c.is_whitespace() || "'\"`(<[=,".contains(c);
+    let closes = |c: char| c.is_whitespace() || "'\"`)>],".contains(c);
+    let mut anonymized = String::with_capacity(text.len());
+    let mut rest = text.as_str();
+    while let Some(start) = absolute_path_start(rest, opens) {
+        let end = rest[start..].find(closes).map_or(rest.len(), |length| start + length);
+        anonymized.push_str(&rest[..start]);
+        anonymized.push_str(&anonymizer.rewrite(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    anonymized.push_str(rest);
+    anonymized
 }
 
-fn get_language_from_extension(filename: &str) -> &str {
+// The first absolute path in `text` starting at its beginning or after an `opens` character: `/...`, or `C:/...` on Windows.
+fn absolute_path_start(text: &str, opens: impl Fn(char) -> bool) -> Option<usize> {
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        if previous.is_none_or(&opens) {
+            let rest = &text[i..];
+            let drive = cfg!(windows) && c.is_ascii_alphabetic() && rest[1..].starts_with(":/");
+            if c == '/' || drive {
+                return Some(i);
+            }
+        }
+        previous = Some(c);
+    }
+    None
+}
+
+// The language of a file for --lang and --filter lang=: the fence language of its extension or, for
+// extensionless scripts, of the interpreter on its shebang line.
+fn detect_language(path: &Path) -> String {
+    let name = path.to_string_lossy();
+    let language = get_language_from_extension(&name);
+    if !language.is_empty() || path.extension().is_some() {
+        return language.to_string();
+    }
+    let Ok(file) = fs::File::open(path) else {
+        return String::new();
+    };
+    let mut first_line = String::new();
+    let _ = io::BufReader::new(file.take(256)).read_line(&mut first_line);
+    language_from_shebang(&first_line).to_string()
+}
+
+// `#!/usr/bin/env python3` is python, `#!/bin/sh -e` is bash, ...
+fn language_from_shebang(line: &str) -> &'static str {
+    let Some(command) = line.strip_prefix("#!") else {
+        return "";
+    };
+    let mut words = command.split_whitespace();
+    let mut program = words.next().unwrap_or_default().rsplit('/').next().unwrap_or_default();
+    if program == "env" {
+        // Options (env -S) and variable assignments come before the program.
+        program = words.find(|word| !word.starts_with('-') && !word.contains('=')).unwrap_or_default();
+    }
+    // python3.12, perl5, ...
+    match program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "python" | "pypy" => "python",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => "bash",
+        "node" | "nodejs" | "bun" => "javascript",
+        "deno" | "ts-node" | "tsx" => "typescript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" | "luajit" => "lua",
+        "Rscript" => "r",
+        "julia" => "julia",
+        "pwsh" => "powershell",
+        "elixir" => "elixir",
+        _ => "",
+    }
+}
+
+// Whether --lang (if given) selects the file.
+fn language_selected(path: &Path, config: &Config) -> bool {
+    config.languages.is_empty() || config.languages.contains(&detect_language(path))
+}
+
+fn get_language_from_extension(filename: &str) -> &'static str {
     let path = Path::new(filename);
     match path.extension().and_then(|ext| ext.to_str()) {
         Some("rs") => "rust", Some("py") => "python", Some("js") => "javascript", Some("ts") => "typescript",
@@ -587,7 +3191,8 @@ fn get_language_from_extension(filename: &str) -> &str {
         Some("ml") | Some("mli") => "ocaml", Some("fs") | Some("fsx") | Some("fsi") => "fsharp",
         Some("pl") | Some("pm") => "perl", Some("ps1") | Some("psm1") | Some("psd1") => "powershell",
         Some("toml") => "toml", Some("ini") => "ini", Some("cfg") => "cfg", Some("conf") => "plaintext",
-        Some("log") => "log", Some("dockerfile") | Some("Dockerfile") => "dockerfile",
+        Some("log") => "log", Some("csv") => "csv", Some("tsv") => "tsv",
+        Some("dockerfile") | Some("Dockerfile") => "dockerfile",
         Some("makefile") | Some("Makefile") | Some("mk") | Some("mak") => "makefile",
         Some("gd") => "gdscript", Some("gql") | Some("graphql") => "graphql",
         Some("hbs") | Some("handlebars") => "handlebars", Some("jinja") | Some("j2") => "jinja",
@@ -596,31 +3201,3 @@ fn get_language_from_extension(filename: &str) -> &str {
         _ => "",
     }
 }
-
-fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn std::error::Error>> {
-    if cfg!(target_os = "macos") {
-        if let Ok(mut child) = Command::new("pbcopy").stdin(Stdio::piped()).spawn() {
-            if let Some(mut stdin) = child.stdin.take() { stdin.write_all(text.as_bytes())?; stdin.flush()?; }
-            if child.wait()?.success() { return Ok(()); }
-        }
-    } else if cfg!(target_os = "windows") {
-        if let Ok(mut child) = Command::new("clip").stdin(Stdio::piped()).spawn() {
-            if let Some(mut stdin) = child.stdin.take() { stdin.write_all(text.as_bytes())?; stdin.flush()?; }
-            if child.wait()?.success() { return Ok(()); }
-        }
-    } else {
-        if let Ok(mut child) = Command::new("xclip").arg("-selection").arg("clipboard").stdin(Stdio::piped()).spawn() {
-            if let Some(mut stdin) = child.stdin.take() { stdin.write_all(text.as_bytes())?; stdin.flush()?; }
-            if child.wait()?.success() { return Ok(()); }
-        }
-        if let Ok(mut child) = Command::new("xsel").arg("--clipboard").arg("--input").stdin(Stdio::piped()).spawn() {
-            if let Some(mut stdin) = child.stdin.take() { stdin.write_all(text.as_bytes())?; stdin.flush()?; }
-            if child.wait()?.success() { return Ok(()); }
-        }
-        if let Ok(mut child) = Command::new("wl-copy").stdin(Stdio::piped()).spawn() {
-            if let Some(mut stdin) = child.stdin.take() { stdin.write_all(text.as_bytes())?; stdin.flush()?; }
-            if child.wait()?.success() { return Ok(()); }
-        }
-    }
-    Err("No clipboard tool found or tool failed. Please install xclip/xsel (Linux X11), wl-clipboard (Wayland), pbcopy (macOS), or ensure clip.exe is in PATH (Windows).".into())
-}
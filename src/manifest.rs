@@ -0,0 +1,69 @@
+// Run manifests: content hashes of the files copied by previous runs, used by --changed-since-last.
+// One manifest is kept per working directory in the cache directory.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::bundle::Bundle;
+
+// FNV-1a, chosen because its output is stable across Rust versions and platforms (unlike DefaultHasher).
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn manifest_path() -> PathBuf {
+    let cwd = env::current_dir().unwrap_or_default();
+    let key = stable_hash(cwd.to_string_lossy().as_bytes());
+    crate::remote::cache_dir().join("manifests").join(format!("{:016x}.tsv", key))
+}
+
+// Maps display names to content hashes from the previous run (empty if there was none).
+pub fn load() -> BTreeMap<String, u64> {
+    let Ok(contents) = fs::read_to_string(manifest_path()) else {
+        return BTreeMap::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (hash, name) = line.split_once('\t')?;
+            Some((name.to_string(), u64::from_str_radix(hash, 16).ok()?))
+        })
+        .collect()
+}
+
+// Records the hashes of this run's files, keeping entries for files this run didn't touch.
+pub fn save(previous: &BTreeMap<String, u64>, bundle: &Bundle) -> std::io::Result<()> {
+    let mut merged = previous.clone();
+    for entry in &bundle.entries {
+        merged.insert(entry.display_name.clone(), stable_hash(entry.contents.as_bytes()));
+    }
+    let path = manifest_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut serialized = String::new();
+    for (name, hash) in &merged {
+        serialized.push_str(&format!("{:016x}\t{}\n", hash, name));
+    }
+    fs::write(path, serialized)
+}
+
+// Removes entries whose contents match the previous run, returning their names.
+pub fn drop_unchanged(previous: &BTreeMap<String, u64>, bundle: &mut Bundle) -> Vec<String> {
+    let mut unchanged = Vec::new();
+    bundle.entries.retain(|entry| {
+        let same = previous.get(&entry.display_name) == Some(&stable_hash(entry.contents.as_bytes()));
+        if same {
+            unchanged.push(entry.display_name.clone());
+        }
+        !same
+    });
+    unchanged
+}
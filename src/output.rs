@@ -0,0 +1,494 @@
+// Output destinations. Each destination is written section by section from the assembled bundle, so the
+// sections are never joined into one String. When a single stdout or file destination is the only output and
+// no pass needs the whole bundle (see `can_stream`), each file section is instead streamed to it as soon as
+// it is formatted, and only the file's name and token count are kept.
+
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::bundle::{Bundle, BundleEntry};
+use crate::Config;
+use crate::{clipboard, tokens};
+
+#[derive(PartialEq)]
+pub enum Destination {
+    Clipboard,
+    // The X11/Wayland primary selection (--primary).
+    Primary,
+    Stdout,
+    File(PathBuf),
+}
+
+impl Destination {
+    pub fn describe(&self) -> String {
+        match self {
+            Destination::Clipboard => "clipboard".to_string(),
+            Destination::Primary => "primary selection".to_string(),
+            Destination::Stdout => "stdout".to_string(),
+            Destination::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+// --escape: re-encodes the whole bundle for pasting into another format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Escape {
+    // One JSON string literal (quoted, with newlines and quotes escaped), e.g. for an API request body.
+    Json,
+}
+
+impl Escape {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Escape::Json),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, bundle: &Bundle) -> io::Result<String> {
+        let mut rendered = Vec::new();
+        bundle.write_to(&mut rendered)?;
+        let rendered = String::from_utf8_lossy(&rendered);
+        match self {
+            Escape::Json => serde_json::to_string(&rendered).map_err(io::Error::other),
+        }
+    }
+}
+
+// --checksum: the SHA-256 of the bundle as it will be written, in lowercase hex.
+pub fn checksum(bundle: &Bundle) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    bundle.write_to(&mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// The closing --checksum line. It covers everything before it, up to but not including the separator in front of it.
+pub fn format_checksum(checksum: &str, use_xml: bool) -> String {
+    if use_xml {
+        format!("<checksum algorithm=\"sha256\">{}</checksum>", checksum)
+    } else {
+        format!("SHA-256 of the content above: {}", checksum)
+    }
+}
+
+// The values accepted by --format and the config file's `format` key.
+pub const FORMATS: [&str; 5] = ["markdown", "xml", "aider", "repomix", "code2prompt"];
+
+// Layouts matching other context-packing tools, so their downstream parsers can read toprompt bundles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preset {
+    // `path` on its own line, then the contents in a plain ``` fence, as aider sends files to the model.
+    Aider,
+    // Repomix's default XML output: a file summary, the directory structure, then `<file path="">` entries.
+    Repomix,
+    // code2prompt's default template: project path, source tree, then `` `path`: `` and a fenced block per file.
+    Code2prompt,
+}
+
+impl Preset {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "aider" => Some(Preset::Aider),
+            "repomix" => Some(Preset::Repomix),
+            "code2prompt" => Some(Preset::Code2prompt),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Aider => "aider",
+            Preset::Repomix => "repomix",
+            Preset::Code2prompt => "code2prompt",
+        }
+    }
+
+    // One file in the preset's layout; `body` has no trailing whitespace.
+    pub fn format_file(self, display_name: &str, language: &str, body: &str) -> String {
+        match self {
+            Preset::Aider => format!("{}\n```\n{}\n```", display_name, body),
+            Preset::Repomix => format!("<file path=\"{}\">\n{}\n</file>", display_name, body),
+            Preset::Code2prompt => format!("`{}`:\n\n```{}\n{}\n```", display_name, language, body),
+        }
+    }
+
+    // Adds the preset's header (and, for repomix, the closing tag) around the bundle's files.
+    pub fn wrap(self, bundle: &mut Bundle, root: &Path, config: &Config) {
+        let files: Vec<&str> = bundle.entries.iter().filter(|entry| entry.path.is_some()).map(|entry| entry.display_name.as_str()).collect();
+        let tree = path_tree(&files);
+        match self {
+            Preset::Aider => {}
+            Preset::Repomix => {
+                let mut structure = String::new();
+                render_indented(&tree, 0, &mut structure);
+                bundle.preamble.insert(
+                    0,
+                    format!(
+                        "This file is a merged representation of the codebase, combined into a single document by toprompt in Repomix's format.\n\n\
+                         <file_summary>\n\
+                         This section contains a summary of this file.\n\n\
+                         <purpose>\n\
+                         This file contains a packed representation of the repository's contents.\n\
+                         It is designed to be easily consumable by AI systems for analysis, code review,\n\
+                         or other automated processes.\n\
+                         </purpose>\n\n\
+                         <file_format>\n\
+                         The content is organized as follows:\n\
+                         1. This summary section\n\
+                         2. Directory structure\n\
+                         3. Repository files, each consisting of:\n  \
+                         - File path as an attribute\n  \
+                         - Full contents of the file\n\
+                         </file_format>\n\
+                         </file_summary>\n\n\
+                         <directory_structure>\n{}</directory_structure>\n\n\
+                         <files>\n\
+                         This section contains the contents of the repository's files.",
+                        structure
+                    ),
+                );
+                bundle.appendix.insert(0, "</files>".to_string());
+            }
+            Preset::Code2prompt => {
+                let root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+                let root_name = root.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| "/".to_string());
+                let mut source_tree = root_name;
+                render_branches(&tree, "", &mut source_tree);
+                bundle.preamble.insert(0, format!("Project Path: {}\n\nSource Tree:\n\n```\n{}\n```", crate::anonymize(config, &root.display().to_string()), source_tree));
+            }
+        }
+    }
+}
+
+// Nested directories (BTreeMap keys) holding files (empty maps) built from slash-separated paths.
+#[derive(Default)]
+struct Tree(BTreeMap<String, Tree>);
+
+fn path_tree(paths: &[&str]) -> Tree {
+    let mut tree = Tree::default();
+    for path in paths {
+        let mut node = &mut tree;
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            node = node.0.entry(part.to_string()).or_default();
+        }
+    }
+    tree
+}
+
+// `dir/` then its children indented by two spaces, as in Repomix's directory structure.
+fn render_indented(tree: &Tree, depth: usize, out: &mut String) {
+    for (name, child) in &tree.0 {
+        let slash = if child.0.is_empty() { "" } else { "/" };
+        out.push_str(&format!("{}{}{}\n", "  ".repeat(depth), name, slash));
+        render_indented(child, depth + 1, out);
+    }
+}
+
+// `├──`/`└──` branches, as in code2prompt's source tree.
+fn render_branches(tree: &Tree, prefix: &str, out: &mut String) {
+    let count = tree.0.len();
+    for (i, (name, child)) in tree.0.iter().enumerate() {
+        let last = i + 1 == count;
+        out.push_str(&format!("\n{}{} {}", prefix, if last { "└──" } else { "├──" }, name));
+        render_branches(child, &format!("{}{}", prefix, if last { "    " } else { "│   " }), out);
+    }
+}
+
+// Whether the bundle can be streamed to its destination while the files are read. Every option listed here
+// needs all the formatted files at once (ranking, budgets, headers summarizing the files, rewriting the whole
+// text, or reading it back for a preview or a second destination); clipboard tools are retried from the
+// start, so they need the whole text too. With a size cap, asking whether to continue needs the whole bundle
+// as well, so a cap only streams when nobody is there to ask and the output simply stops at the cap.
+pub fn can_stream(config: &Config) -> bool {
+    let [destination @ (Destination::Stdout | Destination::File(_))] = config.destinations.as_slice() else {
+        return false;
+    };
+    let capped = config.max_total_size.is_some() || config.max_total_tokens.is_some();
+    // Verbose runs that write a file print a preview of the written text afterwards.
+    let previewed = config.verbose && *destination != Destination::Stdout;
+    !(config.porcelain
+        || config.per_dir_output.is_some()
+        || config.slot.is_some()
+        || config.preview.is_some()
+        || previewed
+        || config.edit
+        || config.checksum
+        || config.escape.is_some()
+        || config.fit
+        || config.bare
+        || config.pseudonymize
+        || config.root_headers
+        || config.expand_imports.is_some()
+        || config.with_tests
+        || config.query.is_some()
+        || config.hot.is_some()
+        || config.keep_arg_order
+        || !config.priority.is_empty()
+        || !config.budget.is_empty()
+        || config.tiered
+        || config.max_files.is_some()
+        || config.changed_since_last
+        || config.at.is_some()
+        || config.deps
+        || !config.crates.is_empty()
+        || config.context_header
+        || config.todos
+        || config.symbol_index
+        || !matches!(config.preset, None | Some(Preset::Aider))
+        || (capped && io::stdin().is_terminal()))
+}
+
+// Why a stream stopped before the end of the bundle.
+pub enum Stop {
+    // The next section would have gone over --max-total-size or --max-total-tokens.
+    TooLarge(String),
+    Failed(io::Error),
+}
+
+// A destination written to while the files are read (see `can_stream`).
+pub struct Stream {
+    writer: Box<dyn Write>,
+    // For a file, the path written to and the final one: it is renamed once complete, so a run stopped by the
+    // size cap or an error leaves no truncated bundle behind.
+    file: Option<(PathBuf, PathBuf)>,
+    separator: String,
+    sections: usize,
+    size: u64,
+    tokens: usize,
+    max_size: Option<u64>,
+    max_tokens: Option<usize>,
+    stop: Option<Stop>,
+}
+
+impl Stream {
+    pub fn open(destination: &Destination, config: &Config) -> io::Result<Stream> {
+        let (writer, file): (Box<dyn Write>, _) = match destination {
+            Destination::File(path) => {
+                let mut partial = path.clone().into_os_string();
+                partial.push(".partial");
+                let partial = PathBuf::from(partial);
+                (Box::new(BufWriter::new(File::create(&partial)?)), Some((partial, path.clone())))
+            }
+            _ => (Box::new(BufWriter::new(io::stdout())), None),
+        };
+        Ok(Stream {
+            writer,
+            file,
+            separator: config.separator.clone(),
+            sections: 0,
+            size: 0,
+            tokens: 0,
+            max_size: config.max_total_size,
+            max_tokens: config.max_total_tokens,
+            stop: None,
+        })
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    // Writes one section unless it would go over a cap. Returns whether it was written.
+    pub fn write(&mut self, section: &str, tokens: usize) -> bool {
+        if self.stop.is_some() {
+            return false;
+        }
+        let separator = if self.sections > 0 { self.separator.len() } else { 0 };
+        let size = self.size + (separator + section.len()) as u64;
+        if let Some(max) = self.max_size
+            && size > max
+        {
+            self.stop = Some(Stop::TooLarge(format!("{} would exceed --max-total-size {}", crate::format_size(size), crate::format_size(max))));
+            return false;
+        }
+        if let Some(max) = self.max_tokens
+            && self.tokens + tokens > max
+        {
+            self.stop = Some(Stop::TooLarge(format!("~{} tokens would exceed --max-total-tokens {}", self.tokens + tokens, max)));
+            return false;
+        }
+        let written = if self.sections > 0 { self.writer.write_all(self.separator.as_bytes()) } else { Ok(()) };
+        if let Err(e) = written.and_then(|_| self.writer.write_all(section.as_bytes())) {
+            self.stop = Some(Stop::Failed(e));
+            return false;
+        }
+        self.sections += 1;
+        self.size = size;
+        self.tokens += tokens;
+        true
+    }
+
+    // Writes the appendix and completes the destination, or discards what was written if the stream stopped.
+    pub fn finish(mut self, appendix: &[String]) -> Result<(), Stop> {
+        for section in appendix {
+            self.write(section, tokens::estimate_tokens(section));
+        }
+        if self.stop.is_none()
+            && let Err(e) = self.complete()
+        {
+            self.stop = Some(Stop::Failed(e));
+        }
+        match self.stop.take() {
+            None => Ok(()),
+            Some(stop) => {
+                self.discard();
+                Err(stop)
+            }
+        }
+    }
+
+    fn complete(&mut self) -> io::Result<()> {
+        // Stdout ends with a newline, as when the whole bundle is written at once.
+        if self.file.is_none() {
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.flush()?;
+        if let Some((partial, path)) = &self.file {
+            std::fs::rename(partial, path)?;
+        }
+        Ok(())
+    }
+
+    // Removes a partial file; what already went to stdout cannot be taken back.
+    pub fn discard(self) {
+        if let Some((partial, _)) = &self.file {
+            let _ = std::fs::remove_file(partial);
+        }
+    }
+}
+
+pub fn write_bundle(bundle: &Bundle, destination: &Destination, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match destination {
+        Destination::Clipboard if config.rich => {
+            let mut text = Vec::new();
+            bundle.write_to(&mut text)?;
+            let text = String::from_utf8_lossy(&text);
+            clipboard::copy_rich(&text, &crate::rich::to_html(&text, config.use_xml))
+        }
+        Destination::Clipboard => clipboard::copy_streaming(clipboard::Selection::Clipboard, &|writer| bundle.write_to(writer)),
+        Destination::Primary => clipboard::copy_streaming(clipboard::Selection::Primary, &|writer| bundle.write_to(writer)),
+        Destination::Stdout => {
+            let stdout = io::stdout();
+            let mut writer = BufWriter::new(stdout.lock());
+            bundle.write_to(&mut writer)?;
+            writeln!(writer)?;
+            writer.flush()?;
+            Ok(())
+        }
+        Destination::File(path) => {
+            let file = File::create(path).map_err(|e| format!("could not create '{}': {}", path.display(), e))?;
+            let mut writer = BufWriter::new(file);
+            bundle.write_to(&mut writer)?;
+            writer.flush()?;
+            Ok(())
+        }
+    }
+}
+
+// --per-dir-output: writes the file sections of each top-level directory of `base` (or of each member crate when
+// `base` is a Cargo workspace) to their own bundle in `dir`, e.g. src.md and tests.md, in the bundle's order.
+// Each is self-contained, with the bundle's preamble and appendix around its files. Files directly in `base`
+// go to @root.md. Returns the written file names with their file and token counts.
+pub fn write_per_dir(bundle: &Bundle, base: &Path, dir: &Path, config: &Config) -> io::Result<Vec<(String, usize, usize)>> {
+    let base = std::fs::canonicalize(base)?;
+    let members = crate::workspace::members(&base);
+    let mut groups: Vec<(String, Vec<&BundleEntry>)> = Vec::new();
+    for entry in &bundle.entries {
+        let key = group_of(entry, &base, &members);
+        match groups.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, entries)) => entries.push(entry),
+            None => groups.push((key, vec![entry])),
+        }
+    }
+
+    std::fs::create_dir_all(dir)?;
+    let extension = if config.use_xml { "xml" } else { "md" };
+    let mut written = Vec::new();
+    for (key, entries) in groups {
+        let name = format!("{}.{}", key, extension);
+        let mut writer = BufWriter::new(File::create(dir.join(&name))?);
+        let sections = bundle.preamble.iter().chain(entries.iter().map(|entry| &entry.segment)).chain(bundle.appendix.iter());
+        for (i, section) in sections.enumerate() {
+            if i > 0 {
+                writer.write_all(bundle.separator.as_bytes())?;
+            }
+            writer.write_all(section.as_bytes())?;
+        }
+        writer.flush()?;
+        let tokens = entries.iter().map(|entry| entry.tokens).sum::<usize>()
+            + bundle.preamble.iter().chain(bundle.appendix.iter()).map(|section| crate::tokens::estimate_tokens(section)).sum::<usize>();
+        written.push((name, entries.len(), tokens));
+    }
+    Ok(written)
+}
+
+// The --per-dir-output bundle an entry belongs to, as a file-name-safe key. Directory and crate names are reduced
+// to letters, digits, '-' and '_', so the catch-all groups start with '@' and cannot clash with them.
+fn group_of(entry: &BundleEntry, base: &Path, members: &[crate::workspace::Member]) -> String {
+    match &entry.path {
+        // The innermost member, so a crate nested in another crate's directory gets its own bundle.
+        Some(path) if !members.is_empty() => members
+            .iter()
+            .filter(|member| path.starts_with(&member.dir))
+            .max_by_key(|member| member.dir.components().count())
+            .map_or_else(|| "@workspace".to_string(), |member| sanitize(&member.name)),
+        Some(path) => match path.strip_prefix(base).ok().map(|relative| relative.components().collect::<Vec<_>>()) {
+            Some(components) if components.len() > 1 => sanitize(&components[0].as_os_str().to_string_lossy()),
+            Some(_) => "@root".to_string(),
+            None => "@other".to_string(),
+        },
+        // Fetched documents have no local path; they are grouped by their source's first segment.
+        None => entry.display_name.split_once('/').map_or_else(|| "@root".to_string(), |(first, _)| sanitize(first)),
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() || "-_".contains(c) { c } else { '_' }).collect()
+}
+
+// One tab-separated record per file for --porcelain: status, path, bytes, tokens.
+// Editor plugins parse this, so keep it stable: only ever add statuses or trailing columns.
+pub fn write_porcelain(bundle: &Bundle, unchanged: &[String], checksum: Option<&str>, writer: &mut dyn Write) -> io::Result<()> {
+    for entry in &bundle.entries {
+        writeln!(writer, "included\t{}\t{}\t{}", escape_field(&entry.display_name), entry.segment.len(), entry.tokens)?;
+    }
+    for name in &bundle.skipped_duplicates {
+        writeln!(writer, "duplicate\t{}\t-\t-", escape_field(name))?;
+    }
+    for name in &bundle.auto_skipped {
+        writeln!(writer, "auto-skipped\t{}\t-\t-", escape_field(name))?;
+    }
+    for name in &bundle.skipped_empty {
+        writeln!(writer, "empty\t{}\t-\t-", escape_field(name))?;
+    }
+    for name in unchanged {
+        writeln!(writer, "unchanged\t{}\t-\t-", escape_field(name))?;
+    }
+    if let Some(checksum) = checksum {
+        writeln!(writer, "checksum\tsha256\t{}\t-", checksum)?;
+    }
+    writer.flush()
+}
+
+// The --list-excluded section: paths that exist but were left out, so the model knows about them.
+pub fn format_excluded(excluded: &[(String, String)], use_xml: bool) -> String {
+    if use_xml {
+        let items: Vec<String> = excluded
+            .iter()
+            .map(|(path, reason)| format!("<path reason=\"{}\">{}</path>", reason.replace('"', "&quot;"), path))
+            .collect();
+        format!("<excluded>\n{}\n</excluded>", items.join("\n"))
+    } else {
+        let items: Vec<String> = excluded.iter().map(|(path, reason)| format!("- {} ({})", path, reason)).collect();
+        format!("# Excluded (exist, but their contents were not included)\n{}", items.join("\n"))
+    }
+}
+
+// Escapes backslashes, tabs and newlines so a path always stays within its field.
+fn escape_field(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
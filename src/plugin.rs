@@ -0,0 +1,90 @@
+// Custom pipeline stages. A `Transform` rewrites a file's contents after the built-in transforms; a `Formatter`
+// replaces the markdown/XML/preset layout of each file. Both are traits so stages can be registered in code, and
+// `External` implements them with a command speaking a stdin/stdout protocol (--transform-plugin,
+// --format-plugin, or `transform_plugins`/`format_plugin` in the config file):
+//   - the file's text (its contents, or for a formatter the final body) is written to the command's stdin;
+//   - TOPROMPT_STAGE ("transform" or "format"), TOPROMPT_PATH and TOPROMPT_LANGUAGE describe the file;
+//   - whatever the command prints becomes the new contents (or the file's whole segment);
+//   - a non-zero exit fails the run, so a broken redaction stage never lets the original text through.
+// --pipe-through uses the same protocol (TOPROMPT_STAGE=pipe) as a lighter preprocessing hook: it runs before any
+// built-in transform, and a command that fails on a file leaves that file as it was, so tools like `jq .` can be
+// applied across a mixed directory.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub trait Transform {
+    // Shown in errors and part of the cache key, so it should change when the output would.
+    fn name(&self) -> &str;
+    fn transform(&self, path: &Path, language: &str, contents: &str) -> Result<String, String>;
+}
+
+pub trait Formatter {
+    fn name(&self) -> &str;
+    // The whole segment for one file; `display_name` is the path as shown in the bundle.
+    fn format(&self, display_name: &str, language: &str, body: &str) -> Result<String, String>;
+}
+
+// A plugin command, run through the shell so it may carry arguments.
+pub struct External {
+    pub command: String,
+    // Where the command runs: the config file's directory for plugins it defines, otherwise the working directory.
+    pub dir: Option<PathBuf>,
+}
+
+impl External {
+    pub fn run(&self, stage: &str, path: &str, language: &str, input: &str) -> Result<String, String> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(&self.command);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(&self.command);
+            command
+        };
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        let mut child = command
+            .env("TOPROMPT_STAGE", stage)
+            .env("TOPROMPT_PATH", path)
+            .env("TOPROMPT_LANGUAGE", language)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("could not run it: {}", e))?;
+        // Written from a thread so a plugin that streams its output before reading all of its input cannot deadlock.
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = input.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        // A plugin may exit without reading its input; only its exit status and output matter then.
+        let _ = writer.join();
+        if !output.status.success() {
+            return Err(output.status.to_string());
+        }
+        String::from_utf8(output.stdout).map_err(|_| "printed invalid UTF-8".to_string())
+    }
+}
+
+impl Transform for External {
+    fn name(&self) -> &str {
+        &self.command
+    }
+
+    fn transform(&self, path: &Path, language: &str, contents: &str) -> Result<String, String> {
+        self.run("transform", &path.to_string_lossy(), language, contents)
+    }
+}
+
+impl Formatter for External {
+    fn name(&self) -> &str {
+        &self.command
+    }
+
+    fn format(&self, display_name: &str, language: &str, body: &str) -> Result<String, String> {
+        self.run("format", display_name, language, body).map(|segment| segment.trim_end().to_string())
+    }
+}
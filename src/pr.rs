@@ -0,0 +1,158 @@
+// `toprompt pr <url|number>`: bundles a GitHub pull request (description, changed files and diff)
+// as a review prompt. Talks to GitHub through the `gh` CLI, which handles authentication
+// (including GH_TOKEN/GITHUB_TOKEN) for us.
+
+use std::process::{Command, Stdio};
+
+struct PullRequest {
+    number: String,
+    url: String,
+    base_ref: String,
+    head_ref: String,
+    title: String,
+    body: String,
+}
+
+struct ChangedFile {
+    path: String,
+    additions: String,
+    deletions: String,
+}
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt pr [--xml] [-v] <url|number>");
+    eprintln!("  Fetches a pull request's description, changed files and diff via the GitHub CLI (gh)");
+    eprintln!("  and copies them to the clipboard as a review bundle.");
+    eprintln!("  A bare number refers to a pull request of the repository in the current directory.");
+}
+
+pub fn run(args: &[String]) {
+    let mut use_xml = false;
+    let mut verbose = false;
+    let mut target = None;
+    for arg in args {
+        match arg.as_str() {
+            "--xml" => use_xml = true,
+            "-v" => verbose = true,
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown argument for pr: {}", arg);
+                print_usage();
+                std::process::exit(crate::EXIT_ERROR);
+            }
+            _ if target.is_none() => target = Some(arg.clone()),
+            _ => {
+                eprintln!("Error: pr takes a single pull request url or number.");
+                print_usage();
+                std::process::exit(crate::EXIT_ERROR);
+            }
+        }
+    }
+    let Some(target) = target else {
+        print_usage();
+        std::process::exit(crate::EXIT_ERROR);
+    };
+
+    let (output, pr, file_count) = match build_bundle(&target, use_xml) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            eprintln!("Error fetching pull request '{}': {}", target, e);
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    };
+
+    match crate::clipboard::copy_to_clipboard(&output) {
+        Ok(_) => {
+            println!(":: Copied PR #{} ({} changed files) ::", pr.number, file_count);
+            println!("{}", pr.title);
+            if verbose {
+                println!("\n--- Clipboard Contents ---\n\n{}", output);
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to copy to clipboard: {}", e);
+            println!("\n--- Output (not copied to clipboard) ---\n");
+            println!("{}", output);
+            std::process::exit(crate::EXIT_OUTPUT_FAILED);
+        }
+    }
+}
+
+fn build_bundle(target: &str, use_xml: bool) -> Result<(String, PullRequest, usize), Box<dyn std::error::Error>> {
+    let pr = fetch_pull_request(target)?;
+    let files = fetch_changed_files(target)?;
+    let diff = gh(&["pr", "diff", target])?;
+
+    let mut output = String::new();
+    if use_xml {
+        output.push_str(&format!(
+            "<pull_request number=\"{}\" url=\"{}\" base=\"{}\" head=\"{}\">\n<title>{}</title>\n<description>\n{}\n</description>\n<changed_files>\n",
+            pr.number, pr.url, pr.base_ref, pr.head_ref, pr.title, pr.body.trim_end()
+        ));
+        for file in &files {
+            output.push_str(&format!(
+                "<file path=\"{}\" additions=\"{}\" deletions=\"{}\" />\n",
+                file.path, file.additions, file.deletions
+            ));
+        }
+        output.push_str(&format!("</changed_files>\n<diff>\n{}\n</diff>\n</pull_request>", diff.trim_end()));
+    } else {
+        output.push_str(&format!("# PR #{}: {}\n{}\n", pr.number, pr.title, pr.url));
+        output.push_str(&format!("Merging `{}` into `{}`\n", pr.head_ref, pr.base_ref));
+        if !pr.body.trim().is_empty() {
+            output.push_str(&format!("\n{}\n", pr.body.trim_end()));
+        }
+        output.push_str("\n# Changed files\n");
+        for file in &files {
+            output.push_str(&format!("- {} (+{} -{})\n", file.path, file.additions, file.deletions));
+        }
+        output.push_str(&format!("\n# Diff\n```diff\n{}\n```", diff.trim_end()));
+    }
+    let file_count = files.len();
+    Ok((output, pr, file_count))
+}
+
+fn fetch_pull_request(target: &str) -> Result<PullRequest, Box<dyn std::error::Error>> {
+    let template = "{{.number}}\t{{.url}}\t{{.baseRefName}}\t{{.headRefName}}\n{{.title}}\n{{.body}}";
+    let raw = gh(&["pr", "view", target, "--json", "number,url,baseRefName,headRefName,title,body", "--template", template])?;
+    let mut lines = raw.splitn(3, '\n');
+    let header: Vec<&str> = lines.next().unwrap_or_default().split('\t').collect();
+    if header.len() != 4 {
+        return Err("unexpected response from gh pr view".into());
+    }
+    Ok(PullRequest {
+        number: header[0].to_string(),
+        url: header[1].to_string(),
+        base_ref: header[2].to_string(),
+        head_ref: header[3].to_string(),
+        title: lines.next().unwrap_or_default().to_string(),
+        body: lines.next().unwrap_or_default().to_string(),
+    })
+}
+
+fn fetch_changed_files(target: &str) -> Result<Vec<ChangedFile>, Box<dyn std::error::Error>> {
+    let template = "{{range .files}}{{.path}}\t{{.additions}}\t{{.deletions}}\n{{end}}";
+    let raw = gh(&["pr", "view", target, "--json", "files", "--template", template])?;
+    Ok(raw
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(ChangedFile {
+                path: fields.next()?.to_string(),
+                additions: fields.next()?.to_string(),
+                deletions: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn gh(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("gh")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run the GitHub CLI (gh): {}. Install it from https://cli.github.com and run `gh auth login`.", e))?;
+    if !output.status.success() {
+        return Err(format!("gh {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
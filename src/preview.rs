@@ -0,0 +1,141 @@
+// Terminal preview of the bundle through a pager, syntax highlighted with bat when it is installed.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+use crate::bundle::Bundle;
+use crate::transform::{self, LineLimitMode};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PreviewMode {
+    // The first HEAD_LINES lines of the bundle.
+    Head,
+    Full,
+}
+
+const HEAD_LINES: usize = 200;
+
+// How much of the bundle the verbose preview shows (--preview-chars / --preview-lines).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PreviewLength {
+    Chars(usize),
+    Lines(usize),
+}
+
+// Describes the excerpt for the verbose preview header, e.g. "first 500 chars".
+pub fn describe(length: PreviewLength, mode: LineLimitMode) -> String {
+    let (count, unit) = match length {
+        PreviewLength::Chars(count) => (count, "chars"),
+        PreviewLength::Lines(count) => (count, "lines"),
+    };
+    match mode {
+        LineLimitMode::Head => format!("first {} {}", count, unit),
+        LineLimitMode::Tail => format!("last {} {}", count, unit),
+        LineLimitMode::Split => format!("first and last {} {}", count, unit),
+    }
+}
+
+// The part of the bundle shown by the verbose preview. Cuts fall on char boundaries.
+pub fn excerpt(bundle: &Bundle, length: PreviewLength, mode: LineLimitMode) -> String {
+    // The common case renders only what it shows.
+    if let (PreviewLength::Chars(count), LineLimitMode::Head) = (length, mode) {
+        return format!("{}...", bundle.preview(count));
+    }
+    let mut rendered = Vec::new();
+    // Writing into a Vec cannot fail.
+    let _ = bundle.write_to(&mut rendered);
+    let rendered = String::from_utf8_lossy(&rendered);
+    match length {
+        PreviewLength::Lines(count) => transform::limit_lines(&rendered, count, mode),
+        PreviewLength::Chars(count) if rendered.chars().count() <= count => rendered.into_owned(),
+        PreviewLength::Chars(count) => {
+            let head_len = if mode == LineLimitMode::Split { count.div_ceil(2) } else { 0 };
+            let tail_len = count - head_len;
+            let head: String = rendered.chars().take(head_len).collect();
+            let tail_start = match tail_len {
+                0 => rendered.len(),
+                n => rendered.char_indices().rev().nth(n - 1).map_or(0, |(i, _)| i),
+            };
+            match mode {
+                LineLimitMode::Split => format!("{}\n...\n{}", head, &rendered[tail_start..]),
+                _ => format!("...{}", &rendered[tail_start..]),
+            }
+        }
+    }
+}
+
+pub fn show(bundle: &Bundle, mode: PreviewMode, use_xml: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let write_preview = |writer: &mut dyn Write| -> io::Result<()> {
+        match mode {
+            PreviewMode::Full => bundle.write_to(writer)?,
+            PreviewMode::Head => {
+                let mut rendered = Vec::new();
+                bundle.write_to(&mut rendered)?;
+                let rendered = String::from_utf8_lossy(&rendered);
+                let total_lines = rendered.lines().count();
+                for line in rendered.lines().take(HEAD_LINES) {
+                    writeln!(writer, "{}", line)?;
+                }
+                if total_lines > HEAD_LINES {
+                    writeln!(writer, "\n... (showing {} of {} lines; use --preview=full for everything) ...", HEAD_LINES, total_lines)?;
+                }
+            }
+        }
+        writeln!(writer)
+    };
+
+    // Without a terminal there is nothing to page; keep stdout free for the bundle itself.
+    if !io::stdout().is_terminal() {
+        return Ok(write_preview(&mut io::stderr().lock())?);
+    }
+
+    let mut child = spawn_pager(use_xml)?;
+    let mut stdin = child.stdin.take().ok_or("could not open the pager's stdin")?;
+    let result = write_preview(&mut stdin);
+    drop(stdin);
+    let status = child.wait()?;
+    match result {
+        // Quitting the pager early closes the pipe; that is not an error.
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => {}
+    }
+    if !status.success() {
+        return Err(format!("pager exited with {}", status).into());
+    }
+    Ok(())
+}
+
+// bat (packaged as batcat on Debian) pages with highlighting; otherwise $PAGER, falling back to less.
+fn spawn_pager(use_xml: bool) -> Result<Child, Box<dyn std::error::Error>> {
+    let language = if use_xml { "xml" } else { "markdown" };
+    for highlighter in ["bat", "batcat"] {
+        match Command::new(highlighter)
+            .args(["--paging=always", "--style=plain", "--language", language])
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => return Ok(child),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("could not run {}: {}", highlighter, e).into()),
+        }
+    }
+
+    let pager = env::var("PAGER").ok().filter(|value| !value.trim().is_empty()).unwrap_or_else(|| {
+        if cfg!(windows) { "more".to_string() } else { "less -R".to_string() }
+    });
+    // Through the shell, so a $PAGER with arguments works.
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(&pager);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&pager);
+        command
+    };
+    command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run pager '{}': {}", pager, e).into())
+}
@@ -0,0 +1,69 @@
+// --pseudonymize: consistently renames project-specific names (customers, codenames, ...) matched by the
+// configured patterns throughout the bundle, paths included. Each distinct name (compared case-insensitively)
+// becomes Name1, Name2, ... in order of first appearance, written in the original's case (NAME1, name1), so
+// the code still reads the same without the real names leaving the machine.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use crate::bundle::Bundle;
+use crate::tokens;
+
+struct Names<'a> {
+    patterns: &'a [Regex],
+    // Lowercased name -> its number.
+    numbers: HashMap<String, usize>,
+    // (pseudonym, name as first seen), in order.
+    legend: Vec<(String, String)>,
+}
+
+impl Names<'_> {
+    fn rewrite(&mut self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in self.patterns {
+            if !pattern.is_match(&text) {
+                continue;
+            }
+            text = pattern.replace_all(&text, |caps: &regex::Captures| self.pseudonym(&caps[0])).into_owned();
+        }
+        text
+    }
+
+    fn pseudonym(&mut self, name: &str) -> String {
+        let next = self.numbers.len() + 1;
+        let number = *self.numbers.entry(name.to_lowercase()).or_insert_with(|| {
+            self.legend.push((format!("Name{}", next), name.to_string()));
+            next
+        });
+        let has_lower = name.chars().any(char::is_lowercase);
+        if name.chars().any(char::is_uppercase) && !has_lower {
+            format!("NAME{}", number)
+        } else if name.starts_with(char::is_uppercase) || !has_lower {
+            format!("Name{}", number)
+        } else {
+            format!("name{}", number)
+        }
+    }
+}
+
+// Rewrites every section, file name and file of the bundle; returns the (pseudonym, original) legend.
+pub fn apply(bundle: &mut Bundle, patterns: &[Regex]) -> Vec<(String, String)> {
+    let mut names = Names { patterns, numbers: HashMap::new(), legend: Vec::new() };
+    for section in bundle.preamble.iter_mut() {
+        *section = names.rewrite(section);
+    }
+    for entry in bundle.entries.iter_mut() {
+        entry.display_name = names.rewrite(&entry.display_name);
+        entry.contents = names.rewrite(&entry.contents);
+        let segment = names.rewrite(&entry.segment);
+        if segment != entry.segment {
+            entry.tokens = tokens::estimate_tokens(&segment);
+            entry.segment = segment;
+        }
+    }
+    for section in bundle.appendix.iter_mut() {
+        *section = names.rewrite(section);
+    }
+    names.legend
+}
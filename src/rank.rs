@@ -0,0 +1,103 @@
+// Keyword relevance ranking (BM25) for --query.
+
+use std::collections::HashMap;
+
+use crate::bundle::{Bundle, BundleEntry};
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// Matches in the file path are a strong relevance signal, so path terms count extra.
+const PATH_TERM_WEIGHT: usize = 3;
+
+// Splits text into lowercase terms, breaking on punctuation, snake_case and camelCase boundaries.
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut previous_lower = false;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            if c.is_uppercase() && previous_lower && !current.is_empty() {
+                terms.push(std::mem::take(&mut current));
+            }
+            previous_lower = c.is_lowercase() || c.is_ascii_digit();
+            current.extend(c.to_lowercase());
+        } else {
+            if !current.is_empty() {
+                terms.push(std::mem::take(&mut current));
+            }
+            previous_lower = false;
+        }
+    }
+    if !current.is_empty() {
+        terms.push(current);
+    }
+    terms.retain(|t| t.chars().count() > 1);
+    terms
+}
+
+fn document_terms(entry: &BundleEntry) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for term in terms(&entry.contents) {
+        *counts.entry(term).or_insert(0) += 1;
+    }
+    for term in terms(&entry.display_name) {
+        *counts.entry(term).or_insert(0) += PATH_TERM_WEIGHT;
+    }
+    counts
+}
+
+// BM25 score of every entry against the query, in entry order.
+pub fn score(entries: &[BundleEntry], query: &str) -> Vec<f64> {
+    let query_terms = terms(query);
+    let documents: Vec<HashMap<String, usize>> = entries.iter().map(document_terms).collect();
+    let lengths: Vec<usize> = documents.iter().map(|d| d.values().sum()).collect();
+    let average_length = (lengths.iter().sum::<usize>() as f64 / documents.len().max(1) as f64).max(1.0);
+    let document_count = documents.len() as f64;
+
+    documents
+        .iter()
+        .zip(&lengths)
+        .map(|(document, &length)| {
+            query_terms
+                .iter()
+                .map(|term| {
+                    let frequency = *document.get(term).unwrap_or(&0) as f64;
+                    if frequency == 0.0 {
+                        return 0.0;
+                    }
+                    let containing = documents.iter().filter(|d| d.contains_key(term)).count() as f64;
+                    let idf = ((document_count - containing + 0.5) / (containing + 0.5) + 1.0).ln();
+                    idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length as f64 / average_length))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// Keeps only the entries most relevant to `query`, most relevant first, limited by count and/or token budget.
+// Returns the number of candidates that were dropped.
+pub fn apply_query(bundle: &mut Bundle, query: &str, top: usize, token_budget: Option<usize>, verbose: bool) -> usize {
+    let scores = score(&bundle.entries, query);
+    let mut ranked: Vec<(f64, BundleEntry)> = scores.into_iter().zip(bundle.entries.drain(..)).collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let candidate_count = ranked.len();
+    let mut used_tokens = 0;
+    for (score, entry) in ranked {
+        if score <= 0.0 || bundle.entries.len() >= top {
+            break;
+        }
+        let entry_tokens = entry.tokens;
+        if let Some(budget) = token_budget
+            && used_tokens + entry_tokens > budget
+        {
+            continue;
+        }
+        if verbose {
+            println!("Query match ({:.3}, ~{} tokens): {}", score, entry_tokens, entry.display_name);
+        }
+        used_tokens += entry_tokens;
+        bundle.entries.push(entry);
+    }
+    candidate_count - bundle.entries.len()
+}
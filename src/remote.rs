@@ -0,0 +1,150 @@
+// Remote git repository inputs: `https://github.com/owner/repo[#ref]` arguments are shallow-cloned
+// into a cache directory and then processed like any local directory. A cached clone is updated from
+// the remote each time it is reused; --fresh-clone discards it and clones again.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const KNOWN_GIT_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "codeberg.org"];
+
+pub struct RemoteRepo {
+    pub url: String,
+    pub host: String,
+    pub owner: String,
+    pub name: String,
+    pub git_ref: Option<String>,
+}
+
+impl RemoteRepo {
+    // Recognizes `https://<known host>/owner/repo[.git][#ref]` and any other `https://...git[#ref]` URL.
+    pub fn parse(arg: &str) -> Option<Self> {
+        let rest = arg.strip_prefix("https://").or_else(|| arg.strip_prefix("http://"))?;
+        let (location, git_ref) = match rest.split_once('#') {
+            Some((location, git_ref)) if !git_ref.is_empty() => (location, Some(git_ref.to_string())),
+            Some((location, _)) => (location, None),
+            None => (rest, None),
+        };
+        let location = location.trim_end_matches('/');
+        let segments: Vec<&str> = location.split('/').collect();
+        let host = *segments.first()?;
+        let is_known_repo = KNOWN_GIT_HOSTS.contains(&host) && segments.len() == 3;
+        if !is_known_repo && !location.ends_with(".git") {
+            return None;
+        }
+        if segments.len() < 3 {
+            return None;
+        }
+        let name = segments[segments.len() - 1].trim_end_matches(".git").to_string();
+        let owner = segments[segments.len() - 2].to_string();
+        let scheme = if arg.starts_with("https://") { "https" } else { "http" };
+        Some(RemoteRepo {
+            url: format!("{}://{}", scheme, location),
+            host: host.to_string(),
+            owner,
+            name,
+            git_ref,
+        })
+    }
+
+    // Label used in place of the clone directory when displaying file names.
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.owner, self.name)
+    }
+
+    // `#<sha>` names a commit (7 to 40 hex digits) rather than a branch or tag.
+    fn commit(&self) -> Option<&str> {
+        self.git_ref.as_deref().filter(|git_ref| (7..=40).contains(&git_ref.len()) && git_ref.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    // A readable name plus a hash of the URL and ref: sanitizing alone would give refs like `feature/x` and
+    // `feature_x` one clone, and reusing it would reset it to the other ref.
+    fn cache_key(&self) -> String {
+        let name = format!("{}-{}-{}", self.host, self.owner, self.name);
+        let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' }).collect();
+        let identity = format!("{}\0{}", self.url, self.git_ref.as_deref().unwrap_or(""));
+        format!("{}-{:016x}", name, crate::manifest::stable_hash(identity.as_bytes()))
+    }
+}
+
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("toprompt");
+    }
+    if cfg!(target_os = "windows")
+        && let Some(dir) = env::var_os("LOCALAPPDATA")
+    {
+        return PathBuf::from(dir).join("toprompt").join("cache");
+    }
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        if cfg!(target_os = "macos") {
+            return PathBuf::from(home).join("Library").join("Caches").join("toprompt");
+        }
+        return PathBuf::from(home).join(".cache").join("toprompt");
+    }
+    env::temp_dir().join("toprompt")
+}
+
+// Returns a local checkout of the repository: the cached clone, updated from the remote, or a new clone.
+// Branches and tags are cloned shallowly; a commit needs the full history to be checked out.
+pub fn fetch(repo: &RemoteRepo, fresh: bool, verbose: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let repos_dir = cache_dir().join("repos");
+    let clone_dir = repos_dir.join(repo.cache_key());
+    if fresh && clone_dir.exists() {
+        fs::remove_dir_all(&clone_dir)?;
+    }
+    if clone_dir.join(".git").is_dir() {
+        if verbose {
+            println!("Using cached clone of {}: {}", repo.url, clone_dir.display());
+        }
+        // A commit never changes, so only branches, tags and the default branch are updated.
+        if repo.commit().is_none() {
+            let git_ref = repo.git_ref.as_deref().unwrap_or("HEAD");
+            let updated = git(&clone_dir, &["fetch", "--depth", "1", "--quiet", "--", "origin", git_ref])
+                .and_then(|_| git(&clone_dir, &["reset", "--hard", "--quiet", "FETCH_HEAD"]));
+            if let Err(e) = updated {
+                eprintln!("Warning: could not update the cached clone of {} ({}); using it as last fetched. --fresh-clone clones it again.", repo.url, e);
+            }
+        }
+        return Ok(clone_dir);
+    }
+
+    fs::create_dir_all(&repos_dir)?;
+    if verbose {
+        println!("Cloning {} into {}", repo.url, clone_dir.display());
+    }
+    let clone_path = clone_dir.to_string_lossy();
+    let mut args = vec!["clone", "--quiet"];
+    match (&repo.git_ref, repo.commit()) {
+        (_, Some(_)) => args.push("--no-checkout"),
+        (Some(git_ref), None) => args.extend(["--depth", "1", "--branch", git_ref]),
+        (None, None) => args.extend(["--depth", "1"]),
+    }
+    // `--` so a URL or ref starting with '-' is never taken for an option.
+    args.extend(["--", repo.url.as_str(), &clone_path]);
+    let cloned = git(&repos_dir, &args).and_then(|_| match repo.commit() {
+        Some(commit) => git(&clone_dir, &["checkout", "--quiet", "--detach", commit, "--"]),
+        None => Ok(()),
+    });
+    if let Err(e) = cloned {
+        let _ = fs::remove_dir_all(&clone_dir);
+        return Err(format!("git clone of '{}' failed: {}", repo.url, e).into());
+    }
+    Ok(clone_dir)
+}
+
+// Runs git in `dir`, describing the failure (with git's own message) if it does not succeed.
+fn git(dir: &Path, args: &[&str]) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run git: {}. Is git installed?", e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(format!("git {} failed ({}): {}", args[0], output.status, stderr.trim()))
+}
@@ -0,0 +1,149 @@
+// The HTML flavor put on the clipboard next to the plain text by --rich, so pasting into documents and chat
+// apps keeps headings and highlighted code blocks while plain-text consumers still get the markdown.
+// The finished bundle text is converted, so --edit and the other output options are reflected; XML bundles
+// have no markdown structure and become one preformatted block.
+
+// Inline styles, since pasted HTML keeps no stylesheet.
+const COMMENT: &str = "color:#6a737d;font-style:italic";
+const STRING: &str = "color:#032f62";
+const KEYWORD: &str = "color:#d73a49;font-weight:bold";
+const NUMBER: &str = "color:#005cc5";
+const PRE: &str = "background:#f6f8fa;padding:8px;border-radius:4px;font-family:Menlo,Consolas,monospace;font-size:12px";
+
+// Keywords common to the languages toprompt recognizes; highlighting is lexical and needs no grammar.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "def", "default", "defer", "do", "elif",
+    "else", "end", "enum", "except", "export", "extends", "false", "final", "finally", "fn", "for", "from", "func", "function",
+    "if", "impl", "implements", "import", "in", "interface", "let", "loop", "match", "mod", "module", "mut", "new", "nil",
+    "None", "null", "package", "private", "protected", "pub", "public", "raise", "return", "self", "static", "struct",
+    "super", "switch", "this", "throw", "trait", "True", "False", "true", "try", "type", "use", "var", "void", "where",
+    "while", "with", "yield",
+];
+
+pub fn to_html(text: &str, use_xml: bool) -> String {
+    if use_xml {
+        return format!("<pre style=\"{}\">{}</pre>", PRE, escape(text));
+    }
+    let mut html = String::with_capacity(text.len() * 2);
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        if let Some((fence, language)) = opening_fence(line) {
+            flush_paragraph(&mut paragraph, &mut html);
+            let mut code = String::new();
+            for line in lines.by_ref() {
+                if is_closing_fence(line, fence) {
+                    break;
+                }
+                code.push_str(line);
+                code.push('\n');
+            }
+            html.push_str(&format!("<pre style=\"{}\"><code class=\"language-{}\">{}</code></pre>\n", PRE, escape(language), highlight(&code, language)));
+        } else if let Some(heading) = line.strip_prefix('#') {
+            flush_paragraph(&mut paragraph, &mut html);
+            let level = (1 + heading.len() - heading.trim_start_matches('#').len()).min(6);
+            html.push_str(&format!("<h{0}>{1}</h{0}>\n", level, escape(heading.trim_start_matches('#').trim())));
+        } else if line.trim().is_empty() {
+            flush_paragraph(&mut paragraph, &mut html);
+        } else {
+            paragraph.push(line);
+        }
+    }
+    flush_paragraph(&mut paragraph, &mut html);
+    html
+}
+
+fn flush_paragraph(paragraph: &mut Vec<&str>, html: &mut String) {
+    if !paragraph.is_empty() {
+        let lines: Vec<String> = paragraph.iter().map(|line| escape(line)).collect();
+        html.push_str(&format!("<p>{}</p>\n", lines.join("<br>")));
+        paragraph.clear();
+    }
+}
+
+// A line opening a code block (three or more backticks or tildes), with its fence and language.
+fn opening_fence(line: &str) -> Option<(&str, &str)> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(marker).len();
+    (length >= 3).then(|| (&line[..length], line[length..].trim()))
+}
+
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let line = line.trim_end();
+    line.starts_with(fence) && line.chars().all(|c| fence.starts_with(c))
+}
+
+// The comment syntax of a code block's language: the line comment and whether /* */ blocks exist.
+fn comment_syntax(language: &str) -> Option<(&'static str, bool)> {
+    match language {
+        "rust" | "c" | "cpp" | "csharp" | "java" | "kotlin" | "scala" | "swift" | "go" | "javascript" | "typescript" | "jsx"
+        | "tsx" | "dart" | "php" | "solidity" | "css" | "scss" | "less" | "protobuf" | "objective-c" | "vlang" | "graphql" => {
+            Some(("//", true))
+        }
+        "python" | "ruby" | "bash" | "perl" | "r" | "toml" | "yaml" | "elixir" | "julia" | "makefile" | "dockerfile"
+        | "terraform" | "powershell" | "gdscript" | "cfg" | "ini" => Some(("#", false)),
+        "sql" | "lua" | "haskell" => Some(("--", false)),
+        _ => None,
+    }
+}
+
+// Escaped code with comments, strings, keywords and numbers wrapped in styled spans.
+fn highlight(code: &str, language: &str) -> String {
+    let Some((line_comment, block_comments)) = comment_syntax(language) else {
+        return escape(code);
+    };
+    let chars: Vec<char> = code.chars().collect();
+    let mut html = String::with_capacity(code.len() * 2);
+    let starts_with = |i: usize, token: &str| token.chars().enumerate().all(|(k, c)| chars.get(i + k) == Some(&c));
+    let span = |html: &mut String, style: &str, text: &[char]| {
+        html.push_str(&format!("<span style=\"{}\">{}</span>", style, escape(&text.iter().collect::<String>())));
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if starts_with(i, line_comment) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            span(&mut html, COMMENT, &chars[start..i]);
+        } else if block_comments && starts_with(i, "/*") {
+            i += 2;
+            while i < chars.len() && !starts_with(i, "*/") {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            span(&mut html, COMMENT, &chars[start..i]);
+        } else if c == '"' || (c == '\'' && (language != "rust" || chars.get(i + 2) == Some(&'\'') || chars.get(i + 1) == Some(&'\\'))) {
+            i += 1;
+            while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            span(&mut html, STRING, &chars[start..i]);
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) {
+                span(&mut html, KEYWORD, &chars[start..i]);
+            } else {
+                html.push_str(&word);
+            }
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            span(&mut html, NUMBER, &chars[start..i]);
+        } else {
+            html.push_str(&escape(&c.to_string()));
+            i += 1;
+        }
+    }
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
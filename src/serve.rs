@@ -0,0 +1,169 @@
+// `toprompt serve --editor`: a small JSON-RPC 2.0 server over stdio for editor plugins (Neovim, VS Code, ...).
+// Each request and response is one line of JSON. Methods:
+//   initialize -> { name, version, methods }
+//   bundle { buffers?: [{ path, text, start_line? }], paths?: [..], pattern?, recursive?, gitignore?, xml? }
+//     -> { prompt, files, tokens, errors, timings_ms }   (timings_ms: milliseconds per phase, as --timings reports)
+//   shutdown -> null, then the server exits
+// Buffers carry the editor's (possibly unsaved) text or current selection and come first in the prompt;
+// project files matching `paths`/`pattern` follow, skipping any file already sent as a buffer.
+// `toprompt daemon` speaks the same protocol over a unix socket (see daemon.rs).
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{Value, json};
+
+use crate::Config;
+use crate::bundle::{Bundle, BundleEntry};
+use crate::cache::Cache;
+use crate::timings::{self, Phase};
+use crate::tokens::estimate_tokens;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt serve --editor");
+    eprintln!("  Serves newline-delimited JSON-RPC 2.0 on stdin/stdout for editor integrations.");
+    eprintln!("  Methods: initialize, bundle, shutdown (see src/serve.rs for the parameters).");
+}
+
+pub fn run(args: &[String]) {
+    if args != ["--editor"] {
+        print_usage();
+        std::process::exit(crate::EXIT_ERROR);
+    }
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, shutdown) = handle_message(&line, None);
+        if let Some(response) = response
+            && (writeln!(stdout, "{}", response).is_err() || stdout.flush().is_err())
+        {
+            break;
+        }
+        if shutdown {
+            break;
+        }
+    }
+}
+
+// Returns the response to send (None for notifications) and whether the server should stop.
+// Bundles use `cache` when given (the daemon's warm cache), otherwise the on-disk cache.
+pub fn handle_message(line: &str, cache: Option<&Cache>) -> (Option<Value>, bool) {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return (Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())), false),
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return (Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "missing method")), false);
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = match method {
+        "initialize" => Ok(json!({
+            "name": "toprompt",
+            "version": env!("CARGO_PKG_VERSION"),
+            "methods": ["initialize", "bundle", "shutdown"],
+        })),
+        "bundle" => bundle(&params, cache),
+        "shutdown" => Ok(Value::Null),
+        _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+    };
+    let shutdown = method == "shutdown";
+    let Some(id) = id else {
+        return (None, shutdown);
+    };
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error_response(id, code, &message),
+    };
+    (Some(response), shutdown)
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn bundle(params: &Value, cache: Option<&Cache>) -> Result<Value, (i64, String)> {
+    timings::start();
+    let flag = |name: &str| params.get(name).and_then(Value::as_bool).unwrap_or(false);
+    let config = Config {
+        use_xml: flag("xml"),
+        recursive: flag("recursive"),
+        use_gitignore: flag("gitignore"),
+        regex_pattern: params.get("pattern").and_then(Value::as_str).map(str::to_string),
+        // Verbose output goes to stdout, which carries the protocol.
+        verbose: false,
+        ..Config::default()
+    };
+    let compiled_regex = match &config.regex_pattern {
+        Some(pattern) => Some(config.case.regex(pattern).map_err(|e| (INVALID_PARAMS, format!("invalid pattern '{}': {}", pattern, e)))?),
+        None => None,
+    };
+
+    let mut bundle = Bundle::new();
+    let mut errors = Vec::new();
+    for buffer in params.get("buffers").and_then(Value::as_array).into_iter().flatten() {
+        let (Some(path), Some(text)) = (buffer.get("path").and_then(Value::as_str), buffer.get("text").and_then(Value::as_str)) else {
+            return Err((INVALID_PARAMS, "each buffer needs a path and text".to_string()));
+        };
+        add_buffer(&mut bundle, path, text, buffer.get("start_line").and_then(Value::as_u64), &config);
+    }
+    if !config.no_cache {
+        bundle.cache = Some(cache.cloned().unwrap_or_else(Cache::open));
+    }
+    for path in params.get("paths").and_then(Value::as_array).into_iter().flatten() {
+        let Some(path) = path.as_str() else {
+            return Err((INVALID_PARAMS, "paths must be strings".to_string()));
+        };
+        if let Err(e) = timings::time(Phase::Traversal, || crate::process_path(path, &mut bundle, &config, &compiled_regex)) {
+            errors.push(format!("{}: {}", path, e));
+        }
+    }
+
+    let mut prompt = Vec::new();
+    timings::time(Phase::Output, || bundle.write_to(&mut prompt)).map_err(|e| (INVALID_REQUEST, e.to_string()))?;
+    let phases = timings::finish().map(|report| report.to_json());
+    Ok(json!({
+        "prompt": String::from_utf8_lossy(&prompt),
+        "files": bundle.file_names().collect::<Vec<_>>(),
+        "tokens": bundle.total_tokens(),
+        "errors": errors,
+        "timings_ms": phases,
+    }))
+}
+
+// Adds an editor buffer (or a selection from it, starting at `start_line`) as an entry.
+fn add_buffer(bundle: &mut Bundle, path: &str, text: &str, start_line: Option<u64>, config: &Config) {
+    let display_name = match start_line {
+        Some(start) => {
+            let end = start + text.lines().count().max(1) as u64 - 1;
+            format!("{} (lines {}-{})", path, start, end)
+        }
+        None => path.to_string(),
+    };
+    let canonical_path = fs::canonicalize(path).ok();
+    // A whole buffer replaces the saved file in the project walk; a selection is shown in addition to it.
+    if let (Some(canonical_path), None) = (&canonical_path, start_line) {
+        bundle.seen_paths.insert(canonical_path.clone());
+    }
+    let contents = text.replace("\r\n", "\n");
+    let segment = crate::format_segment(&display_name, path, &contents, config);
+    let tokens = estimate_tokens(&segment);
+    bundle.add_file(BundleEntry {
+        display_name,
+        path: canonical_path,
+        contents,
+        segment,
+        tokens,
+    });
+}
@@ -0,0 +1,135 @@
+// Named slots: `--slot <name>` keeps a copy of the finished bundle in the state directory, and
+// `toprompt recopy <name>` (or `toprompt paste <name>`) puts it back on the clipboard, so several
+// context packs can be prepared once and switched between during a conversation.
+// Slots live in the state directory rather than the cache directory so clearing caches keeps them.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::bundle::Bundle;
+
+pub fn print_usage() {
+    eprintln!("Usage: toprompt recopy <name> [--stdout] | toprompt recopy --list | toprompt recopy --delete <name>");
+    eprintln!("  Puts the bundle saved with `--slot <name>` back on the clipboard (`toprompt paste <name>` is the same).");
+    eprintln!("  --stdout          Print the slot instead of copying it.");
+    eprintln!("  --list            List the saved slots with their size and age.");
+    eprintln!("  --delete <name>   Remove a slot.");
+}
+
+// Where state that should outlive the cache is kept, following the platform's conventions.
+fn state_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("toprompt");
+    }
+    if cfg!(target_os = "windows")
+        && let Some(dir) = env::var_os("LOCALAPPDATA")
+    {
+        return PathBuf::from(dir).join("toprompt").join("state");
+    }
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        if cfg!(target_os = "macos") {
+            return PathBuf::from(home).join("Library").join("Application Support").join("toprompt");
+        }
+        return PathBuf::from(home).join(".local").join("state").join("toprompt");
+    }
+    env::temp_dir().join("toprompt-state")
+}
+
+fn slots_dir() -> PathBuf {
+    state_dir().join("slots")
+}
+
+// Slot names become file names, so they are limited to a portable set of characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+// The file of a slot; None for an invalid name, so no other path is ever read, written or removed.
+fn slot_path(name: &str) -> Option<PathBuf> {
+    is_valid_name(name).then(|| slots_dir().join(format!("{}.txt", name)))
+}
+
+// Saves the bundle exactly as it is written to its destinations, replacing the slot's previous contents.
+pub fn save(name: &str, bundle: &Bundle) -> io::Result<()> {
+    let path = slot_path(name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid slot name"))?;
+    fs::create_dir_all(slots_dir())?;
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    bundle.write_to(&mut file)?;
+    file.flush()
+}
+
+pub fn run(args: &[String]) {
+    match args {
+        [flag] if flag == "-h" || flag == "--help" => print_usage(),
+        [flag] if flag == "--list" => list(),
+        [flag, name] if flag == "--delete" => {
+            if slot_path(name).is_none_or(|path| fs::remove_file(path).is_err()) {
+                eprintln!("Error: there is no slot named '{}'.", name);
+                std::process::exit(crate::EXIT_ERROR);
+            }
+            println!(":: Deleted slot {} ::", name);
+        }
+        [name] => recopy(name, false),
+        [name, flag] | [flag, name] if flag == "--stdout" => recopy(name, true),
+        _ => {
+            print_usage();
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    }
+}
+
+fn recopy(name: &str, to_stdout: bool) {
+    let contents = match slot_path(name).map(fs::read_to_string) {
+        Some(Ok(contents)) => contents,
+        _ => {
+            eprintln!("Error: there is no slot named '{}'; `toprompt recopy --list` shows the saved ones.", name);
+            std::process::exit(crate::EXIT_ERROR);
+        }
+    };
+    if to_stdout {
+        println!("{}", contents);
+        return;
+    }
+    match crate::clipboard::copy_to_clipboard(&contents) {
+        Ok(()) => println!(":: Copied slot {} (~{} tokens) ::", name, crate::tokens::estimate_tokens(&contents)),
+        Err(e) => {
+            eprintln!("Failed to copy slot '{}' to the clipboard: {}", name, e);
+            std::process::exit(crate::EXIT_OUTPUT_FAILED);
+        }
+    }
+}
+
+fn list() {
+    let mut slots: Vec<(String, u64, Option<SystemTime>)> = fs::read_dir(slots_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().strip_suffix(".txt")?.to_string();
+            let metadata = entry.metadata().ok()?;
+            Some((name, metadata.len(), metadata.modified().ok()))
+        })
+        .collect();
+    if slots.is_empty() {
+        println!("No slots saved yet; add --slot <name> to a toprompt run to save one.");
+        return;
+    }
+    slots.sort();
+    let width = slots.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    for (name, size, modified) in slots {
+        let age = modified.and_then(|modified| modified.elapsed().ok()).map(|elapsed| format_age(elapsed.as_secs()));
+        println!("{:<width$}  {:>9}  {}", name, crate::format_size(size), age.unwrap_or_default());
+    }
+}
+
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86_400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86_400),
+    }
+}
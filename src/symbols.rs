@@ -0,0 +1,193 @@
+// Top-level symbol index for --symbol-index: functions, types and constants with path:line references, read from
+// the files on disk so truncated files and summarized directories are still mapped. Common languages are
+// handled with line regexes; other files go through universal-ctags when it is installed.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{LazyLock, OnceLock};
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::Config;
+use crate::bundle::Bundle;
+
+// (kind, pattern) pairs; the symbol name is the pattern's first group. Patterns are anchored at column 0,
+// which is what keeps them to top-level definitions.
+type Rules = Vec<(&'static str, Regex)>;
+
+fn rules(pairs: &[(&'static str, &str)]) -> Rules {
+    pairs.iter().map(|(kind, pattern)| (*kind, Regex::new(pattern).unwrap())).collect()
+}
+
+static RUST: LazyLock<Rules> = LazyLock::new(|| {
+    const VIS: &str = r"^(?:pub(?:\([^)]*\))?\s+)?";
+    rules(&[
+        ("fn", &format!(r#"{}(?:(?:const|async|unsafe|extern\s+"[^"]*")\s+)*fn\s+(\w+)"#, VIS)),
+        ("struct", &format!(r"{}struct\s+(\w+)", VIS)),
+        ("enum", &format!(r"{}enum\s+(\w+)", VIS)),
+        ("union", &format!(r"{}union\s+(\w+)", VIS)),
+        ("trait", &format!(r"{}(?:unsafe\s+)?trait\s+(\w+)", VIS)),
+        ("type", &format!(r"{}type\s+(\w+)", VIS)),
+        ("const", &format!(r"{}const\s+(\w+)\s*:", VIS)),
+        ("static", &format!(r"{}static\s+(?:mut\s+)?(\w+)\s*:", VIS)),
+        ("mod", &format!(r"{}mod\s+(\w+)\s*\{{", VIS)),
+        ("macro", r"^macro_rules!\s*(\w+)"),
+    ])
+});
+static PYTHON: LazyLock<Rules> = LazyLock::new(|| {
+    rules(&[("def", r"^(?:async\s+)?def\s+(\w+)"), ("class", r"^class\s+(\w+)"), ("const", r"^([A-Z][A-Z0-9_]*)\s*(?::[^=]+)?=")])
+});
+static JS: LazyLock<Rules> = LazyLock::new(|| {
+    const EXPORT: &str = r"^(?:export\s+)?(?:default\s+)?(?:declare\s+)?";
+    rules(&[
+        ("function", &format!(r"{}(?:async\s+)?function\*?\s+(\w+)", EXPORT)),
+        ("class", &format!(r"{}(?:abstract\s+)?class\s+(\w+)", EXPORT)),
+        ("interface", &format!(r"{}interface\s+(\w+)", EXPORT)),
+        ("type", &format!(r"{}type\s+(\w+)", EXPORT)),
+        ("enum", &format!(r"{}(?:const\s+)?enum\s+(\w+)", EXPORT)),
+        ("const", &format!(r"{}(?:const|let|var)\s+(\w+)", EXPORT)),
+    ])
+});
+static GO: LazyLock<Rules> = LazyLock::new(|| {
+    rules(&[
+        ("func", r"^func\s+(?:\([^)]*\)\s*)?(\w+)"),
+        ("type", r"^type\s+(\w+)"),
+        ("const", r"^const\s+(\w+)"),
+        ("var", r"^var\s+(\w+)"),
+    ])
+});
+static C: LazyLock<Rules> = LazyLock::new(|| {
+    rules(&[
+        ("struct", r"^(?:typedef\s+)?struct\s+(\w+)\s*\{"),
+        ("enum", r"^(?:typedef\s+)?enum\s+(?:class\s+)?(\w+)"),
+        ("class", r"^(?:template\s*<[^>]*>\s*)?class\s+(\w+)\s*[:{]?\s*$"),
+        ("define", r"^#\s*define\s+(\w+)"),
+        // A definition line: a return type, the name, and an unterminated or brace-opened parameter list.
+        ("function", r"^(?:static\s+|inline\s+|extern\s+)*[A-Za-z_][\w:<>,]*(?:\s+|\s*\*+\s*)(\w+)\s*\([^;]*$"),
+    ])
+});
+
+struct Symbol {
+    path: String,
+    line: usize,
+    kind: String,
+    name: String,
+}
+
+// The symbol index for the bundle's local files and the files of summarized directories, or None if none are found.
+pub fn index(bundle: &Bundle, config: &Config) -> Option<String> {
+    let mut symbols = Vec::new();
+    let mut file_count = 0;
+    let files = bundle
+        .entries
+        .iter()
+        .filter_map(|entry| Some((entry.display_name.clone(), entry.path.as_deref()?)))
+        .chain(bundle.summarized.iter().map(|path| (crate::anonymize(config, &crate::display_relative(path)), path.as_path())));
+    for (display_name, path) in files {
+        let before = symbols.len();
+        symbols.extend(file_symbols(path).into_iter().map(|(line, kind, name)| Symbol { path: display_name.clone(), line, kind, name }));
+        if symbols.len() > before {
+            file_count += 1;
+        }
+    }
+    if symbols.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = symbols.iter().map(|symbol| format!("{}:{} {} {}", symbol.path, symbol.line, symbol.kind, symbol.name)).collect();
+    if config.use_xml {
+        Some(format!("<symbol_index files=\"{}\">\n{}\n</symbol_index>", file_count, lines.join("\n")))
+    } else {
+        Some(format!("# Symbol index ({} symbols in {} files)\n{}", symbols.len(), file_count, lines.join("\n")))
+    }
+}
+
+// (1-based line, kind, name) for each top-level definition in the file.
+fn file_symbols(path: &Path) -> Vec<(usize, String, String)> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let rules: &Rules = match ext {
+        "rs" => &RUST,
+        "py" | "pyi" => &PYTHON,
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => &JS,
+        "go" => &GO,
+        "c" | "h" | "cpp" | "cc" | "cxx" | "hpp" | "hh" => &C,
+        _ => return ctags_symbols(path),
+    };
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let mut symbols = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if let Some((kind, cap)) = rules.iter().find_map(|(kind, rule)| rule.captures(line).map(|cap| (kind, cap))) {
+            symbols.push((i + 1, kind.to_string(), cap[1].to_string()));
+        }
+    }
+    symbols
+}
+
+// Whether universal-ctags (which supports JSON output) is installed; checked once.
+fn has_ctags() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        Command::new("ctags")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("Universal Ctags"))
+    })
+}
+
+fn ctags_symbols(path: &Path) -> Vec<(usize, String, String)> {
+    if !has_ctags() {
+        return Vec::new();
+    }
+    let Ok(output) = Command::new("ctags").args(["--output-format=json", "--fields=+nK", "--sort=no", "-f", "-"]).arg(path).output() else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        // Tags with a scope are members of another symbol.
+        .filter(|tag| tag.get("_type").and_then(Value::as_str) == Some("tag") && tag.get("scope").is_none())
+        .filter_map(|tag| {
+            let line = tag.get("line")?.as_u64()? as usize;
+            let kind = tag.get("kind").and_then(Value::as_str).unwrap_or("symbol").to_string();
+            Some((line, kind, tag.get("name")?.as_str()?.to_string()))
+        })
+        .collect()
+}
+
+// The top-level definition lines of a file with the comment lines directly above them (and, for Python, the first
+// docstring line below), or None if no symbols are found.
+pub fn outline(path: &Path) -> Option<String> {
+    let symbols = file_symbols(path);
+    if symbols.is_empty() {
+        return None;
+    }
+    let contents = fs::read_to_string(path).ok()?;
+    let lines: Vec<&str> = contents.lines().collect();
+    let is_comment = |line: &str| {
+        let line = line.trim_start();
+        ["///", "//", "/*", "*", "# ", "#[", "@", "--"].iter().any(|opener| line.starts_with(opener)) || line == "#"
+    };
+    let mut blocks = Vec::new();
+    for (line, _, _) in symbols {
+        let index = line - 1;
+        let Some(signature) = lines.get(index) else {
+            continue;
+        };
+        let mut start = index;
+        while start > 0 && is_comment(lines[start - 1]) {
+            start -= 1;
+        }
+        let mut block: Vec<&str> = lines[start..index].to_vec();
+        block.push(signature.trim_end().trim_end_matches('{').trim_end());
+        if let Some(next) = lines.get(index + 1)
+            && (next.trim_start().starts_with("\"\"\"") || next.trim_start().starts_with("'''"))
+        {
+            block.push(next.trim_end());
+        }
+        blocks.push(block.join("\n"));
+    }
+    Some(blocks.join("\n\n"))
+}
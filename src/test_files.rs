@@ -0,0 +1,117 @@
+// Per-language conventions for locating test files.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Conventional test counterparts of a source file that exist on disk.
+pub fn companion_tests(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem), Some(ext)) = (
+        path.parent(),
+        path.file_stem().and_then(|s| s.to_str()),
+        path.extension().and_then(|e| e.to_str()),
+    ) else {
+        return Vec::new();
+    };
+    let mut candidates = Vec::new();
+    match ext {
+        "go" => candidates.push(dir.join(format!("{}_test.go", stem))),
+        "py" => {
+            candidates.push(dir.join(format!("test_{}.py", stem)));
+            candidates.push(dir.join(format!("{}_test.py", stem)));
+            for ancestor in dir.ancestors().take(3) {
+                candidates.push(ancestor.join("tests").join(format!("test_{}.py", stem)));
+                candidates.push(ancestor.join("test").join(format!("test_{}.py", stem)));
+            }
+        }
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => {
+            for kind in ["spec", "test"] {
+                candidates.push(dir.join(format!("{}.{}.{}", stem, kind, ext)));
+                candidates.push(dir.join("__tests__").join(format!("{}.{}.{}", stem, kind, ext)));
+            }
+            candidates.push(dir.join("__tests__").join(format!("{}.{}", stem, ext)));
+        }
+        "rs" => {
+            candidates.push(dir.join(format!("{}_test.rs", stem)));
+            candidates.push(dir.join(format!("{}_tests.rs", stem)));
+            // Unit tests split out of `foo.rs` into `foo/tests.rs`.
+            candidates.push(dir.join(stem).join("tests.rs"));
+            if let Some(crate_dir) = dir.ancestors().find(|d| d.join("Cargo.toml").is_file()) {
+                candidates.push(crate_dir.join("tests").join(format!("{}.rs", stem)));
+            }
+            if matches!(stem, "mod" | "lib" | "main") {
+                candidates.push(dir.join("tests.rs"));
+            }
+        }
+        "java" | "kt" => {
+            candidates.push(dir.join(format!("{}Test.{}", stem, ext)));
+            let as_string = dir.to_string_lossy().replace('\\', "/");
+            if as_string.contains("/src/main/") {
+                candidates.push(PathBuf::from(as_string.replacen("/src/main/", "/src/test/", 1)).join(format!("{}Test.{}", stem, ext)));
+            }
+        }
+        "rb" => {
+            candidates.push(dir.join(format!("{}_spec.rb", stem)));
+            candidates.push(dir.join(format!("{}_test.rb", stem)));
+        }
+        "c" | "cpp" | "cc" => candidates.push(dir.join(format!("{}_test.{}", stem, ext))),
+        _ => {}
+    }
+    candidates.retain(|c| c != path && c.is_file());
+    if ext == "rs" {
+        // Rust test modules are only worth including if they actually contain tests.
+        candidates.retain(|c| fs::read_to_string(c).is_ok_and(|contents| contents.contains("#[test]") || contents.contains("#[cfg(test)]")));
+    }
+    candidates.dedup();
+    candidates
+}
+
+// Directory names that hold tests (and their fixtures) by convention.
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testdata", "__mocks__", "__snapshots__"];
+
+pub fn is_test_dir(name: &str) -> bool {
+    TEST_DIRS.contains(&name)
+}
+
+// Why --no-tests treats `path` as test code, if it does: a test directory among the components of `relative`,
+// a test file name (test_x.py, x_test.go, x.spec.ts, XTest.java, ...), or a Rust file that is all `#[cfg(test)]`.
+pub fn test_reason(path: &Path, relative: &str) -> Option<String> {
+    let mut components: Vec<&str> = relative.split('/').collect();
+    components.pop();
+    if let Some(dir) = components.iter().find(|component| is_test_dir(component)) {
+        return Some(format!("inside a {}/ directory", dir));
+    }
+    let name = path.file_name()?.to_str()?;
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let base = name.rsplit_once('.').map_or(name, |(base, _)| base);
+    // Words of the name: test_parser.py, parser_test.go, parser.spec.ts, parser-test.js (but not a SPEC.md document).
+    let is_test_word = |word: &str| matches!(word.to_lowercase().as_str(), "test" | "tests" | "spec" | "specs");
+    let is_document = matches!(ext, "md" | "markdown" | "rst" | "txt" | "adoc");
+    if !is_document && (base.split(['_', '-', '.']).any(is_test_word) || name == "conftest.py") {
+        return Some(format!("'{}' is named like a test file", name));
+    }
+    if matches!(ext, "java" | "kt" | "scala" | "cs" | "swift") && ["Test", "Tests", "Spec"].iter().any(|suffix| base.ends_with(suffix)) {
+        return Some(format!("'{}' is named like a test class", name));
+    }
+    if ext == "rs" && is_rust_test_module(path) {
+        return Some("Rust module compiled only for tests (#[cfg(test)])".to_string());
+    }
+    None
+}
+
+// Whether a Rust file is compiled only for tests: it starts with `#![cfg(test)]`, or its only top-level item
+// is a `#[cfg(test)] mod` (the column-0 lines are the attribute, the `mod` line and the closing brace).
+fn is_rust_test_module(path: &Path) -> bool {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return false;
+    };
+    let top_level: Vec<&str> = contents
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with(char::is_whitespace) && !line.starts_with("//"))
+        .collect();
+    match top_level.as_slice() {
+        ["#![cfg(test)]", ..] => true,
+        ["#[cfg(test)]", module, "}"] => module.contains("mod ") && module.ends_with('{'),
+        _ => false,
+    }
+}
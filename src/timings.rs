@@ -0,0 +1,121 @@
+// --timings: wall time per phase of a run, to diagnose slow filesystems and performance regressions.
+// Phases nest (files are read while the tree is traversed), so each phase is charged only its own time:
+// entering a nested phase pauses the enclosing one. Nothing is measured unless `start` was called.
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use serde_json::{Map, Value};
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    // Listing directories and walking the tree, less the phases below.
+    Traversal,
+    // Ignore rules, -R, --filter and the other per-path selection rules.
+    Filtering,
+    Reading,
+    // Transforms and laying out each file.
+    Formatting,
+    Tokens,
+    // Writing the bundle to the clipboard, stdout or files.
+    Output,
+}
+
+const PHASES: [Phase; 6] = [Phase::Traversal, Phase::Filtering, Phase::Reading, Phase::Formatting, Phase::Tokens, Phase::Output];
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Traversal => "traversal",
+            Phase::Filtering => "filtering",
+            Phase::Reading => "reading",
+            Phase::Formatting => "formatting",
+            Phase::Tokens => "token counting",
+            Phase::Output => "output",
+        }
+    }
+}
+
+struct Recorder {
+    started: Instant,
+    totals: [Duration; PHASES.len()],
+    // The phases currently entered, innermost last, with when each last started being charged.
+    stack: Vec<(Phase, Instant)>,
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) };
+}
+
+// Starts measuring on this thread, discarding any earlier measurements.
+pub fn start() {
+    RECORDER.set(Some(Recorder { started: Instant::now(), totals: [Duration::ZERO; PHASES.len()], stack: Vec::new() }));
+}
+
+// Runs `f`, charging the time it takes (less nested phases) to `phase`.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let active = RECORDER.with_borrow_mut(|recorder| {
+        let Some(recorder) = recorder else { return false };
+        let now = Instant::now();
+        if let Some((outer, since)) = recorder.stack.last() {
+            recorder.totals[*outer as usize] += now - *since;
+        }
+        recorder.stack.push((phase, now));
+        true
+    });
+    let result = f();
+    if active {
+        RECORDER.with_borrow_mut(|recorder| {
+            let Some(recorder) = recorder else { return };
+            let now = Instant::now();
+            if let Some((phase, since)) = recorder.stack.pop() {
+                recorder.totals[phase as usize] += now - since;
+            }
+            if let Some((_, since)) = recorder.stack.last_mut() {
+                *since = now;
+            }
+        });
+    }
+    result
+}
+
+pub struct Report {
+    phases: Vec<(&'static str, Duration)>,
+    total: Duration,
+}
+
+// Stops measuring and returns what was measured since `start`, or None if it was not called.
+pub fn finish() -> Option<Report> {
+    let recorder = RECORDER.take()?;
+    let phases = PHASES.iter().map(|phase| (phase.name(), recorder.totals[*phase as usize])).collect();
+    Some(Report { phases, total: recorder.started.elapsed() })
+}
+
+impl Report {
+    // Time not spent in any phase: ranking, budgets, --fit and the other whole-bundle steps.
+    fn other(&self) -> Duration {
+        self.total.saturating_sub(self.phases.iter().map(|(_, duration)| *duration).sum())
+    }
+
+    pub fn format(&self) -> String {
+        let mut rows = self.phases.clone();
+        rows.push(("other", self.other()));
+        let mut text = String::from(":: Timings ::\n");
+        for (name, duration) in rows {
+            let share = duration.as_secs_f64() * 100.0 / self.total.as_secs_f64().max(f64::MIN_POSITIVE);
+            text.push_str(&format!("{:<15} {:>10.1} ms  {:>3.0}%\n", name, duration.as_secs_f64() * 1000.0, share));
+        }
+        text.push_str(&format!("{:<15} {:>10.1} ms\n", "total", self.total.as_secs_f64() * 1000.0));
+        text
+    }
+
+    // Milliseconds per phase, keyed by phase name, plus "other" and "total".
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        for (name, duration) in self.phases.iter().copied().chain([("other", self.other()), ("total", self.total)]) {
+            // Microsecond precision is plenty, and keeps the numbers short.
+            map.insert(name.replace(' ', "_"), Value::from((duration.as_secs_f64() * 1_000_000.0).round() / 1000.0));
+        }
+        Value::Object(map)
+    }
+}
@@ -0,0 +1,43 @@
+// TODO/FIXME/HACK discovery for --todos: collects marker comments from the bundled files into one appendix.
+
+use std::sync::LazyLock;
+use regex::Regex;
+
+use crate::bundle::Bundle;
+
+// A marker word after a comment opener (//, #, /*, *, --, <!--, ;) on the same line.
+static MARKER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?://|#|/\*|^\s*\*|--|<!--|;)[^\n]*?\b((?:TODO|FIXME|HACK)\b.*)").unwrap());
+
+pub struct Todo {
+    pub path: String,
+    // 1-based line within the bundled (transformed) contents.
+    pub line: usize,
+    pub text: String,
+}
+
+// Marker comments in the bundle's local files, in bundle order.
+pub fn collect(bundle: &Bundle) -> Vec<Todo> {
+    let mut todos = Vec::new();
+    for entry in bundle.entries.iter().filter(|entry| entry.path.is_some()) {
+        for (i, line) in entry.contents.lines().enumerate() {
+            if let Some(cap) = MARKER.captures(line) {
+                let text = cap[1].trim_end().trim_end_matches("*/").trim_end_matches("-->").trim_end();
+                todos.push(Todo { path: entry.display_name.clone(), line: i + 1, text: text.to_string() });
+            }
+        }
+    }
+    todos
+}
+
+pub fn format(todos: &[Todo], use_xml: bool) -> String {
+    if use_xml {
+        let items: Vec<String> = todos
+            .iter()
+            .map(|todo| format!("<todo path=\"{}\" line=\"{}\">{}</todo>", todo.path, todo.line, todo.text))
+            .collect();
+        format!("<todos>\n{}\n</todos>", items.join("\n"))
+    } else {
+        let items: Vec<String> = todos.iter().map(|todo| format!("- {}:{}: {}", todo.path, todo.line, todo.text)).collect();
+        format!("# TODO/FIXME/HACK comments ({})\n{}", todos.len(), items.join("\n"))
+    }
+}
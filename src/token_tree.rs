@@ -0,0 +1,51 @@
+// Token heatmap for --token-tree: the bundled files as a directory tree, each directory annotated with the
+// tokens of everything below it, heaviest first, so the subtree that eats the context window stands out.
+
+use std::collections::BTreeMap;
+
+use crate::bundle::Bundle;
+
+// Shares of the total at or above which a line is colored red or yellow.
+const HOT_SHARE: f64 = 0.20;
+const WARM_SHARE: f64 = 0.05;
+
+#[derive(Default)]
+struct Node {
+    tokens: usize,
+    children: BTreeMap<String, Node>,
+}
+
+pub fn render(bundle: &Bundle, color: bool) -> String {
+    let mut root = Node::default();
+    for entry in bundle.entries.iter().filter(|entry| entry.path.is_some()) {
+        root.tokens += entry.tokens;
+        let mut node = &mut root;
+        for part in entry.display_name.split('/').filter(|part| !part.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+            node.tokens += entry.tokens;
+        }
+    }
+    let mut out = format!("Tokens by directory (~{} total)\n", root.tokens);
+    render_children(&root, root.tokens, "", color, &mut out);
+    out
+}
+
+fn render_children(node: &Node, total: usize, prefix: &str, color: bool, out: &mut String) {
+    let mut children: Vec<(&String, &Node)> = node.children.iter().collect();
+    children.sort_by(|a, b| b.1.tokens.cmp(&a.1.tokens).then(a.0.cmp(b.0)));
+    let count = children.len();
+    for (i, (name, child)) in children.into_iter().enumerate() {
+        let last = i + 1 == count;
+        let share = child.tokens as f64 / total.max(1) as f64;
+        let slash = if child.children.is_empty() { "" } else { "/" };
+        let annotation = format!("~{} tokens ({:.1}%)", child.tokens, share * 100.0);
+        let annotation = match (color, share) {
+            (false, _) => annotation,
+            (true, share) if share >= HOT_SHARE => format!("\x1b[31m{}\x1b[0m", annotation),
+            (true, share) if share >= WARM_SHARE => format!("\x1b[33m{}\x1b[0m", annotation),
+            (true, _) => format!("\x1b[32m{}\x1b[0m", annotation),
+        };
+        out.push_str(&format!("{}{} {}{}  {}\n", prefix, if last { "└──" } else { "├──" }, name, slash, annotation));
+        render_children(child, total, &format!("{}{}", prefix, if last { "    " } else { "│   " }), color, out);
+    }
+}
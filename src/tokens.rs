@@ -0,0 +1,52 @@
+// Token estimation. Uses the common ~4 characters per token heuristic, which is close enough
+// for budgeting across the popular BPE tokenizers; --model switches to the ratio measured for that
+// model's tokenizer on source code and reports its context window.
+
+use std::sync::OnceLock;
+
+pub struct Model {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    // The tokenizer family, for reports.
+    pub tokenizer: &'static str,
+    pub chars_per_token: f64,
+    pub context_window: usize,
+}
+
+pub const MODELS: &[Model] = &[
+    Model { name: "gpt-4o", aliases: &["gpt-4o-mini", "gpt-4.1", "o1", "o3"], tokenizer: "o200k_base", chars_per_token: 4.0, context_window: 128_000 },
+    Model { name: "gpt-4", aliases: &["gpt-4-turbo", "gpt-3.5"], tokenizer: "cl100k_base", chars_per_token: 3.7, context_window: 128_000 },
+    Model { name: "claude-3.5", aliases: &["claude", "claude-3", "claude-3.7", "sonnet", "opus", "haiku"], tokenizer: "claude", chars_per_token: 3.5, context_window: 200_000 },
+    Model { name: "llama3", aliases: &["llama-3"], tokenizer: "llama3 (tiktoken 128k)", chars_per_token: 3.9, context_window: 8_192 },
+    Model { name: "llama3.1", aliases: &["llama-3.1", "llama3.2", "llama3.3"], tokenizer: "llama3 (tiktoken 128k)", chars_per_token: 3.9, context_window: 128_000 },
+    Model { name: "gemini-1.5", aliases: &["gemini", "gemini-2.0", "gemini-2.5"], tokenizer: "gemini (SentencePiece)", chars_per_token: 4.0, context_window: 1_000_000 },
+];
+
+// The model selected with --model, set once before any file is read.
+static MODEL: OnceLock<&'static Model> = OnceLock::new();
+
+pub fn find_model(name: &str) -> Option<&'static Model> {
+    let name = name.to_lowercase();
+    MODELS.iter().find(|model| model.name == name || model.aliases.contains(&name.as_str()))
+}
+
+pub fn set_model(model: &'static Model) {
+    let _ = MODEL.set(model);
+}
+
+// Re-estimates a count made with the active tokenizer ratio for another model's tokenizer (None: the default ratio).
+pub fn convert(tokens: usize, to: Option<&Model>) -> usize {
+    let from_ratio = MODEL.get().map_or(4.0, |model| model.chars_per_token);
+    let to_ratio = to.map_or(4.0, |model| model.chars_per_token);
+    (tokens as f64 * from_ratio / to_ratio).ceil() as usize
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    crate::timings::time(crate::timings::Phase::Tokens, || {
+        let chars = text.chars().count();
+        match MODEL.get() {
+            Some(model) => (chars as f64 / model.chars_per_token).ceil() as usize,
+            None => chars.div_ceil(4),
+        }
+    })
+}
@@ -0,0 +1,563 @@
+// Content transforms applied to each file before it is formatted into the bundle.
+
+use std::path::Path;
+use std::sync::LazyLock;
+use regex::Regex;
+
+// Expands tabs, strips trailing whitespace and collapses runs of blank lines into a single one.
+pub fn normalize_whitespace(contents: &str, tab_width: usize) -> String {
+    let mut normalized = String::with_capacity(contents.len());
+    let mut previous_blank = false;
+    for line in contents.lines() {
+        let line = expand_tabs(line, tab_width);
+        let line = line.trim_end();
+        if line.is_empty() {
+            if previous_blank {
+                continue;
+            }
+            previous_blank = true;
+        } else {
+            previous_blank = false;
+        }
+        normalized.push_str(line);
+        normalized.push('\n');
+    }
+    normalized
+}
+
+fn expand_tabs(line: &str, tab_width: usize) -> String {
+    if !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut expanded = String::with_capacity(line.len() + tab_width);
+    let mut column = 0;
+    for c in line.chars() {
+        if c == '\t' {
+            let spaces = if tab_width == 0 { 0 } else { tab_width - column % tab_width };
+            expanded.extend(std::iter::repeat_n(' ', spaces));
+            column += spaces;
+        } else {
+            expanded.push(c);
+            column += 1;
+        }
+    }
+    expanded
+}
+
+// Cuts lines longer than `max_chars` characters, marking how much was dropped.
+pub fn truncate_long_lines(contents: &str, max_chars: usize) -> String {
+    let mut truncated = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let char_count = line.chars().count();
+        if char_count > max_chars {
+            truncated.extend(line.chars().take(max_chars));
+            truncated.push_str(&format!("… ({} chars truncated)", char_count - max_chars));
+        } else {
+            truncated.push_str(line);
+        }
+        truncated.push('\n');
+    }
+    truncated
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineLimitMode {
+    Head,
+    Tail,
+    Split,
+}
+
+impl LineLimitMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "head" => Some(LineLimitMode::Head),
+            "tail" => Some(LineLimitMode::Tail),
+            "split" => Some(LineLimitMode::Split),
+            _ => None,
+        }
+    }
+}
+
+// Keeps at most `max_lines` lines (first, last, or first+last halves), replacing the rest with a marker.
+pub fn limit_lines(contents: &str, max_lines: usize, mode: LineLimitMode) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    if lines.len() <= max_lines {
+        return contents.to_string();
+    }
+    let omitted = lines.len() - max_lines;
+    let marker = format!("... ({} lines omitted) ...", omitted);
+    let (head, tail): (&[&str], &[&str]) = match mode {
+        LineLimitMode::Head => (&lines[..max_lines], &[]),
+        LineLimitMode::Tail => (&[], &lines[lines.len() - max_lines..]),
+        LineLimitMode::Split => {
+            let head_len = max_lines.div_ceil(2);
+            let tail_len = max_lines - head_len;
+            (&lines[..head_len], &lines[lines.len() - tail_len..])
+        }
+    };
+    let mut limited = String::new();
+    for line in head {
+        limited.push_str(line);
+        limited.push('\n');
+    }
+    limited.push_str(&marker);
+    limited.push('\n');
+    for line in tail {
+        limited.push_str(line);
+        limited.push('\n');
+    }
+    limited
+}
+
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(\x07|\x1b\\)|\x1b[@-Z\\-_]").unwrap()
+});
+
+// Removes terminal colour and control sequences, which garble prompts.
+pub fn strip_ansi_codes(contents: &str) -> String {
+    ANSI_ESCAPE.replace_all(contents, "").into_owned()
+}
+
+pub fn is_log_file(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("log")
+}
+
+pub fn is_table_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|ext| ext.to_str()), Some("csv") | Some("tsv"))
+}
+
+// Keeps the header row plus the first `max_rows` data rows, noting the total row count.
+pub fn preview_table(contents: &str, max_rows: usize) -> String {
+    let mut lines = contents.lines();
+    let Some(header) = lines.next() else {
+        return String::new();
+    };
+    let rows: Vec<&str> = lines.filter(|line| !line.trim().is_empty()).collect();
+    if rows.len() <= max_rows {
+        return contents.to_string();
+    }
+    let mut preview = String::new();
+    preview.push_str(header);
+    preview.push('\n');
+    for row in &rows[..max_rows] {
+        preview.push_str(row);
+        preview.push('\n');
+    }
+    preview.push_str(&format!("... (showing {} of {} data rows) ...\n", max_rows, rows.len()));
+    preview
+}
+
+// Compacts the whitespace of JSON, XML and YAML data files (--minify-data); other files, and data files that
+// don't parse (JSON with comments), are returned unchanged.
+pub fn minify_data(contents: &str, path: &Path) -> String {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("json") if serde_json::from_str::<serde_json::Value>(contents).is_ok() => minify_json(contents),
+        Some("xml") if !contents.contains("xml:space=\"preserve\"") => minify_xml(contents),
+        Some("yaml") | Some("yml") => compact_yaml(contents),
+        _ => contents.to_string(),
+    }
+}
+
+// Drops the whitespace outside strings; key order and number formatting are kept as written.
+fn minify_json(contents: &str) -> String {
+    let mut minified = String::with_capacity(contents.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in contents.chars() {
+        if in_string {
+            minified.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if !c.is_whitespace() {
+            in_string = c == '"';
+            minified.push(c);
+        }
+    }
+    minified.push('\n');
+    minified
+}
+
+static XML_INDENTATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r">\s*\n\s*<").unwrap());
+
+// Removes the line breaks and indentation between tags; text content is left alone.
+fn minify_xml(contents: &str) -> String {
+    let mut minified = XML_INDENTATION.replace_all(contents.trim(), "><").into_owned();
+    minified.push('\n');
+    minified
+}
+
+// YAML's indentation is its structure, so only blank lines, full-line comments and trailing whitespace go,
+// except inside block scalars (`key: |`), whose lines are content.
+fn compact_yaml(contents: &str) -> String {
+    let mut compacted = String::with_capacity(contents.len());
+    // The indentation of the key that opened the current block scalar.
+    let mut block_indent: Option<usize> = None;
+    for line in contents.lines() {
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        if let Some(key_indent) = block_indent {
+            if trimmed.is_empty() || indent > key_indent {
+                compacted.push_str(line);
+                compacted.push('\n');
+                continue;
+            }
+            block_indent = None;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let value = trimmed.rsplit_once(": ").map_or(trimmed, |(_, value)| value).trim_start_matches("- ");
+        if value.starts_with(['|', '>']) && value.len() <= 3 {
+            block_indent = Some(indent);
+        }
+        compacted.push_str(line.trim_end());
+        compacted.push('\n');
+    }
+    compacted
+}
+
+// What the in-file `toprompt:` marker comments ask for.
+pub enum Markers {
+    // No markers: bundle the whole file.
+    All,
+    // `toprompt:ignore-file`: leave the file out.
+    IgnoreFile,
+    // `toprompt:begin` ... `toprompt:end`: only these (0-based, end-exclusive) line ranges are bundled.
+    Regions(Vec<(usize, usize)>),
+}
+
+// The marker named by a line that consists only of a `toprompt:<name>` comment, e.g. `// toprompt:begin`.
+fn marker_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let body = ["//", "#", "/*", "<!--", "--", ";", "%", "*"].iter().find_map(|opener| line.strip_prefix(opener))?;
+    let body = body.trim().trim_end_matches("*/").trim_end_matches("-->").trim_end();
+    body.strip_prefix("toprompt:").filter(|name| matches!(*name, "ignore-file" | "begin" | "end"))
+}
+
+pub fn find_markers(contents: &str) -> Markers {
+    if !contents.contains("toprompt:") {
+        return Markers::All;
+    }
+    let mut regions = Vec::new();
+    let mut start = None;
+    for (i, line) in contents.lines().enumerate() {
+        match marker_name(line) {
+            Some("ignore-file") => return Markers::IgnoreFile,
+            Some("begin") if start.is_none() => start = Some(i + 1),
+            Some("end") => {
+                if let Some(begin) = start.take() {
+                    regions.push((begin, i));
+                }
+            }
+            _ => {}
+        }
+    }
+    // A region left open runs to the end of the file.
+    if let Some(begin) = start {
+        regions.push((begin, contents.lines().count()));
+    }
+    if regions.is_empty() { Markers::All } else { Markers::Regions(regions) }
+}
+
+// Keeps only the given line ranges, marking the lines left out between them.
+pub fn keep_regions(contents: &str, regions: &[(usize, usize)]) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut kept = String::new();
+    let mut next = 0;
+    for &(start, end) in regions {
+        let (start, end) = (start.min(lines.len()), end.min(lines.len()));
+        // Marker lines themselves are not counted as omitted content.
+        let omitted = start.saturating_sub(next).saturating_sub(if next == 0 { 1 } else { 2 });
+        if omitted > 0 {
+            kept.push_str(&format!("... ({} lines omitted) ...\n", omitted));
+        }
+        for line in &lines[start..end] {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+        next = end;
+    }
+    let trailing = lines.len().saturating_sub(next + 1);
+    if trailing > 0 {
+        kept.push_str(&format!("... ({} lines omitted) ...\n", trailing));
+    }
+    kept
+}
+
+// Removes comments from source files whose comment syntax is known (C-style, `#` and `--` languages), skipping
+// over string literals; lines left empty by the removal are dropped. Other files are returned unchanged.
+pub fn strip_comments(contents: &str, path: &Path) -> String {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    let (line_comment, block_comments) = match ext.as_str() {
+        "rs" | "c" | "h" | "cpp" | "cc" | "cxx" | "hpp" | "java" | "kt" | "kts" | "scala" | "swift" | "cs" | "go" | "js" | "jsx"
+        | "mjs" | "cjs" | "ts" | "tsx" | "dart" | "php" | "zig" => ("//", true),
+        "py" | "pyi" | "rb" | "toml" | "yaml" | "yml" | "r" | "pl" => ("#", false),
+        "sql" | "lua" | "hs" => ("--", false),
+        _ => return contents.to_string(),
+    };
+    // Rust uses ' for lifetimes as well as char literals, so only a closed 'x' or '\x' counts as a literal there.
+    let single_quote_strings = ext != "rs";
+
+    let chars: Vec<char> = contents.chars().collect();
+    let mut stripped = String::with_capacity(contents.len());
+    let mut i = 0;
+    let starts_with = |i: usize, token: &str| token.chars().enumerate().all(|(k, c)| chars.get(i + k) == Some(&c));
+    while i < chars.len() {
+        let c = chars[i];
+        if starts_with(i, line_comment) && !(line_comment == "#" && i == 0 && starts_with(i, "#!")) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if block_comments && starts_with(i, "/*") {
+            i += 2;
+            while i < chars.len() && !starts_with(i, "*/") {
+                // Keep the line structure so line-based cleanup below still works.
+                if chars[i] == '\n' {
+                    stripped.push('\n');
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' || (c == '\'' && (single_quote_strings || is_char_literal(&chars, i))) {
+            stripped.push(c);
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' && i + 1 < chars.len() {
+                    stripped.push(chars[i]);
+                    i += 1;
+                }
+                stripped.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                stripped.push(c);
+                i += 1;
+            }
+        } else {
+            stripped.push(c);
+            i += 1;
+        }
+    }
+
+    // Drop the lines that only held comments, keeping blank lines that were already there.
+    let mut result = String::with_capacity(stripped.len());
+    for (original, line) in contents.lines().zip(stripped.lines()) {
+        let line = line.trim_end();
+        if line.is_empty() && !original.trim().is_empty() {
+            continue;
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+fn is_char_literal(chars: &[char], i: usize) -> bool {
+    match chars.get(i + 1) {
+        Some('\\') => true,
+        Some(_) => chars.get(i + 2) == Some(&'\''),
+        None => false,
+    }
+}
+
+// Drops every blank line.
+pub fn remove_blank_lines(contents: &str) -> String {
+    let mut kept = String::with_capacity(contents.len());
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    kept
+}
+
+// How a language writes imports: whether a column-0 line starts an import statement, and whether the statement
+// collected so far is complete (multi-line `use a::{...};`, `from x import (...)`, Go's `import (...)` blocks).
+struct ImportSyntax {
+    comment: &'static str,
+    starts: fn(&str) -> bool,
+    complete: fn(&str) -> bool,
+}
+
+fn balanced(text: &str, open: char, close: char) -> bool {
+    text.matches(open).count() <= text.matches(close).count()
+}
+
+fn import_syntax(ext: &str) -> Option<ImportSyntax> {
+    let single_line: fn(&str) -> bool = |_| true;
+    Some(match ext {
+        // `pub use` re-exports are part of the module's interface, so they stay.
+        "rs" => ImportSyntax {
+            comment: "//",
+            starts: |line| line.starts_with("use ") || line.starts_with("extern crate "),
+            complete: |statement| statement.trim_end().ends_with(';'),
+        },
+        "py" | "pyi" => ImportSyntax {
+            comment: "#",
+            starts: |line| line.starts_with("import ") || (line.starts_with("from ") && line.contains(" import")),
+            complete: |statement| balanced(statement, '(', ')') && !statement.trim_end().ends_with('\\'),
+        },
+        "js" | "jsx" | "ts" | "tsx" | "mjs" | "cjs" => ImportSyntax {
+            comment: "//",
+            starts: |line| {
+                (line.starts_with("import ") && !line.starts_with("import ("))
+                    || (["const ", "let ", "var "].iter().any(|keyword| line.starts_with(keyword)) && line.contains("require("))
+            },
+            complete: |statement| {
+                let end = statement.trim_end();
+                balanced(statement, '{', '}') && (end.ends_with(';') || end.ends_with('\'') || end.ends_with('"') || end.ends_with(')'))
+            },
+        },
+        "go" => ImportSyntax { comment: "//", starts: |line| line.starts_with("import "), complete: |statement| balanced(statement, '(', ')') },
+        "c" | "h" | "cpp" | "cc" | "cxx" | "hpp" | "hh" | "m" | "mm" => {
+            ImportSyntax { comment: "//", starts: |line| line.starts_with("#include") || line.starts_with("#import"), complete: single_line }
+        }
+        "java" | "kt" | "kts" | "scala" | "swift" | "dart" => ImportSyntax { comment: "//", starts: |line| line.starts_with("import "), complete: single_line },
+        "cs" => ImportSyntax {
+            comment: "//",
+            starts: |line| (line.starts_with("using ") || line.starts_with("global using ")) && line.trim_end().ends_with(';'),
+            complete: single_line,
+        },
+        "rb" => ImportSyntax { comment: "#", starts: |line| line.starts_with("require ") || line.starts_with("require_relative "), complete: single_line },
+        _ => return None,
+    })
+}
+
+// Replaces each run of top-level import/use/include statements (blank lines between them included) with a
+// `// (N imports elided)` comment. Files in languages without known import syntax are returned unchanged.
+pub fn strip_imports(contents: &str, path: &Path) -> String {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_lowercase();
+    let Some(syntax) = import_syntax(&ext) else {
+        return contents.to_string();
+    };
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut result = String::with_capacity(contents.len());
+    // Imports in the current run, and the blank lines seen since its last one.
+    let mut run = 0;
+    let mut pending_blank = 0;
+    let flush = |result: &mut String, run: &mut usize, pending_blank: &mut usize| {
+        if *run > 0 {
+            let noun = if *run == 1 { "import" } else { "imports" };
+            result.push_str(&format!("{} ({} {} elided)\n", syntax.comment, run, noun));
+        }
+        result.push_str(&"\n".repeat(*pending_blank));
+        *run = 0;
+        *pending_blank = 0;
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if (syntax.starts)(line) {
+            let mut statement = line.to_string();
+            while !(syntax.complete)(&statement) && i + 1 < lines.len() {
+                i += 1;
+                statement.push('\n');
+                statement.push_str(lines[i]);
+            }
+            // A Go `import (...)` block holds one import per line.
+            run += match statement.strip_prefix("import (") {
+                Some(block) => block.lines().filter(|line| !matches!(line.trim(), "" | ")")).count().max(1),
+                None => 1,
+            };
+            pending_blank = 0;
+        } else if run > 0 && line.trim().is_empty() {
+            pending_blank += 1;
+        } else {
+            flush(&mut result, &mut run, &mut pending_blank);
+            result.push_str(line);
+            result.push('\n');
+        }
+        i += 1;
+    }
+    flush(&mut result, &mut run, &mut pending_blank);
+    result
+}
+
+// A sed-style substitution from --replace or the config file's `replace` list.
+pub struct Rewrite {
+    // The rule as written, for reports and the cache fingerprint.
+    pub rule: String,
+    regex: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Rewrite {
+    // Parses `s/pattern/replacement/flags`: any delimiter may follow the `s` (and is escaped with a backslash),
+    // the replacement may use `&` and `\1`..`\9`, and the flags are `g` (every match in a line) and `i` (ignore case).
+    pub fn parse(rule: &str) -> Result<Rewrite, String> {
+        let mut chars = rule.chars();
+        let (Some('s'), Some(delimiter)) = (chars.next(), chars.next()) else {
+            return Err("expected s/pattern/replacement/flags".to_string());
+        };
+        if delimiter.is_alphanumeric() || delimiter == '\\' || delimiter.is_whitespace() {
+            return Err(format!("'{}' cannot be used as the delimiter", delimiter));
+        }
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            match c {
+                _ if escaped => {
+                    // An escaped delimiter is the plain character; other escapes keep their meaning.
+                    if c != delimiter {
+                        parts.last_mut().unwrap().push('\\');
+                    }
+                    parts.last_mut().unwrap().push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                _ if c == delimiter => parts.push(String::new()),
+                _ => parts.last_mut().unwrap().push(c),
+            }
+        }
+        let [pattern, replacement, flags] = <[String; 3]>::try_from(parts).map_err(|_| "expected s/pattern/replacement/flags".to_string())?;
+        if pattern.is_empty() {
+            return Err("the pattern is empty".to_string());
+        }
+        if let Some(flag) = flags.chars().find(|flag| !matches!(flag, 'g' | 'i')) {
+            return Err(format!("unknown flag '{}' (expected g or i)", flag));
+        }
+        let regex = regex::RegexBuilder::new(&pattern).case_insensitive(flags.contains('i')).build().map_err(|e| e.to_string())?;
+        Ok(Rewrite { rule: rule.to_string(), regex, replacement: sed_replacement(&replacement), global: flags.contains('g') })
+    }
+}
+
+// Translates a sed replacement (`&`, `\1`, `\&`) into the regex crate's syntax (`${0}`, `${1}`, `$$`).
+fn sed_replacement(replacement: &str) -> String {
+    let mut translated = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => translated.push_str("${0}"),
+            '$' => translated.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => translated.push_str(&format!("${{{}}}", digit)),
+                Some('n') => translated.push('\n'),
+                Some('t') => translated.push('\t'),
+                Some('$') => translated.push_str("$$"),
+                Some(other) => translated.push(other),
+                None => translated.push('\\'),
+            },
+            _ => translated.push(c),
+        }
+    }
+    translated
+}
+
+// Applies the rewrites in order, line by line like sed.
+pub fn apply_rewrites(contents: &str, rewrites: &[Rewrite]) -> String {
+    let mut rewritten = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let mut line = line.to_string();
+        for rewrite in rewrites {
+            let limit = if rewrite.global { 0 } else { 1 };
+            line = rewrite.regex.replacen(&line, limit, rewrite.replacement.as_str()).into_owned();
+        }
+        rewritten.push_str(&line);
+        rewritten.push('\n');
+    }
+    rewritten
+}
@@ -0,0 +1,40 @@
+// Webpage inputs: `http(s)://` arguments that are not git repositories are fetched with curl
+// and, when they are HTML, converted to markdown before being bundled as documents.
+
+use std::process::{Command, Stdio};
+
+pub struct WebDocument {
+    pub url: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub is_html: bool,
+}
+
+pub fn is_url(arg: &str) -> bool {
+    arg.starts_with("https://") || arg.starts_with("http://")
+}
+
+pub fn fetch(url: &str) -> Result<WebDocument, Box<dyn std::error::Error>> {
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", "--max-time", "30"])
+        .arg("--user-agent")
+        .arg(concat!("toprompt/", env!("CARGO_PKG_VERSION")))
+        .arg(url)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run curl to fetch '{}': {}. Is curl installed?", url, e))?;
+    if !output.status.success() {
+        return Err(format!("fetching '{}' failed: {}", url, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    let body = String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n");
+    if crate::html::looks_like_html(&body) {
+        Ok(WebDocument {
+            url: url.to_string(),
+            title: crate::html::extract_title(&body),
+            content: crate::html::to_markdown(&body),
+            is_html: true,
+        })
+    } else {
+        Ok(WebDocument { url: url.to_string(), title: None, content: body, is_html: false })
+    }
+}
@@ -0,0 +1,124 @@
+// Cargo workspaces: the member crates listed by a root Cargo.toml's [workspace] table, used to split output
+// per crate (--per-dir-output), to select a member's sources (--crate) and to describe the workspace.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::Value as Toml;
+
+use crate::Config;
+
+// What --crate includes of a member, where present: its manifest, build script and Cargo's target directories.
+const CRATE_LAYOUT: [&str; 6] = ["Cargo.toml", "build.rs", "src", "tests", "benches", "examples"];
+
+pub struct Member {
+    // The package name from the member's Cargo.toml, or its directory name.
+    pub name: String,
+    // Canonical path of the member's directory.
+    pub dir: PathBuf,
+    // Literal values only; `version.workspace = true` and the like are left out.
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+// The nearest directory at or above `start` whose Cargo.toml has a [workspace] table.
+pub fn find_root(start: &Path) -> Option<PathBuf> {
+    let start = fs::canonicalize(start).ok()?;
+    start.ancestors().find(|dir| is_workspace(dir)).map(Path::to_path_buf)
+}
+
+fn is_workspace(dir: &Path) -> bool {
+    fs::read_to_string(dir.join("Cargo.toml")).ok().and_then(|contents| contents.parse::<toml::Table>().ok()).is_some_and(|manifest| manifest.contains_key("workspace"))
+}
+
+// The paths --crate adds for a member: those entries of CRATE_LAYOUT that exist.
+pub fn crate_paths(member: &Member) -> Vec<PathBuf> {
+    CRATE_LAYOUT.iter().map(|name| member.dir.join(name)).filter(|path| path.exists()).collect()
+}
+
+// The workspace overview put before the files with --crate: every member with its version, directory and
+// description, marking the ones bundled.
+pub fn overview(root: &Path, selected: &[String], config: &Config) -> String {
+    let members = members(root);
+    let lines: Vec<String> = members
+        .iter()
+        .map(|member| {
+            let dir = crate::anonymize(config, &crate::display_relative(&member.dir));
+            let version = member.version.as_deref().map(|version| format!(" {}", version)).unwrap_or_default();
+            let description = member.description.as_deref().map(|description| format!(": {}", description)).unwrap_or_default();
+            let bundled = if selected.contains(&member.name) { " (bundled)" } else { "" };
+            if config.use_xml {
+                format!("{}{} in {}{}{}", member.name, version, dir, bundled, description)
+            } else {
+                format!("- {}{} in {}{}{}", member.name, version, dir, bundled, description)
+            }
+        })
+        .collect();
+    let root = crate::anonymize(config, &crate::display_relative(root));
+    if config.use_xml {
+        format!("<workspace root=\"{}\" members=\"{}\">\n{}\n</workspace>", root, members.len(), lines.join("\n"))
+    } else {
+        format!("# Cargo workspace at {} ({} members)\n{}", root, members.len(), lines.join("\n"))
+    }
+}
+
+// The members of the workspace rooted at `root`; empty if `root` has no Cargo.toml with a [workspace] table.
+// Member entries may end in a `*` glob (e.g. "crates/*"); `exclude` entries are honored.
+pub fn members(root: &Path) -> Vec<Member> {
+    let Some(manifest) = fs::read_to_string(root.join("Cargo.toml")).ok().and_then(|contents| contents.parse::<toml::Table>().ok()) else {
+        return Vec::new();
+    };
+    let Some(workspace) = manifest.get("workspace") else {
+        return Vec::new();
+    };
+    let list = |key: &str| -> Vec<String> {
+        workspace.get(key).and_then(Toml::as_array).into_iter().flatten().filter_map(Toml::as_str).map(str::to_string).collect()
+    };
+    let excluded: Vec<PathBuf> = list("exclude").iter().filter_map(|path| fs::canonicalize(root.join(path)).ok()).collect();
+
+    let mut dirs = Vec::new();
+    for pattern in list("members") {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => {
+                let parent = root.join(prefix.trim_end_matches('/'));
+                let mut found: Vec<PathBuf> = fs::read_dir(&parent)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.join("Cargo.toml").is_file())
+                    .collect();
+                found.sort();
+                dirs.extend(found);
+            }
+            None => dirs.push(root.join(pattern)),
+        }
+    }
+    let mut members = Vec::new();
+    for dir in dirs {
+        let Ok(dir) = fs::canonicalize(&dir) else { continue };
+        if excluded.contains(&dir) || members.iter().any(|member: &Member| member.dir == dir) {
+            continue;
+        }
+        members.push(member(dir));
+    }
+    // A root that is also a package is a member too.
+    if manifest.contains_key("package")
+        && let Ok(dir) = fs::canonicalize(root)
+        && !members.iter().any(|member| member.dir == dir)
+    {
+        members.push(member(dir));
+    }
+    members
+}
+
+fn member(dir: PathBuf) -> Member {
+    let manifest = fs::read_to_string(dir.join("Cargo.toml")).ok().and_then(|contents| contents.parse::<toml::Table>().ok());
+    let field = |key: &str| manifest.as_ref()?.get("package")?.get(key)?.as_str().map(str::to_string);
+    Member {
+        name: field("name").unwrap_or_else(|| dir.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()),
+        version: field("version"),
+        description: field("description"),
+        dir,
+    }
+}